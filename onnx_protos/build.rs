@@ -1,4 +1,11 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
 use protobuf_codegen::Codegen;
+use serde_json::Value;
+
+const OP_SCHEMA: &str = "./protobuf_gen/op_schema.json";
 
 fn main() {
     Codegen::new()
@@ -7,4 +14,286 @@ fn main() {
         .input("./protobuf_gen/onnx.proto3")
         .include("./protobuf_gen/")
         .run_from_script();
+
+    println!("cargo:rerun-if-changed={}", OP_SCHEMA);
+    let schema: Value = serde_json::from_str(&std::fs::read_to_string(OP_SCHEMA).unwrap()).unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out_dir).join("ops.rs"), generate_ops(&schema)).unwrap();
+}
+
+/// Generate one module per schema version of every operator and one
+/// module per opset re-exporting the versions in effect. Operators are
+/// left out of the opsets in which they are deprecated.
+fn generate_ops(schema: &Value) -> String {
+    let min_opset = schema["min_opset"].as_i64().unwrap();
+    let max_opset = schema["max_opset"].as_i64().unwrap();
+
+    // Operator name -> since versions and whether they are deprecated
+    let mut versions: BTreeMap<&str, Vec<(i64, bool)>> = BTreeMap::new();
+    let mut out = String::new();
+    out.push_str("mod defs {\n");
+    for op in schema["schemas"].as_array().unwrap() {
+        let name = op["name"].as_str().unwrap();
+        let since = op["since_version"].as_i64().unwrap();
+        let deprecated = op["deprecated"].as_bool().unwrap();
+        versions.entry(name).or_default().push((since, deprecated));
+        if deprecated {
+            continue;
+        }
+        writeln!(out, "pub mod {}_{} {{", snake_case(name), since).unwrap();
+        out.push_str("use super::super::*;\n");
+        generate_op(&mut out, op);
+        out.push_str("}\n");
+    }
+    out.push_str("}\n");
+
+    for opset in min_opset..=max_opset {
+        writeln!(
+            out,
+            "/// Operators of the default domain as of opset {}.",
+            opset
+        )
+        .unwrap();
+        writeln!(out, "pub mod v{} {{", opset).unwrap();
+        for (name, since) in versions.iter() {
+            let in_effect = since.iter().filter(|(v, _)| *v <= opset).max();
+            if let Some((since, false)) = in_effect {
+                writeln!(
+                    out,
+                    "pub use super::defs::{}_{}::*;",
+                    snake_case(name),
+                    since
+                )
+                .unwrap();
+            }
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+fn generate_op(out: &mut String, op: &Value) {
+    let name = op["name"].as_str().unwrap();
+    let since = op["since_version"].as_i64().unwrap();
+    let inputs = op["inputs"].as_array().unwrap();
+    let outputs = op["outputs"].as_array().unwrap();
+    let attributes = op["attributes"].as_array().unwrap();
+    let attrs_struct = format!("{}Attrs", name);
+
+    if !attributes.is_empty() {
+        let all_optional = attributes.iter().all(|a| !a["required"].as_bool().unwrap());
+        writeln!(out, "/// Attributes of `{}` version {}.", name, since).unwrap();
+        if all_optional {
+            out.push_str("#[derive(Clone, Debug, Default, PartialEq)]\n");
+        } else {
+            out.push_str("#[derive(Clone, Debug, PartialEq)]\n");
+        }
+        writeln!(out, "pub struct {} {{", attrs_struct).unwrap();
+        for attr in attributes {
+            let ty = attribute_type(attr["type"].as_str().unwrap()).0;
+            if !attr["default"].is_null() {
+                writeln!(out, "/// Defaults to `{}`.", attr["default"]).unwrap();
+            }
+            if attr["required"].as_bool().unwrap() {
+                writeln!(out, "pub {}: {},", field_name(attr), ty).unwrap();
+            } else {
+                writeln!(out, "pub {}: Option<{}>,", field_name(attr), ty).unwrap();
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    // Signature
+    let mut params = vec!["g: &mut GraphBuilder".to_string()];
+    for input in inputs {
+        let ty = match input["option"].as_str().unwrap() {
+            "single" => "&Handle",
+            "optional" => "Option<&Handle>",
+            _ => "&[&Handle]",
+        };
+        params.push(format!(
+            "{}: {}",
+            ident(input["name"].as_str().unwrap()),
+            ty
+        ));
+    }
+    if !attributes.is_empty() {
+        params.push(format!("attrs: {}", attrs_struct));
+    }
+    let variadic_output = outputs.iter().any(|o| o["option"] == "variadic");
+    let mut n_outputs = outputs.iter().filter(|o| o["option"] == "single").count();
+    // Operators such as `LSTM` produce nothing but optional outputs
+    let only_optional_outputs = n_outputs == 0 && !variadic_output;
+    if only_optional_outputs {
+        n_outputs = outputs.len();
+    }
+    let (generics, returns, n) = if variadic_output {
+        (
+            "<const N: usize>",
+            "[Handle; N]".to_string(),
+            "N".to_string(),
+        )
+    } else if n_outputs == 1 {
+        ("", "Handle".to_string(), "1".to_string())
+    } else {
+        (
+            "",
+            format!("[Handle; {}]", n_outputs),
+            n_outputs.to_string(),
+        )
+    };
+
+    writeln!(out, "/// Add a `{}` node, version {}.", name, since).unwrap();
+    let optional_outputs: Vec<_> = outputs
+        .iter()
+        .filter(|o| o["option"] == "optional")
+        .map(|o| format!("`{}`", o["name"].as_str().unwrap()))
+        .collect();
+    if !optional_outputs.is_empty() && !only_optional_outputs {
+        writeln!(
+            out,
+            "///\n/// The optional outputs {} are omitted.",
+            optional_outputs.join(", ")
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "pub fn {}{}({}) -> {} {{",
+        ident(name),
+        generics,
+        params.join(", "),
+        returns
+    )
+    .unwrap();
+
+    // Body
+    let mut fixed = vec![];
+    let mut variadic = None;
+    for input in inputs {
+        let param = ident(input["name"].as_str().unwrap());
+        match input["option"].as_str().unwrap() {
+            "single" => fixed.push(format!("Some({})", param)),
+            "optional" => fixed.push(param),
+            _ => variadic = Some(param),
+        }
+    }
+    let mutable = if variadic.is_some() { "mut " } else { "" };
+    writeln!(
+        out,
+        "let {}inputs: Vec<Option<&Handle>> = vec![{}];",
+        mutable,
+        fixed.join(", ")
+    )
+    .unwrap();
+    if let Some(param) = variadic {
+        writeln!(out, "inputs.extend({}.iter().copied().map(Some));", param).unwrap();
+    }
+
+    let mut required = vec![];
+    let mut optional = vec![];
+    for attr in attributes {
+        let key = attr["name"].as_str().unwrap();
+        let variant = attribute_type(attr["type"].as_str().unwrap()).1;
+        let field = field_name(attr);
+        if attr["required"].as_bool().unwrap() {
+            required.push(format!(
+                "(\"{}\", Attribute::{}(attrs.{}))",
+                key, variant, field
+            ));
+        } else {
+            optional.push(format!(
+                "if let Some(value) = attrs.{} {{ attributes.push((\"{}\", Attribute::{}(value))); }}",
+                field, key, variant
+            ));
+        }
+    }
+    let mutable = if optional.is_empty() { "" } else { "mut " };
+    writeln!(
+        out,
+        "let {}attributes: Vec<(&str, Attribute)> = vec![{}];",
+        mutable,
+        required.join(", ")
+    )
+    .unwrap();
+    for line in optional {
+        writeln!(out, "{}", line).unwrap();
+    }
+    writeln!(
+        out,
+        "let outputs: [Handle; {}] = add_node(g, \"{}\", inputs, attributes);",
+        n, name
+    )
+    .unwrap();
+    if !variadic_output && n_outputs == 1 {
+        out.push_str("let [output] = outputs;\noutput\n");
+    } else {
+        out.push_str("outputs\n");
+    }
+    out.push_str("}\n");
+}
+
+/// The Rust type of the field and the `Attribute` variant for an
+/// attribute type of the schema.
+fn attribute_type(ty: &str) -> (&'static str, &'static str) {
+    match ty {
+        "FLOAT" => ("f32", "F32"),
+        "INT" => ("i64", "I64"),
+        "STRING" => ("String", "String"),
+        "TENSOR" => ("crate::Tensor", "Tensor"),
+        "SPARSE_TENSOR" => ("crate::SparseTensor", "SparseTensor"),
+        "GRAPH" => ("crate::Graph", "Graph"),
+        "FLOATS" => ("Vec<f32>", "F32s"),
+        "INTS" => ("Vec<i64>", "I64s"),
+        "STRINGS" => ("Vec<String>", "Strings"),
+        "TENSORS" => ("Vec<crate::Tensor>", "Tensors"),
+        "SPARSE_TENSORS" => ("Vec<crate::SparseTensor>", "SparseTensors"),
+        "GRAPHS" => ("Vec<crate::Graph>", "Graphs"),
+        "TYPE_PROTO" => ("crate::ValueInfo", "TypeProto"),
+        _ => panic!("Unsupported attribute type `{}`", ty),
+    }
+}
+
+fn field_name(attr: &Value) -> String {
+    ident(attr["name"].as_str().unwrap())
+}
+
+/// `snake_case` identifier for an operator, input or attribute name.
+fn ident(name: &str) -> String {
+    let name = snake_case(name);
+    match name.as_str() {
+        "as" | "break" | "const" | "continue" | "else" | "enum" | "false" | "fn" | "for" | "if"
+        | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move" | "mut" | "pub" | "ref"
+        | "return" | "static" | "struct" | "trait" | "true" | "type" | "unsafe" | "use"
+        | "where" | "while" | "async" | "await" | "dyn" | "abstract" | "become" | "box" | "do"
+        | "final" | "macro" | "override" | "priv" | "typeof" | "unsized" | "virtual" | "yield"
+        | "try" => format!("r#{}", name),
+        // Reserved for the builder and the attributes
+        "g" | "attrs" | "inputs" | "attributes" | "outputs" | "output" => format!("{}_", name),
+        _ => name,
+    }
+}
+
+/// `MatMul` -> `mat_mul`, `transA` -> `trans_a`, `TopK` -> `top_k`.
+fn snake_case(name: &str) -> String {
+    // Abbreviations which are not split at their inner capitals
+    match name {
+        "IsNaN" => return "is_nan".to_string(),
+        "Col2Im" => return "col2im".to_string(),
+        "PRelu" => return "prelu".to_string(),
+        _ => {}
+    }
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
 }
@@ -6,3 +6,6 @@ set -exuo pipefail
 ONNX_VERSION="1.15.0"
 
 curl https://raw.githubusercontent.com/onnx/onnx/v${ONNX_VERSION}/onnx/onnx.proto3 -o onnx.proto3
+
+# Requires the `onnx` python package of the same version
+python3 op_schema.py
@@ -0,0 +1,75 @@
+"""Dump the schemas of the default domain operators into ``op_schema.json``.
+
+The resulting file is consumed by ``build.rs`` to generate the typed
+operator constructors in ``onnx_protos::ops``. All operators of the
+default domain are exported with the schema versions relevant to
+opsets ``MIN_OPSET`` to ``MAX_OPSET``, which match the range supported
+by ``onnx_protos::convert_opset``.
+"""
+
+import json
+from pathlib import Path
+
+import onnx.defs
+from onnx import helper
+
+MIN_OPSET = 10
+MAX_OPSET = 20
+
+
+def parameter(p):
+    return {"name": p.name, "option": p.option.name.lower()}
+
+
+def attribute(a):
+    default = None
+    if a.default_value.name:
+        default = helper.get_attribute_value(a.default_value)
+        if isinstance(default, bytes):
+            default = default.decode()
+    return {
+        "name": a.name,
+        "type": a.type.name,
+        "required": a.required,
+        "default": default,
+    }
+
+
+def main():
+    all_schemas = [
+        s
+        for s in onnx.defs.get_all_schemas_with_history()
+        if s.domain == "" and s.since_version <= MAX_OPSET
+    ]
+    schemas = []
+    for name in sorted({s.name for s in all_schemas}):
+        history = sorted(
+            (s for s in all_schemas if s.name == name),
+            key=lambda s: s.since_version,
+        )
+        oldest = max(
+            (i for i, s in enumerate(history) if s.since_version <= MIN_OPSET),
+            default=0,
+        )
+        for s in history[oldest:]:
+            schemas.append(
+                {
+                    "name": s.name,
+                    "since_version": s.since_version,
+                    "deprecated": s.deprecated,
+                    "inputs": [parameter(p) for p in s.inputs],
+                    "outputs": [parameter(p) for p in s.outputs],
+                    "attributes": [
+                        attribute(a)
+                        for a in sorted(s.attributes.values(), key=lambda a: a.name)
+                    ],
+                }
+            )
+
+    out = {"min_opset": MIN_OPSET, "max_opset": MAX_OPSET, "schemas": schemas}
+    path = Path(__file__).parent / "op_schema.json"
+    path.write_text(json.dumps(out, indent=1) + "\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,10 +1,12 @@
 use crate::protos::{attribute_proto::AttributeType, AttributeProto};
 
-use crate::{Error, Graph, Tensor};
+use crate::{Error, Graph, SparseTensor, Tensor, ValueInfo};
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum Attribute {
     Tensor(Tensor),
+    SparseTensor(SparseTensor),
 
     F32(f32),
     I64(i64),
@@ -13,54 +15,132 @@ pub enum Attribute {
     Strings(Vec<String>),
     F32s(Vec<f32>),
     I64s(Vec<i64>),
+    Tensors(Vec<Tensor>),
+    SparseTensors(Vec<SparseTensor>),
 
+    /// Subgraph such as the body of an `If`, `Loop` or `Scan` node.
     Graph(Graph),
+    Graphs(Vec<Graph>),
 
-    /// Reference to attribute in outer scope
-    RefAttrName(String),
+    TypeProto(ValueInfo),
+
+    /// Reference to the attribute of the given name and kind in the
+    /// outer scope. Only valid inside of a `Function`.
+    RefAttrName(String, AttributeKind),
+}
+
+/// The kind of value an [`Attribute`] holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeKind {
+    Tensor,
+    SparseTensor,
+
+    F32,
+    I64,
+    String,
+
+    Strings,
+    F32s,
+    I64s,
+    Tensors,
+    SparseTensors,
+
+    Graph,
+    Graphs,
+
+    TypeProto,
+}
+
+impl From<AttributeKind> for AttributeType {
+    fn from(kind: AttributeKind) -> Self {
+        match kind {
+            AttributeKind::Tensor => AttributeType::TENSOR,
+            AttributeKind::SparseTensor => AttributeType::SPARSE_TENSOR,
+            AttributeKind::F32 => AttributeType::FLOAT,
+            AttributeKind::I64 => AttributeType::INT,
+            AttributeKind::String => AttributeType::STRING,
+            AttributeKind::Strings => AttributeType::STRINGS,
+            AttributeKind::F32s => AttributeType::FLOATS,
+            AttributeKind::I64s => AttributeType::INTS,
+            AttributeKind::Tensors => AttributeType::TENSORS,
+            AttributeKind::SparseTensors => AttributeType::SPARSE_TENSORS,
+            AttributeKind::Graph => AttributeType::GRAPH,
+            AttributeKind::Graphs => AttributeType::GRAPHS,
+            AttributeKind::TypeProto => AttributeType::TYPE_PROTO,
+        }
+    }
+}
+
+impl TryFrom<AttributeType> for AttributeKind {
+    type Error = Error;
+
+    fn try_from(ty: AttributeType) -> Result<Self, Self::Error> {
+        Ok(match ty {
+            AttributeType::TENSOR => AttributeKind::Tensor,
+            AttributeType::SPARSE_TENSOR => AttributeKind::SparseTensor,
+            AttributeType::FLOAT => AttributeKind::F32,
+            AttributeType::INT => AttributeKind::I64,
+            AttributeType::STRING => AttributeKind::String,
+            AttributeType::STRINGS => AttributeKind::Strings,
+            AttributeType::FLOATS => AttributeKind::F32s,
+            AttributeType::INTS => AttributeKind::I64s,
+            AttributeType::TENSORS => AttributeKind::Tensors,
+            AttributeType::SPARSE_TENSORS => AttributeKind::SparseTensors,
+            AttributeType::GRAPH => AttributeKind::Graph,
+            AttributeType::GRAPHS => AttributeKind::Graphs,
+            AttributeType::TYPE_PROTO => AttributeKind::TypeProto,
+            ty => Err(Error::new_validation(format!(
+                "Unsupported attribute type `{:?}`",
+                ty
+            )))?,
+        })
+    }
 }
 
 impl Attribute {
     pub fn into_proto(self, name: String) -> AttributeProto {
+        self.to_proto(name)
+    }
+
+    pub fn to_proto(&self, name: String) -> AttributeProto {
         let default = AttributeProto {
             name,
             ..Default::default()
         };
         match self {
             Attribute::F32(f) => AttributeProto {
-                f,
+                f: *f,
                 type_: AttributeType::FLOAT.into(),
                 ..default
             },
             Attribute::I64(i) => AttributeProto {
-                i,
+                i: *i,
                 type_: AttributeType::INT.into(),
                 ..default
             },
             Attribute::String(s) => AttributeProto {
-                s: s.into_bytes(),
+                s: s.as_bytes().to_vec(),
                 type_: AttributeType::STRING.into(),
                 ..default
             },
             Attribute::F32s(floats) => AttributeProto {
-                floats,
+                floats: floats.clone(),
                 type_: AttributeType::FLOATS.into(),
                 ..default
             },
             Attribute::I64s(ints) => AttributeProto {
-                ints,
+                ints: ints.clone(),
                 type_: AttributeType::INTS.into(),
                 ..default
             },
             Attribute::Strings(s) => AttributeProto {
-                strings: s.into_iter().map(|s| s.into_bytes()).collect(),
+                strings: s.iter().map(|s| s.as_bytes().to_vec()).collect(),
                 type_: AttributeType::STRINGS.into(),
                 ..default
             },
-            Attribute::RefAttrName(ref_attr_name) => AttributeProto {
-                ref_attr_name,
-                // TODO: Unclear which type to use in this case
-                type_: AttributeType::UNDEFINED.into(),
+            Attribute::RefAttrName(ref_attr_name, kind) => AttributeProto {
+                ref_attr_name: ref_attr_name.clone(),
+                type_: AttributeType::from(*kind).into(),
                 ..default
             },
             Attribute::Graph(g) => AttributeProto {
@@ -73,6 +153,37 @@ impl Attribute {
                 type_: AttributeType::TENSOR.into(),
                 ..default
             },
+            Attribute::Graphs(graphs) => AttributeProto {
+                graphs: graphs.iter().map(|g| g.into()).collect(),
+                type_: AttributeType::GRAPHS.into(),
+                ..default
+            },
+            Attribute::Tensors(tensors) => AttributeProto {
+                tensors: tensors
+                    .iter()
+                    .map(|t| t.tensor_proto("".to_string()))
+                    .collect(),
+                type_: AttributeType::TENSORS.into(),
+                ..default
+            },
+            Attribute::SparseTensor(t) => AttributeProto {
+                sparse_tensor: Some(t.sparse_tensor_proto("".to_string())).into(),
+                type_: AttributeType::SPARSE_TENSOR.into(),
+                ..default
+            },
+            Attribute::SparseTensors(tensors) => AttributeProto {
+                sparse_tensors: tensors
+                    .iter()
+                    .map(|t| t.sparse_tensor_proto("".to_string()))
+                    .collect(),
+                type_: AttributeType::SPARSE_TENSORS.into(),
+                ..default
+            },
+            Attribute::TypeProto(info) => AttributeProto {
+                tp: Some(info.clone().into()).into(),
+                type_: AttributeType::TYPE_PROTO.into(),
+                ..default
+            },
         }
     }
 }
@@ -83,6 +194,10 @@ impl TryFrom<AttributeProto> for Attribute {
     fn try_from(ap: AttributeProto) -> Result<Self, Self::Error> {
         let ty = ap.type_.unwrap();
 
+        if !ap.ref_attr_name.is_empty() {
+            return Ok(Attribute::RefAttrName(ap.ref_attr_name, ty.try_into()?));
+        }
+
         Ok(match (ty, ap) {
             (AttributeType::FLOAT, AttributeProto { f, .. }) => Attribute::F32(f),
             (AttributeType::INT, AttributeProto { i, .. }) => Attribute::I64(i),
@@ -110,12 +225,44 @@ impl TryFrom<AttributeProto> for Attribute {
                     .ok_or_else(|| Error::new_validation("Tensor has not data".into()))?;
                 Attribute::Tensor(t.try_into()?)
             }
-            // (AttributeType::GRAPH, AttributeProto { g, .. }) => {
-            //     let g = g
-            //         .into_option()
-            //         .ok_or_else(|| Err(Error::new_validation("Graph has not data".into())));
-            //     Attribute::Graph(g.try_into()?)
-            // }
+            (AttributeType::TENSORS, AttributeProto { tensors, .. }) => Attribute::Tensors(
+                tensors
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<_, _>>()?,
+            ),
+            (AttributeType::SPARSE_TENSOR, AttributeProto { sparse_tensor, .. }) => {
+                let t = sparse_tensor
+                    .into_option()
+                    .ok_or_else(|| Error::new_validation("Sparse tensor has not data".into()))?;
+                Attribute::SparseTensor(t.try_into()?)
+            }
+            (AttributeType::SPARSE_TENSORS, AttributeProto { sparse_tensors, .. }) => {
+                Attribute::SparseTensors(
+                    sparse_tensors
+                        .into_iter()
+                        .map(TryInto::try_into)
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (AttributeType::GRAPH, AttributeProto { g, .. }) => {
+                let g = g
+                    .into_option()
+                    .ok_or_else(|| Error::new_validation("Graph has not data".into()))?;
+                Attribute::Graph(g.try_into()?)
+            }
+            (AttributeType::GRAPHS, AttributeProto { graphs, .. }) => Attribute::Graphs(
+                graphs
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<_, _>>()?,
+            ),
+            (AttributeType::TYPE_PROTO, AttributeProto { tp, .. }) => {
+                let tp = tp
+                    .into_option()
+                    .ok_or_else(|| Error::new_validation("Type proto has not data".into()))?;
+                Attribute::TypeProto(tp.try_into()?)
+            }
             (ty, ap) => Err(Error::new_validation(format!(
                 "Cannot handle type `{:#?}` for `{:#?}`",
                 ty, ap
@@ -0,0 +1,233 @@
+//! Programmatic construction of graphs.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::{Dim, Dtype, TensorInfo, ValueInfo};
+//!
+//! let mut b = GraphBuilder::new("graph").opset("", 19);
+//! let a = b.input("a", Dtype::F32, &[Dim::Dynamic("N".into())]);
+//! let [abs] = b.node("Abs", "", &[&a], []);
+//! let info = ValueInfo::Tensor(TensorInfo {
+//!     shape: Some(vec![Dim::Dynamic("N".into())]),
+//!     dtype: Dtype::F32,
+//! });
+//! b.output(&abs, info);
+//!
+//! let model = b.into_model().unwrap();
+//! assert_eq!(model.check(), Ok(()));
+//! ```
+use std::collections::HashSet;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    Attribute, Dim, Dtype, Error, Graph, Input, Model, Node, Operation, Output, Tensor, TensorInfo,
+    TensorValue, ValueInfo,
+};
+
+/// Handle to an edge of the graph under construction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    name: String,
+}
+
+impl Handle {
+    /// Placeholder for an omitted optional node input.
+    pub fn omitted() -> Self {
+        Self {
+            name: String::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphBuilder {
+    graph: Graph,
+    opsets: IndexMap<String, i64>,
+    /// Domains of all nodes added so far.
+    domains: IndexSet<String>,
+    /// All edge and node names in use.
+    names: HashSet<String>,
+}
+
+impl GraphBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            graph: Graph {
+                name: name.to_string(),
+                ..Default::default()
+            },
+            opsets: IndexMap::new(),
+            domains: IndexSet::new(),
+            names: HashSet::new(),
+        }
+    }
+
+    /// Declare the opset `version` to use for `domain`.
+    pub fn opset(mut self, domain: &str, version: i64) -> Self {
+        self.opsets.insert(domain.to_string(), version);
+        self
+    }
+
+    /// Add a tensor input to the graph.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is already in use.
+    pub fn input(&mut self, name: &str, dtype: Dtype, dims: &[Dim]) -> Handle {
+        let handle = self.claim(name);
+        self.graph.inputs.push(Input {
+            name: name.to_string(),
+            info: ValueInfo::Tensor(TensorInfo {
+                shape: Some(dims.to_vec()),
+                dtype,
+            }),
+            doc_string: None,
+            denotation: None,
+        });
+        handle
+    }
+
+    /// Add a constant tensor to the graph.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is already in use.
+    pub fn initializer(&mut self, name: &str, array: impl Into<TensorValue>) -> Handle {
+        let handle = self.claim(name);
+        self.graph.initializers.insert(
+            name.to_string(),
+            Tensor {
+                tensor: array.into(),
+                path: None,
+            },
+        );
+        handle
+    }
+
+    /// Add a node and return handles to its `N` outputs, which are
+    /// given unique names derived from `op`.
+    pub fn node<'a, const N: usize>(
+        &mut self,
+        op: &str,
+        domain: &str,
+        inputs: &[&Handle],
+        attrs: impl IntoIterator<Item = (&'a str, Attribute)>,
+    ) -> [Handle; N] {
+        let outputs = std::array::from_fn(|_| self.fresh(op));
+        let name = self.fresh(op).name;
+
+        self.domains.insert(domain.to_string());
+        self.graph.nodes.push(Node {
+            name,
+            inputs: inputs.iter().map(|h| h.name.clone()).collect(),
+            outputs: outputs.iter().map(|h: &Handle| h.name.clone()).collect(),
+            operation: Operation {
+                name: op.to_string(),
+                domain: domain.to_string(),
+            },
+            attributes: attrs.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            ..Default::default()
+        });
+        outputs
+    }
+
+    /// Expose the edge behind `handle` as a graph output.
+    pub fn output(&mut self, handle: &Handle, info: ValueInfo) {
+        self.graph.outputs.push(Output {
+            name: handle.name.clone(),
+            info,
+            doc_string: None,
+            denotation: None,
+        });
+    }
+
+    /// The graph without any opset information, e.g. for use as a
+    /// subgraph attribute.
+    pub fn build(self) -> Graph {
+        self.graph
+    }
+
+    /// Wrap the graph into a model importing the opsets of all used
+    /// domains.
+    pub fn into_model(self) -> Result<Model, Error> {
+        let opsets = self
+            .domains
+            .iter()
+            .map(|domain| {
+                let version = self.opsets.get(domain).ok_or_else(|| {
+                    Error::new_validation(format!("No opset version declared for `{}`.", domain))
+                })?;
+                Ok((domain.clone(), *version))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Model {
+            opsets,
+            graph: self.graph,
+            ..Default::default()
+        })
+    }
+
+    fn claim(&mut self, name: &str) -> Handle {
+        assert!(
+            self.names.insert(name.to_string()),
+            "Name `{}` is already in use",
+            name
+        );
+        Handle {
+            name: name.to_string(),
+        }
+    }
+
+    fn fresh(&mut self, prefix: &str) -> Handle {
+        let name = (0..)
+            .map(|i| format!("{}_{}", prefix, i))
+            .find(|name| !self.names.contains(name))
+            .unwrap();
+        self.claim(&name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unique_names() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("Add_0", Dtype::I64, &[]);
+        let one = b.initializer("one", ndarray::arr0(1i64).into_dyn());
+        let [y] = b.node("Add", "", &[&x, &one], []);
+        let [z] = b.node("Add", "", &[&y, &one], []);
+        let [values, indices] = b.node("TopK", "", &[&z, &one], [("axis", Attribute::I64(0))]);
+        b.output(
+            &indices,
+            ValueInfo::Tensor(TensorInfo {
+                shape: None,
+                dtype: Dtype::I64,
+            }),
+        );
+
+        assert_eq!(y.name(), "Add_1");
+        assert_eq!(z.name(), "Add_3");
+        assert_ne!(values, indices);
+
+        let model = b.into_model().unwrap();
+        assert_eq!(model.opsets, [("".to_string(), 19)].into());
+        assert_eq!(model.graph.nodes[2].outputs, ["TopK_0", "TopK_1"]);
+        assert_eq!(model.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_missing_opset() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[]);
+        let _: [Handle; 1] = b.node("Foo", "ai.foo", &[&x], []);
+
+        assert!(b.into_model().is_err());
+    }
+}
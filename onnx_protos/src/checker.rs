@@ -0,0 +1,403 @@
+//! Structural validation of models modelled on `onnx.checker`.
+use std::collections::HashSet;
+use std::fmt;
+
+use indexmap::IndexMap;
+
+use crate::{Attribute, Dim, Graph, Model, Node, ValueInfo};
+
+/// A problem found by [`Model::check`].
+///
+/// Nodes are identified by their name or, if they are unnamed, by
+/// their operator type and position in the graph. Nodes inside of
+/// subgraphs are prefixed by the path of the parent node and the
+/// attribute holding the subgraph, as are the edges of diagnostics
+/// which do not name a node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// `edge` is produced by `node` but was already defined before,
+    /// violating the static single assignment form.
+    DuplicateOutput { node: String, edge: String },
+    /// `node` consumes `edge`, but nothing produces it.
+    UndefinedInput { node: String, edge: String },
+    /// `node` consumes `edge` which is only produced by a later node.
+    NotTopologicallySorted { node: String, edge: String },
+    /// The graph output `edge` is never produced.
+    UnproducedOutput { edge: String },
+    /// The operator domain of `node` is not listed in the opsets.
+    UnknownDomain { node: String, domain: String },
+    /// The initializer `edge` contradicts the declared shape of the
+    /// graph input of the same name.
+    InitializerShapeMismatch {
+        edge: String,
+        declared: Vec<Dim>,
+        actual: Vec<usize>,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::DuplicateOutput { node, edge } => write!(
+                f,
+                "Node `{}` produces `{}` which is already defined",
+                node, edge
+            ),
+            Diagnostic::UndefinedInput { node, edge } => write!(
+                f,
+                "Node `{}` consumes `{}` which is never produced",
+                node, edge
+            ),
+            Diagnostic::NotTopologicallySorted { node, edge } => write!(
+                f,
+                "Node `{}` consumes `{}` before it is produced",
+                node, edge
+            ),
+            Diagnostic::UnproducedOutput { edge } => {
+                write!(f, "Graph output `{}` is never produced", edge)
+            }
+            Diagnostic::UnknownDomain { node, domain } => write!(
+                f,
+                "Domain `{}` of node `{}` is not imported by any opset",
+                domain, node
+            ),
+            Diagnostic::InitializerShapeMismatch {
+                edge,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "Initializer `{}` has shape {:?} but is declared as {:?}",
+                edge, actual, declared
+            ),
+        }
+    }
+}
+
+pub(crate) fn check_model(model: &Model) -> Result<(), Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+
+    let mut checker = Checker {
+        opsets: &model.opsets,
+        diagnostics: &mut diagnostics,
+    };
+    checker.check_graph(&model.graph, "", &HashSet::new());
+
+    for function in model.functions.iter() {
+        let mut checker = Checker {
+            opsets: &function.opsets,
+            diagnostics: &mut diagnostics,
+        };
+        let prefix = format!("{}/", function.operation.name);
+        let defined = function.inputs.iter().map(String::as_str).collect();
+        let defined = checker.check_nodes(&function.nodes, &prefix, &HashSet::new(), defined);
+        for output in function.outputs.iter() {
+            if !defined.contains(output.as_str()) {
+                checker.report(Diagnostic::UnproducedOutput {
+                    edge: format!("{}{}", prefix, output),
+                });
+            }
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+struct Checker<'a> {
+    opsets: &'a IndexMap<String, i64>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// `""` and `"ai.onnx"` both denote the default domain.
+    fn imports(&self, domain: &str) -> bool {
+        if is_default_domain(domain) {
+            self.opsets.keys().any(|k| is_default_domain(k))
+        } else {
+            self.opsets.contains_key(domain)
+        }
+    }
+
+    /// Check `graph` which may capture the edges in `outer_scope`.
+    fn check_graph<'g>(&mut self, graph: &'g Graph, prefix: &str, outer_scope: &HashSet<&'g str>) {
+        for input in graph.inputs.iter() {
+            let Some(initializer) = graph.initializers.get(&input.name) else {
+                continue;
+            };
+            let ValueInfo::Tensor(info) = &input.info else {
+                continue;
+            };
+            let Some(declared) = &info.shape else {
+                continue;
+            };
+            let actual = initializer.tensor.shape();
+            let compatible = declared.len() == actual.len()
+                && declared.iter().zip(actual.iter()).all(|(d, a)| match d {
+                    Dim::Fixed(d) => d == a,
+                    Dim::Dynamic(_) | Dim::Unknown => true,
+                });
+            if !compatible {
+                self.report(Diagnostic::InitializerShapeMismatch {
+                    edge: format!("{}{}", prefix, input.name),
+                    declared: declared.clone(),
+                    actual,
+                });
+            }
+        }
+
+        let defined = graph
+            .inputs
+            .iter()
+            .map(|input| input.name.as_str())
+            .chain(graph.initializers.keys().map(String::as_str))
+            .chain(graph.sparse_initializers.keys().map(String::as_str))
+            .collect();
+        let defined = self.check_nodes(&graph.nodes, prefix, outer_scope, defined);
+
+        for output in graph.outputs.iter() {
+            let name = output.name.as_str();
+            if !defined.contains(name) && !outer_scope.contains(name) {
+                self.report(Diagnostic::UnproducedOutput {
+                    edge: format!("{}{}", prefix, output.name),
+                });
+            }
+        }
+    }
+
+    /// Check `nodes` given the edges `defined` before the first
+    /// node. Returns all edges defined after the last node.
+    fn check_nodes<'g>(
+        &mut self,
+        nodes: &'g [Node],
+        prefix: &str,
+        outer_scope: &HashSet<&'g str>,
+        mut defined: HashSet<&'g str>,
+    ) -> HashSet<&'g str> {
+        let produced_later: HashSet<&str> = nodes
+            .iter()
+            .flat_map(|node| node.outputs.iter().map(String::as_str))
+            .collect();
+
+        for (idx, node) in nodes.iter().enumerate() {
+            let node_name = match node.name.as_str() {
+                "" => format!("{}{}#{}", prefix, node.operation.name, idx),
+                name => format!("{}{}", prefix, name),
+            };
+
+            if !self.imports(&node.operation.domain) {
+                self.report(Diagnostic::UnknownDomain {
+                    node: node_name.clone(),
+                    domain: node.operation.domain.clone(),
+                });
+            }
+
+            for input in node.inputs.iter() {
+                let name = input.as_str();
+                // Empty names denote omitted optional inputs
+                if name.is_empty() || defined.contains(name) || outer_scope.contains(name) {
+                    continue;
+                }
+                let edge = input.clone();
+                let node = node_name.clone();
+                if produced_later.contains(name) {
+                    self.report(Diagnostic::NotTopologicallySorted { node, edge });
+                } else {
+                    self.report(Diagnostic::UndefinedInput { node, edge });
+                }
+            }
+
+            // Subgraphs may capture everything defined so far
+            let scope: HashSet<&str> = outer_scope.union(&defined).copied().collect();
+            for (attr_name, attr) in node.attributes.iter() {
+                let graphs = match attr {
+                    Attribute::Graph(g) => std::slice::from_ref(g),
+                    Attribute::Graphs(gs) => gs.as_slice(),
+                    _ => continue,
+                };
+                for g in graphs {
+                    let prefix = format!("{}/{}/", node_name, attr_name);
+                    self.check_graph(g, &prefix, &scope);
+                }
+            }
+
+            for output in node.outputs.iter() {
+                let name = output.as_str();
+                if name.is_empty() {
+                    continue;
+                }
+                if !defined.insert(name) || outer_scope.contains(name) {
+                    self.report(Diagnostic::DuplicateOutput {
+                        node: node_name.clone(),
+                        edge: output.clone(),
+                    });
+                }
+            }
+        }
+        defined
+    }
+}
+
+fn is_default_domain(domain: &str) -> bool {
+    domain.is_empty() || domain == "ai.onnx"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dtype, Input, Operation, Output, Tensor, TensorInfo, TensorValue};
+
+    fn node(name: &str, inputs: &[&str], outputs: &[&str]) -> Node {
+        Node {
+            name: name.into(),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            operation: Operation {
+                name: "Add".into(),
+                domain: "".into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn info(shape: Vec<Dim>) -> ValueInfo {
+        ValueInfo::Tensor(TensorInfo {
+            shape: Some(shape),
+            dtype: Dtype::F32,
+        })
+    }
+
+    fn model(nodes: Vec<Node>, outputs: &[&str]) -> Model {
+        Model {
+            opsets: [("".to_string(), 19)].into(),
+            graph: Graph {
+                inputs: vec![Input {
+                    name: "a".into(),
+                    info: info(vec![Dim::Dynamic("N".into())]),
+                    doc_string: None,
+                    denotation: None,
+                }],
+                outputs: outputs
+                    .iter()
+                    .map(|name| Output {
+                        name: name.to_string(),
+                        info: info(vec![Dim::Dynamic("N".into())]),
+                        doc_string: None,
+                        denotation: None,
+                    })
+                    .collect(),
+                nodes,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid() {
+        let model = model(
+            vec![
+                node("n0", &["a", "a"], &["b"]),
+                node("", &["b", ""], &["c"]),
+            ],
+            &["c"],
+        );
+        assert_eq!(model.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_edges() {
+        let model = model(
+            vec![
+                node("n0", &["a", "c"], &["b"]),
+                node("", &["b", "x"], &["c"]),
+                node("n2", &["b"], &["a"]),
+            ],
+            &["c", "y"],
+        );
+        let expected = vec![
+            Diagnostic::NotTopologicallySorted {
+                node: "n0".into(),
+                edge: "c".into(),
+            },
+            Diagnostic::UndefinedInput {
+                node: "Add#1".into(),
+                edge: "x".into(),
+            },
+            Diagnostic::DuplicateOutput {
+                node: "n2".into(),
+                edge: "a".into(),
+            },
+            Diagnostic::UnproducedOutput { edge: "y".into() },
+        ];
+        assert_eq!(model.check(), Err(expected));
+    }
+
+    #[test]
+    fn test_domains_and_subgraphs() {
+        let mut inner = node("inner", &["a", "z"], &["a"]);
+        inner.operation.domain = "ai.foo".into();
+        let mut outer = node("if", &["a"], &["b"]);
+        outer.attributes.insert(
+            "then_branch".into(),
+            Attribute::Graph(Graph {
+                nodes: vec![inner],
+                outputs: vec![Output {
+                    name: "missing".into(),
+                    info: ValueInfo::Tensor(TensorInfo {
+                        shape: None,
+                        dtype: Dtype::F32,
+                    }),
+                    doc_string: None,
+                    denotation: None,
+                }],
+                ..Default::default()
+            }),
+        );
+        let model = model(vec![outer], &["b"]);
+
+        let expected = vec![
+            Diagnostic::UnknownDomain {
+                node: "if/then_branch/inner".into(),
+                domain: "ai.foo".into(),
+            },
+            Diagnostic::UndefinedInput {
+                node: "if/then_branch/inner".into(),
+                edge: "z".into(),
+            },
+            Diagnostic::DuplicateOutput {
+                node: "if/then_branch/inner".into(),
+                edge: "a".into(),
+            },
+            Diagnostic::UnproducedOutput {
+                edge: "if/then_branch/missing".into(),
+            },
+        ];
+        assert_eq!(model.check(), Err(expected));
+    }
+
+    #[test]
+    fn test_initializer_shape() {
+        let mut model = model(vec![node("n0", &["a", "a"], &["b"])], &["b"]);
+        model.graph.initializers.insert(
+            "a".into(),
+            Tensor {
+                tensor: TensorValue::F32(ndarray::array![[1.0]].into_dyn()),
+                path: None,
+            },
+        );
+        let expected = vec![Diagnostic::InitializerShapeMismatch {
+            edge: "a".into(),
+            declared: vec![Dim::Dynamic("N".into())],
+            actual: vec![1, 1],
+        }];
+        assert_eq!(model.check(), Err(expected));
+    }
+}
@@ -0,0 +1,451 @@
+//! Combining several models into a single one.
+//!
+//! [`merge`] chains two models by feeding outputs of the first into
+//! inputs of the second, e.g. to ship a preprocessing graph, the core
+//! model and a postprocessing graph as one artifact. Names of the
+//! second model which clash with the first one are prefixed
+//! automatically; [`add_prefix`] namespaces a model up front.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::compose::merge;
+//! use onnx_protos::{Dim, Dtype, TensorInfo, ValueInfo};
+//!
+//! let info = ValueInfo::Tensor(TensorInfo {
+//!     shape: Some(vec![Dim::Fixed(2)]),
+//!     dtype: Dtype::F32,
+//! });
+//! let model = |name: &str, op: &str| {
+//!     let mut b = GraphBuilder::new(name).opset("", 19);
+//!     let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+//!     let [y] = b.node(op, "", &[&x], []);
+//!     b.output(&y, info.clone());
+//!     b.into_model().unwrap()
+//! };
+//! let pre = model("pre", "Abs");
+//! let core = model("core", "Relu");
+//!
+//! let output = pre.graph.outputs[0].name.clone();
+//! let merged = merge(&pre, &core, &[(&output, "x")]).unwrap();
+//! assert_eq!(merged.graph.inputs.len(), 1);
+//! assert_eq!(merged.graph.nodes.len(), 2);
+//! assert_eq!(merged.graph.nodes[1].inputs, [output]);
+//! ```
+use std::collections::HashMap;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::graph_view::{subgraphs, subgraphs_mut};
+use crate::passes::is_default_domain;
+use crate::{Dim, Error, Graph, Model, ValueInfo};
+
+type Rename<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Prepend `prefix` to the names of all nodes and edges of `model`,
+/// including its inputs, outputs, initializers and subgraphs.
+///
+/// Training graphs and their bindings are renamed consistently.
+/// Function bodies have their own scope and are left untouched.
+pub fn add_prefix(model: &mut Model, prefix: &str) {
+    let prefixed = |name: &str| (!name.is_empty()).then(|| format!("{}{}", prefix, name));
+    rename_graph(&mut model.graph, &prefixed, &prefixed);
+    for info in model.training_info.iter_mut() {
+        if let Some(initialization) = &mut info.initialization {
+            rename_graph(initialization, &prefixed, &prefixed);
+        }
+        rename_graph(&mut info.algorithm, &prefixed, &prefixed);
+        for binding in [&mut info.initialization_binding, &mut info.update_binding] {
+            *binding = binding
+                .drain(..)
+                .map(|(k, v)| (format!("{}{}", prefix, k), format!("{}{}", prefix, v)))
+                .collect();
+        }
+    }
+}
+
+/// Merge `m1` and `m2` into a single model, connecting every output
+/// of `m1` to an input of `m2` as given by the `(output, input)` pairs
+/// of `io_map`.
+///
+/// The merged graph runs the nodes of `m1` followed by those of `m2`.
+/// Its inputs are the inputs of `m1` and the unconnected inputs of
+/// `m2`; its outputs are the unconnected outputs of `m1` and all
+/// outputs of `m2`. Nodes and edges of `m2` whose names are already
+/// used by `m1` are prefixed with the name of `m2`'s graph.
+///
+/// Opsets, functions and metadata of both models are combined. It is
+/// an error if they disagree, e.g. if the models import different
+/// versions of the same opset; use [`crate::convert_opset`] to align
+/// them first. Models with training info cannot be merged.
+pub fn merge(m1: &Model, m2: &Model, io_map: &[(&str, &str)]) -> Result<Model, Error> {
+    if !m1.training_info.is_empty() || !m2.training_info.is_empty() {
+        return Err(Error::new_validation(
+            "Cannot merge models with training info.".into(),
+        ));
+    }
+
+    let mut merged = m1.clone();
+    merged.ir_version = m1.ir_version.max(m2.ir_version);
+    for (domain, &version) in m2.opsets.iter() {
+        let existing = merged
+            .opsets
+            .iter()
+            .find(|(d, _)| *d == domain || (is_default_domain(d) && is_default_domain(domain)));
+        match existing {
+            Some((_, &v)) if v != version => {
+                return Err(Error::new_validation(format!(
+                    "Models import conflicting versions {} and {} of opset `{}`.",
+                    v, version, domain
+                )))
+            }
+            Some(_) => {}
+            None => {
+                merged.opsets.insert(domain.clone(), version);
+            }
+        }
+    }
+    for function in m2.functions.iter() {
+        match merged
+            .functions
+            .iter()
+            .find(|f| f.operation == function.operation)
+        {
+            Some(f) if f != function => {
+                return Err(Error::new_validation(format!(
+                    "Models define different functions `{}.{}`.",
+                    function.operation.domain, function.operation.name
+                )))
+            }
+            Some(_) => {}
+            None => merged.functions.push(function.clone()),
+        }
+    }
+    for (key, value) in m2.metadata.iter() {
+        match merged.metadata.get(key) {
+            Some(v) if v != value => {
+                return Err(Error::new_validation(format!(
+                    "Models have conflicting values `{}` and `{}` for metadata `{}`.",
+                    v, value, key
+                )))
+            }
+            Some(_) => {}
+            None => {
+                merged.metadata.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    // Input of `m2` -> output of `m1` feeding it
+    let mut connections: HashMap<&str, &str> = HashMap::new();
+    for &(output, input) in io_map {
+        let out = m1
+            .graph
+            .outputs
+            .iter()
+            .find(|o| o.name == output)
+            .ok_or_else(|| {
+                Error::new_validation(format!("`{}` is not an output of the first model.", output))
+            })?;
+        let inp = m2
+            .graph
+            .inputs
+            .iter()
+            .find(|i| i.name == input)
+            .ok_or_else(|| {
+                Error::new_validation(format!("`{}` is not an input of the second model.", input))
+            })?;
+        if !compatible(&out.info, &inp.info) {
+            return Err(Error::new_validation(format!(
+                "Cannot connect `{}` to `{}`: {:?} does not match {:?}.",
+                output, input, out.info, inp.info
+            )));
+        }
+        if connections.insert(input, output).is_some() {
+            return Err(Error::new_validation(format!(
+                "Input `{}` is connected more than once.",
+                input
+            )));
+        }
+    }
+
+    let (mut edges1, mut nodes1) = (IndexSet::new(), IndexSet::new());
+    collect_names(&m1.graph, &mut edges1, &mut nodes1);
+    let (mut edges2, mut nodes2) = (IndexSet::new(), IndexSet::new());
+    collect_names(&m2.graph, &mut edges2, &mut nodes2);
+    let prefix = match m2.graph.name.as_str() {
+        "" => "merged",
+        name => name,
+    };
+    let edge_names = renames(&edges1, &edges2, prefix, |edge| {
+        connections.get(edge).map(|output| output.to_string())
+    });
+    let node_names = renames(&nodes1, &nodes2, prefix, |_| None);
+
+    let mut g2 = m2.graph.clone();
+    g2.inputs
+        .retain(|i| !connections.contains_key(i.name.as_str()));
+    for input in connections.keys() {
+        // Default values of connected inputs are superseded by `m1`
+        g2.initializers.shift_remove(*input);
+    }
+    rename_graph(&mut g2, &|edge| edge_names.get(edge).cloned(), &|node| {
+        node_names.get(node).cloned()
+    });
+
+    let graph = &mut merged.graph;
+    if !g2.name.is_empty() {
+        graph.name = match graph.name.as_str() {
+            "" => g2.name.clone(),
+            name => format!("{}_{}", name, g2.name),
+        };
+    }
+    let outputs = std::mem::take(&mut graph.outputs);
+    for output in outputs {
+        if connections.values().any(|o| *o == output.name) {
+            graph.edge_infos.insert(output.name, output.info);
+        } else {
+            graph.outputs.push(output);
+        }
+    }
+    graph.inputs.extend(g2.inputs);
+    graph.nodes.extend(g2.nodes);
+    graph.initializers.extend(g2.initializers);
+    graph.sparse_initializers.extend(g2.sparse_initializers);
+    graph.edge_infos.extend(g2.edge_infos);
+    graph.outputs.extend(g2.outputs);
+    Ok(merged)
+}
+
+/// New names for the names of `second` which clash with `first`,
+/// unless `fixed` already decides on one.
+fn renames(
+    first: &IndexSet<&str>,
+    second: &IndexSet<&str>,
+    prefix: &str,
+    fixed: impl Fn(&str) -> Option<String>,
+) -> HashMap<String, String> {
+    let mut renamed = HashMap::new();
+    let mut taken: IndexSet<String> = first.iter().chain(second).map(|s| s.to_string()).collect();
+    for &name in second {
+        let new = match fixed(name) {
+            Some(new) => new,
+            None if first.contains(name) => {
+                let mut new = format!("{}/{}", prefix, name);
+                let mut i = 1;
+                while taken.contains(&new) {
+                    new = format!("{}/{}_{}", prefix, name, i);
+                    i += 1;
+                }
+                taken.insert(new.clone());
+                new
+            }
+            None => continue,
+        };
+        if new != name {
+            renamed.insert(name.to_string(), new);
+        }
+    }
+    renamed
+}
+
+/// Collect the names of all edges and nodes in `graph` and its
+/// subgraphs.
+fn collect_names<'g>(
+    graph: &'g Graph,
+    edges: &mut IndexSet<&'g str>,
+    nodes: &mut IndexSet<&'g str>,
+) {
+    edges.extend(graph.inputs.iter().map(|i| i.name.as_str()));
+    edges.extend(graph.initializers.keys().map(String::as_str));
+    edges.extend(graph.sparse_initializers.keys().map(String::as_str));
+    for node in graph.nodes.iter() {
+        if !node.name.is_empty() {
+            nodes.insert(&node.name);
+        }
+        edges.extend(
+            node.inputs
+                .iter()
+                .chain(node.outputs.iter())
+                .filter(|e| !e.is_empty())
+                .map(String::as_str),
+        );
+        for subgraph in subgraphs(node) {
+            collect_names(subgraph, edges, nodes);
+        }
+    }
+    edges.extend(graph.edge_infos.keys().map(String::as_str));
+    edges.extend(graph.outputs.iter().map(|o| o.name.as_str()));
+}
+
+/// Rename all edges and nodes of `graph` and its subgraphs for which
+/// `edges` or `nodes` return a new name.
+fn rename_graph(graph: &mut Graph, edges: Rename, nodes: Rename) {
+    let rename = |name: &mut String| {
+        if let Some(new) = edges(name) {
+            *name = new;
+        }
+    };
+    graph.inputs.iter_mut().for_each(|i| rename(&mut i.name));
+    graph.outputs.iter_mut().for_each(|o| rename(&mut o.name));
+    rename_keys(&mut graph.initializers, edges);
+    rename_keys(&mut graph.sparse_initializers, edges);
+    rename_keys(&mut graph.edge_infos, edges);
+    for node in graph.nodes.iter_mut() {
+        if let Some(new) = nodes(&node.name) {
+            node.name = new;
+        }
+        node.inputs.iter_mut().for_each(rename);
+        node.outputs.iter_mut().for_each(rename);
+        for subgraph in subgraphs_mut(node) {
+            rename_graph(subgraph, edges, nodes);
+        }
+    }
+}
+
+fn rename_keys<V>(map: &mut IndexMap<String, V>, rename: Rename) {
+    *map = map
+        .drain(..)
+        .map(|(k, v)| (rename(&k).unwrap_or(k), v))
+        .collect();
+}
+
+/// Whether a value described by `a` may be fed where `b` is expected.
+/// Symbolic dimensions of different models are assumed to agree.
+fn compatible(a: &ValueInfo, b: &ValueInfo) -> bool {
+    match (a, b) {
+        (ValueInfo::Tensor(a), ValueInfo::Tensor(b))
+        | (ValueInfo::SparseTensor(a), ValueInfo::SparseTensor(b)) => {
+            a.dtype == b.dtype
+                && match (&a.shape, &b.shape) {
+                    (Some(a), Some(b)) => {
+                        a.len() == b.len()
+                            && a.iter().zip(b).all(|dims| match dims {
+                                (Dim::Fixed(a), Dim::Fixed(b)) => a == b,
+                                _ => true,
+                            })
+                    }
+                    _ => true,
+                }
+        }
+        (ValueInfo::Sequence(a), ValueInfo::Sequence(b))
+        | (ValueInfo::Optional(a), ValueInfo::Optional(b)) => compatible(a, b),
+        (ValueInfo::Map(ka, a), ValueInfo::Map(kb, b)) => ka == kb && compatible(a, b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::GraphBuilder;
+    use crate::{Attribute, Dtype, TensorInfo};
+
+    fn info(dtype: Dtype) -> ValueInfo {
+        ValueInfo::Tensor(TensorInfo {
+            shape: Some(vec![Dim::Dynamic("N".into())]),
+            dtype,
+        })
+    }
+
+    /// `x -> op -> y`, with a `z` output passing `x` through `Identity`.
+    fn model(name: &str, op: &str, dtype: Dtype) -> Model {
+        let mut b = GraphBuilder::new(name).opset("", 19);
+        let x = b.input("x", dtype, &[Dim::Dynamic("N".into())]);
+        let [y] = b.node(op, "", &[&x], []);
+        let [z] = b.node("Identity", "", &[&x], []);
+        b.output(&y, info(dtype));
+        b.output(&z, info(dtype));
+        b.into_model().unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut m1 = model("pre", "Abs", Dtype::F32);
+        m1.metadata.insert("author".into(), "a".into());
+        let mut m2 = model("core", "Relu", Dtype::F32);
+        m2.metadata.insert("author".into(), "a".into());
+        m2.metadata.insert("license".into(), "MIT".into());
+        m2.opsets.insert("custom".into(), 1);
+
+        let y1 = m1.graph.outputs[0].name.clone();
+        let merged = merge(&m1, &m2, &[(&y1, "x")]).unwrap();
+        let graph = &merged.graph;
+        assert_eq!(graph.name, "pre_core");
+        assert_eq!(graph.inputs.len(), 1);
+        assert_eq!(graph.inputs[0].name, "x");
+        assert_eq!(graph.nodes.len(), 4);
+        assert!(graph.edge_infos.contains_key(&y1));
+        // Both Relu and Identity of `m2` now consume the output of Abs
+        assert_eq!(graph.nodes[2].inputs, graph.nodes[3].inputs);
+        assert_eq!(graph.nodes[3].inputs, [y1]);
+
+        // Builder names clash and are prefixed
+        let outputs: Vec<_> = graph.outputs.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(outputs, ["Identity_0", "Relu_0", "core/Identity_0"]);
+        assert_eq!(graph.nodes[3].name, "core/Identity_1");
+        assert_eq!(merged.opsets.len(), 2);
+        assert_eq!(merged.metadata.len(), 2);
+
+        // Unconnected inputs of the same name are prefixed as well
+        let merged = merge(&m1, &m2, &[]).unwrap();
+        let inputs: Vec<_> = merged
+            .graph
+            .inputs
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(inputs, ["x", "core/x"]);
+        assert_eq!(merged.graph.outputs.len(), 4);
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let m1 = model("a", "Abs", Dtype::F32);
+        let y1 = m1.graph.outputs[0].name.clone();
+
+        let m2 = model("b", "Relu", Dtype::I64);
+        assert!(merge(&m1, &m2, &[(&y1, "x")]).is_err());
+        let m2 = model("b", "Relu", Dtype::F32);
+        assert!(merge(&m1, &m2, &[("missing", "x")]).is_err());
+        assert!(merge(&m1, &m2, &[(&y1, "x"), (&y1, "x")]).is_err());
+
+        let mut m3 = m2.clone();
+        m3.opsets.insert("".into(), 13);
+        assert!(merge(&m1, &m3, &[]).is_err());
+        let mut m3 = m2.clone();
+        m3.opsets.shift_remove("");
+        m3.opsets.insert("ai.onnx".into(), 19);
+        assert_eq!(merge(&m1, &m3, &[]).unwrap().opsets.len(), 1);
+
+        let (mut m1, mut m3) = (m1, m2);
+        m1.metadata.insert("version".into(), "1".into());
+        m3.metadata.insert("version".into(), "2".into());
+        assert!(merge(&m1, &m3, &[]).is_err());
+    }
+
+    #[test]
+    fn test_add_prefix() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let c = b.input("c", Dtype::Bool, &[]);
+        let x = b.initializer("x", ndarray::arr1(&[1f32, 2.]).into_dyn());
+        let mut branch = GraphBuilder::new("branch");
+        let [n] = branch.node("Neg", "", &[&x], []);
+        branch.output(&n, info(Dtype::F32));
+        let then_branch = Attribute::Graph(branch.build());
+        let [y] = b.node("If", "", &[&c], [("then_branch", then_branch)]);
+        b.output(&y, info(Dtype::F32));
+        let mut model = b.into_model().unwrap();
+
+        add_prefix(&mut model, "p/");
+        let graph = &model.graph;
+        assert_eq!(graph.inputs[0].name, "p/c");
+        assert!(graph.initializers.contains_key("p/x"));
+        assert!(graph.nodes[0].name.starts_with("p/If_"));
+        assert_eq!(graph.outputs[0].name, format!("p/{}", y.name()));
+        let Some(Attribute::Graph(branch)) = graph.nodes[0].attributes.get("then_branch") else {
+            panic!("missing branch");
+        };
+        assert_eq!(branch.nodes[0].inputs, ["p/x"]);
+        assert_eq!(branch.outputs[0].name, format!("p/{}", n.name()));
+    }
+}
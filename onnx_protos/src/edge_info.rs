@@ -3,20 +3,24 @@ use crate::protos::{
     tensor_shape_proto::{dimension, Dimension},
     type_proto, TensorShapeProto, TypeProto, ValueInfoProto,
 };
-use protobuf::Enum;
+use protobuf::{Enum, MessageField};
 
 use crate::Error;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ValueInfo {
     Tensor(TensorInfo),
-    // Only allow sequence of Tensors. Everything else is madness
-    Sequence(TensorInfo),
+    SparseTensor(TensorInfo),
+    Sequence(Box<ValueInfo>),
+    /// Map from keys of an integral or string type to values.
+    Map(Dtype, Box<ValueInfo>),
+    Optional(Box<ValueInfo>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct TensorInfo {
-    pub shape: Vec<Dim>,
+    /// `None` if not even the rank is known.
+    pub shape: Option<Vec<Dim>>,
     pub dtype: Dtype,
 }
 
@@ -27,7 +31,7 @@ pub enum Dim {
     Fixed(usize),
 }
 
-#[derive(Clone, Debug, PartialEq, Copy)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Copy)]
 pub enum Dtype {
     U8,
     U16,
@@ -41,8 +45,24 @@ pub enum Dtype {
     F64,
     String,
     Bool,
+    F16,
+    BF16,
+    /// Complex number of two `f32`s.
+    C64,
+    /// Complex number of two `f64`s.
+    C128,
+    F8E4M3FN,
+    F8E4M3FNUZ,
+    F8E5M2,
+    F8E5M2FNUZ,
+    U4,
+    I4,
 }
 
+/// `DataType`s which are newer than the vendored `onnx.proto3`.
+pub(crate) const UINT4: i32 = 21;
+pub(crate) const INT4: i32 = 22;
+
 impl From<Dim> for Dimension {
     fn from(dim: Dim) -> Self {
         Dimension {
@@ -73,11 +93,8 @@ impl TryFrom<ValueInfoProto> for ValueInfo {
 
     fn try_from(proto: ValueInfoProto) -> Result<Self, Self::Error> {
         let typro = proto.type_.into_option();
-        if let Some(TypeProto { value: Some(v), .. }) = typro {
-            Ok(match v {
-                type_proto::Value::TensorType(t) => ValueInfo::Tensor(t.try_into()?),
-                _ => todo!(),
-            })
+        if let Some(typro @ TypeProto { value: Some(_), .. }) = typro {
+            typro.try_into()
         } else {
             Err(Error::new_validation(format!(
                 "Invalid TypeProto: `{:?}`",
@@ -87,40 +104,119 @@ impl TryFrom<ValueInfoProto> for ValueInfo {
     }
 }
 
-impl From<TensorInfo> for type_proto::Value {
-    fn from(t: TensorInfo) -> Self {
-        let data_type: DataType = t.dtype.into();
-        type_proto::Value::TensorType(crate::protos::type_proto::Tensor {
-            elem_type: data_type as _,
-            shape: Some(TensorShapeProto {
-                dim: t.shape.into_iter().map(|dim| dim.into()).collect(),
-                ..Default::default()
-            })
-            .into(),
-            ..Default::default()
+impl TryFrom<TypeProto> for ValueInfo {
+    type Error = Error;
+
+    fn try_from(proto: TypeProto) -> Result<Self, Self::Error> {
+        use type_proto::Value;
+
+        fn elem_type(elem_type: MessageField<TypeProto>) -> Result<Box<ValueInfo>, Error> {
+            let elem_type = elem_type
+                .into_option()
+                .ok_or_else(|| Error::new_validation("Missing element type.".into()))?;
+            Ok(Box::new(elem_type.try_into()?))
+        }
+
+        Ok(match proto.value {
+            Some(Value::TensorType(t)) => {
+                ValueInfo::Tensor(TensorInfo::from_parts(t.elem_type, t.shape)?)
+            }
+            Some(Value::SparseTensorType(t)) => {
+                ValueInfo::SparseTensor(TensorInfo::from_parts(t.elem_type, t.shape)?)
+            }
+            Some(Value::SequenceType(s)) => ValueInfo::Sequence(elem_type(s.elem_type)?),
+            Some(Value::MapType(m)) => {
+                ValueInfo::Map(m.key_type.try_into()?, elem_type(m.value_type)?)
+            }
+            Some(Value::OptionalType(o)) => ValueInfo::Optional(elem_type(o.elem_type)?),
+            None => Err(Error::new_validation("TypeProto without value.".into()))?,
         })
     }
 }
 
-impl TryFrom<type_proto::Tensor> for TensorInfo {
-    type Error = Error;
+impl From<ValueInfo> for TypeProto {
+    fn from(info: ValueInfo) -> Self {
+        use type_proto::Value;
 
-    fn try_from(
-        type_proto::Tensor {
-            elem_type, shape, ..
-        }: type_proto::Tensor,
-    ) -> Result<Self, Self::Error> {
+        let elem_type = |info: Box<ValueInfo>| MessageField::some(TypeProto::from(*info));
+
+        TypeProto {
+            value: Some(match info {
+                ValueInfo::Tensor(t) => {
+                    let (elem_type, shape) = t.into_parts();
+                    Value::TensorType(type_proto::Tensor {
+                        elem_type,
+                        shape,
+                        ..Default::default()
+                    })
+                }
+                ValueInfo::SparseTensor(t) => {
+                    let (elem_type, shape) = t.into_parts();
+                    Value::SparseTensorType(type_proto::SparseTensor {
+                        elem_type,
+                        shape,
+                        ..Default::default()
+                    })
+                }
+                ValueInfo::Sequence(info) => Value::SequenceType(type_proto::Sequence {
+                    elem_type: elem_type(info),
+                    ..Default::default()
+                }),
+                ValueInfo::Map(key, value) => Value::MapType(type_proto::Map {
+                    key_type: key.into(),
+                    value_type: elem_type(value),
+                    ..Default::default()
+                }),
+                ValueInfo::Optional(info) => Value::OptionalType(type_proto::Optional {
+                    elem_type: elem_type(info),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl TensorInfo {
+    fn from_parts(elem_type: i32, shape: MessageField<TensorShapeProto>) -> Result<Self, Error> {
         let shape = shape
             .into_option()
-            .ok_or_else(|| Error::new_validation("Incomplete dimenson data.".into()))?
-            .dim
-            .into_iter()
-            .map(|el| el.try_into())
-            .collect::<Result<_, Self::Error>>()?;
+            .map(|shape| {
+                shape
+                    .dim
+                    .into_iter()
+                    .map(|el| el.try_into())
+                    .collect::<Result<_, Error>>()
+            })
+            .transpose()?;
 
         let dtype = elem_type.try_into()?;
         Ok(TensorInfo { shape, dtype })
     }
+
+    fn into_parts(self) -> (i32, MessageField<TensorShapeProto>) {
+        let shape = self.shape.map(|shape| TensorShapeProto {
+            dim: shape.into_iter().map(|dim| dim.into()).collect(),
+            ..Default::default()
+        });
+        (self.dtype.into(), shape.into())
+    }
+}
+
+impl Dtype {
+    /// Size of a single element in bits, or `None` for strings.
+    pub fn bits(self) -> Option<usize> {
+        use Dtype::*;
+        match self {
+            U4 | I4 => Some(4),
+            U8 | I8 | Bool | F8E4M3FN | F8E4M3FNUZ | F8E5M2 | F8E5M2FNUZ => Some(8),
+            U16 | I16 | F16 | BF16 => Some(16),
+            U32 | I32 | F32 => Some(32),
+            U64 | I64 | F64 | C64 => Some(64),
+            C128 => Some(128),
+            String => None,
+        }
+    }
 }
 
 impl TryFrom<i32> for Dtype {
@@ -128,6 +224,11 @@ impl TryFrom<i32> for Dtype {
 
     fn try_from(value: i32) -> Result<Self, Self::Error> {
         use DataType::*;
+        match value {
+            UINT4 => return Ok(Self::U4),
+            INT4 => return Ok(Self::I4),
+            _ => {}
+        }
         let value = DataType::from_i32(value);
         Ok(match value {
             Some(UINT8) => Self::U8,
@@ -142,6 +243,14 @@ impl TryFrom<i32> for Dtype {
             Some(DOUBLE) => Self::F64,
             Some(BOOL) => Self::Bool,
             Some(STRING) => Self::String,
+            Some(FLOAT16) => Self::F16,
+            Some(BFLOAT16) => Self::BF16,
+            Some(COMPLEX64) => Self::C64,
+            Some(COMPLEX128) => Self::C128,
+            Some(FLOAT8E4M3FN) => Self::F8E4M3FN,
+            Some(FLOAT8E4M3FNUZ) => Self::F8E4M3FNUZ,
+            Some(FLOAT8E5M2) => Self::F8E5M2,
+            Some(FLOAT8E5M2FNUZ) => Self::F8E5M2FNUZ,
             _ => {
                 return Err(Error::new_validation(format!(
                     "Unsupported data type `{:?}`",
@@ -152,9 +261,10 @@ impl TryFrom<i32> for Dtype {
     }
 }
 
-impl From<Dtype> for DataType {
+/// The `DataType` value of `dtype`.
+impl From<Dtype> for i32 {
     fn from(dtype: Dtype) -> Self {
-        match dtype {
+        let data_type = match dtype {
             Dtype::U8 => DataType::UINT8,
             Dtype::U16 => DataType::UINT16,
             Dtype::U32 => DataType::UINT32,
@@ -167,7 +277,18 @@ impl From<Dtype> for DataType {
             Dtype::F64 => DataType::DOUBLE,
             Dtype::String => DataType::STRING,
             Dtype::Bool => DataType::BOOL,
-        }
+            Dtype::F16 => DataType::FLOAT16,
+            Dtype::BF16 => DataType::BFLOAT16,
+            Dtype::C64 => DataType::COMPLEX64,
+            Dtype::C128 => DataType::COMPLEX128,
+            Dtype::F8E4M3FN => DataType::FLOAT8E4M3FN,
+            Dtype::F8E4M3FNUZ => DataType::FLOAT8E4M3FNUZ,
+            Dtype::F8E5M2 => DataType::FLOAT8E5M2,
+            Dtype::F8E5M2FNUZ => DataType::FLOAT8E5M2FNUZ,
+            Dtype::U4 => return UINT4,
+            Dtype::I4 => return INT4,
+        };
+        data_type as i32
     }
 }
 
@@ -175,14 +296,7 @@ impl ValueInfo {
     pub fn value_info_proto(self, name: String) -> ValueInfoProto {
         ValueInfoProto {
             name,
-            type_: Some(TypeProto {
-                value: match self {
-                    Self::Tensor(t) => Some(t.into()),
-                    Self::Sequence(_s) => todo!(),
-                },
-                ..Default::default()
-            })
-            .into(),
+            type_: Some(self.into()).into(),
             doc_string: String::new(),
             ..Default::default()
         }
@@ -6,6 +6,8 @@ use thiserror::Error;
 pub enum Error {
     #[error("Deserialization faild: `{0}`")]
     DeserializingError(#[from] protobuf::Error),
+    #[error("IO failed: `{0}`")]
+    IoError(#[from] std::io::Error),
     #[error("Validation failed: `{msg}`: {bt:?}")]
     ValidationError { msg: String, bt: String },
 }
@@ -0,0 +1,447 @@
+//! Resolution of tensor data stored outside of the model protobuf.
+//!
+//! Most functions operate on the raw protobuf messages so that the
+//! data is in place before (or after) the conversion into our own
+//! types.
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::protos::{
+    tensor_proto::{DataLocation, DataType},
+    AttributeProto, GraphProto, ModelProto, NodeProto, TensorProto,
+};
+use crate::tensor::ExternalData;
+use crate::utils::{dims_to_shape, num_elements};
+use crate::{Attribute, Error, FunctionAttribute, Graph, Model, Node, Tensor, TensorValue};
+
+/// How [`Model::save_with_external_data`] moves tensor data out of the
+/// model file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalDataPolicy {
+    /// Tensors with less data (in bytes) are stored inline.
+    pub size_threshold: usize,
+    /// Store all data in a single file named like the model with an
+    /// additional `.data` extension instead of one file per tensor.
+    pub single_file: bool,
+    /// Offsets of the tensors within their file are multiples of this
+    /// many bytes.
+    pub alignment: usize,
+}
+
+impl Default for ExternalDataPolicy {
+    fn default() -> Self {
+        Self {
+            size_threshold: 1024,
+            single_file: true,
+            alignment: 4096,
+        }
+    }
+}
+
+/// Path of the external data `location` relative to `base_dir`.
+/// Locations are taken from possibly untrusted models, so like
+/// `onnx.checker` we reject those which could escape `base_dir`.
+pub(crate) fn resolve(base_dir: &Path, location: &Path) -> Result<PathBuf, Error> {
+    let escapes = location.components().any(|c| {
+        matches!(
+            c,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    if escapes || location.as_os_str().is_empty() {
+        return Err(Error::new_validation(format!(
+            "External data location `{}` must be a relative path within the model directory.",
+            location.display()
+        )));
+    }
+    Ok(base_dir.join(location))
+}
+
+/// Reads external data relative to `base_dir`, verifying the
+/// checksum of every file at most once.
+struct Reader<'a> {
+    base_dir: &'a Path,
+    verified: HashSet<(PathBuf, Vec<u8>)>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(base_dir: &'a Path) -> Self {
+        Self {
+            base_dir,
+            verified: HashSet::new(),
+        }
+    }
+
+    fn read(&mut self, external_data: &ExternalData) -> Result<Vec<u8>, Error> {
+        let path = resolve(self.base_dir, &external_data.location)?;
+        if let Some(checksum) = &external_data.checksum {
+            let key = (path.clone(), checksum.clone());
+            if !self.verified.contains(&key) {
+                verify_checksum(&path, checksum)?;
+                self.verified.insert(key);
+            }
+        }
+
+        let mut file = File::open(&path)?;
+        // The range comes from the model, so check it before allocating
+        let size = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+        let start = external_data.offset.unwrap_or_default();
+        let end = match external_data.length {
+            Some(length) => start.checked_add(length),
+            None => Some(size),
+        };
+        if !end.is_some_and(|end| start <= end && end <= size) {
+            return Err(Error::new_validation(format!(
+                "External data range exceeds `{}`.",
+                path.display()
+            )));
+        }
+        file.seek(SeekFrom::Start(start as _))?;
+        Ok(match external_data.length {
+            Some(length) => {
+                let mut buf = vec![0; length];
+                file.read_exact(&mut buf)?;
+                buf
+            }
+            None => {
+                let mut buf = vec![];
+                file.read_to_end(&mut buf)?;
+                buf
+            }
+        })
+    }
+}
+
+fn verify_checksum(path: &Path, expected: &[u8]) -> Result<(), Error> {
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if actual.as_bytes().eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(Error::new_validation(format!(
+            "SHA1 checksum mismatch for `{}`: expected `{}` but found `{}`.",
+            path.display(),
+            String::from_utf8_lossy(expected),
+            actual
+        )))
+    }
+}
+
+/// Read the data of all externally stored tensors into `raw_data`.
+pub(crate) fn load(proto: &mut ModelProto, base_dir: &Path) -> Result<(), Error> {
+    let mut reader = Reader::new(base_dir);
+    visit_model(proto, &mut |tp| {
+        if !is_external(tp) {
+            return Ok(());
+        }
+        tp.raw_data = reader.read(&tp.external_data.clone().try_into()?)?;
+        Ok(())
+    })
+}
+
+/// Read the data of all [`TensorValue::Unloaded`] tensors.
+pub(crate) fn load_unloaded(model: &mut Model, base_dir: &Path) -> Result<(), Error> {
+    let mut reader = Reader::new(base_dir);
+    visit_model_tensors(model, &mut |tensor| {
+        let (TensorValue::Unloaded { dtype, shape }, Some(external_data)) =
+            (&tensor.tensor, &tensor.path)
+        else {
+            return Ok(());
+        };
+        let tp = TensorProto {
+            dims: shape.iter().map(|el| *el as i64).collect(),
+            data_type: (*dtype).into(),
+            raw_data: reader.read(external_data)?,
+            ..Default::default()
+        };
+        tensor.tensor = Tensor::try_from(tp)?.tensor;
+        Ok(())
+    })
+}
+
+/// Mark all tensors holding at least `policy.size_threshold` bytes as
+/// externally stored, unless they already are. `model_name` is the
+/// file name of the model.
+pub(crate) fn externalize(
+    proto: &mut ModelProto,
+    policy: &ExternalDataPolicy,
+    model_name: &str,
+) -> Result<(), Error> {
+    let mut locations = HashSet::from([model_name.to_string()]);
+    visit_model(proto, &mut |tp| {
+        if is_external(tp) || tp.data_type == DataType::STRING as i32 {
+            return Ok(());
+        }
+        if tp.raw_data.len() < policy.size_threshold {
+            return Ok(());
+        }
+        let location = if policy.single_file {
+            format!("{}.data", model_name)
+        } else {
+            unique_file_name(&tp.name, &mut locations)
+        };
+        tp.data_location = DataLocation::EXTERNAL.into();
+        tp.external_data = ExternalData::new(location).into();
+        Ok(())
+    })
+}
+
+/// File name derived from the tensor `name` which is not yet in
+/// `taken`.
+fn unique_file_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    let stem = match stem.trim_start_matches('.') {
+        "" => "tensor".to_string(),
+        stem => stem.to_string(),
+    };
+    let name = std::iter::once(stem.clone())
+        .chain((1..).map(|i| format!("{}_{}", stem, i)))
+        .find(|name| !taken.contains(name))
+        .unwrap();
+    taken.insert(name.clone());
+    name
+}
+
+/// Move the `raw_data` of all externally stored tensors into their
+/// respective files. Files are (re-)written from scratch and tensors
+/// sharing a location are stored back-to-back, each starting at a
+/// multiple of `alignment`.
+///
+/// Each file is written to a temporary file first which then replaces
+/// the original, so that memory maps of the previous contents (see
+/// [`Model::from_path_lazy`]) stay valid.
+pub(crate) fn save(proto: &mut ModelProto, base_dir: &Path, alignment: usize) -> Result<(), Error> {
+    let alignment = alignment.max(1) as u64;
+    let mut files: HashMap<PathBuf, File> = HashMap::new();
+    visit_model(proto, &mut |tp| {
+        if !is_external(tp) {
+            return Ok(());
+        }
+        ensure_loaded(tp)?;
+        let mut external_data: ExternalData = tp.external_data.clone().try_into()?;
+
+        let file = match files.get_mut(&external_data.location) {
+            Some(file) => file,
+            None => {
+                let path = resolve(base_dir, &external_data.location)?;
+                let file = File::create(temporary(&path))?;
+                files.entry(external_data.location.clone()).or_insert(file)
+            }
+        };
+        let end = file.stream_position()?;
+        let offset = end.next_multiple_of(alignment);
+        file.write_all(&vec![0; (offset - end) as usize])?;
+        file.write_all(&tp.raw_data)?;
+
+        external_data.offset = Some(offset as usize);
+        external_data.length = Some(tp.raw_data.len());
+        // The file changed, so any previous checksum is stale
+        external_data.checksum = None;
+        tp.external_data = external_data.into();
+        tp.raw_data = vec![];
+        Ok(())
+    })?;
+
+    for location in files.into_keys() {
+        let path = resolve(base_dir, &location)?;
+        std::fs::rename(temporary(&path), path)?;
+    }
+    Ok(())
+}
+
+fn temporary(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    name.into()
+}
+
+/// Turn all externally stored tensors into regular tensors whose data
+/// is already available in `raw_data`.
+pub(crate) fn inline(proto: &mut ModelProto) -> Result<(), Error> {
+    visit_model(proto, &mut |tp| {
+        if is_external(tp) {
+            ensure_loaded(tp)?;
+            tp.data_location = DataLocation::DEFAULT.into();
+            tp.external_data = vec![];
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn is_external(tp: &TensorProto) -> bool {
+    tp.data_location.enum_value() == Ok(DataLocation::EXTERNAL)
+}
+
+fn ensure_loaded(tp: &TensorProto) -> Result<(), Error> {
+    if tp.raw_data.is_empty() && num_elements(&dims_to_shape(&tp.dims)?)? > 0 {
+        return Err(Error::new_validation(format!(
+            "Data of tensor `{}` has not been loaded.",
+            tp.name
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) type Visitor<'a> = dyn FnMut(&mut TensorProto) -> Result<(), Error> + 'a;
+
+/// Call `f` on every tensor of the model including those nested in
+/// attributes, subgraphs and functions.
+pub(crate) fn visit_model(proto: &mut ModelProto, f: &mut Visitor) -> Result<(), Error> {
+    if let Some(graph) = proto.graph.as_mut() {
+        visit_graph(graph, f)?;
+    }
+    for func in proto.functions.iter_mut() {
+        for attr in func.attribute_proto.iter_mut() {
+            visit_attribute(attr, f)?;
+        }
+        visit_nodes(&mut func.node, f)?;
+    }
+    for info in proto.training_info.iter_mut() {
+        if let Some(graph) = info.initialization.as_mut() {
+            visit_graph(graph, f)?;
+        }
+        if let Some(graph) = info.algorithm.as_mut() {
+            visit_graph(graph, f)?;
+        }
+    }
+    Ok(())
+}
+
+fn visit_graph(graph: &mut GraphProto, f: &mut Visitor) -> Result<(), Error> {
+    for tp in graph.initializer.iter_mut() {
+        f(tp)?;
+    }
+    for sparse in graph.sparse_initializer.iter_mut() {
+        if let Some(tp) = sparse.values.as_mut() {
+            f(tp)?;
+        }
+        if let Some(tp) = sparse.indices.as_mut() {
+            f(tp)?;
+        }
+    }
+    visit_nodes(&mut graph.node, f)
+}
+
+fn visit_nodes(nodes: &mut [NodeProto], f: &mut Visitor) -> Result<(), Error> {
+    for node in nodes.iter_mut() {
+        for attr in node.attribute.iter_mut() {
+            visit_attribute(attr, f)?;
+        }
+    }
+    Ok(())
+}
+
+fn visit_attribute(attr: &mut AttributeProto, f: &mut Visitor) -> Result<(), Error> {
+    if let Some(tp) = attr.t.as_mut() {
+        f(tp)?;
+    }
+    for tp in attr.tensors.iter_mut() {
+        f(tp)?;
+    }
+    if let Some(graph) = attr.g.as_mut() {
+        visit_graph(graph, f)?;
+    }
+    for graph in attr.graphs.iter_mut() {
+        visit_graph(graph, f)?;
+    }
+    for sparse in attr
+        .sparse_tensor
+        .as_mut()
+        .into_iter()
+        .chain(attr.sparse_tensors.iter_mut())
+    {
+        if let Some(tp) = sparse.values.as_mut() {
+            f(tp)?;
+        }
+        if let Some(tp) = sparse.indices.as_mut() {
+            f(tp)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) type TensorVisitor<'a> = dyn FnMut(&mut Tensor) -> Result<(), Error> + 'a;
+
+/// Like [`visit_model`] but on our own types.
+pub(crate) fn visit_model_tensors(model: &mut Model, f: &mut TensorVisitor) -> Result<(), Error> {
+    visit_graph_tensors(&mut model.graph, f)?;
+    for func in model.functions.iter_mut() {
+        for attr in func.attributes.values_mut() {
+            if let FunctionAttribute::Optional(attr) = attr {
+                visit_attribute_tensors(attr, f)?;
+            }
+        }
+        visit_node_tensors(&mut func.nodes, f)?;
+    }
+    for info in model.training_info.iter_mut() {
+        if let Some(graph) = info.initialization.as_mut() {
+            visit_graph_tensors(graph, f)?;
+        }
+        visit_graph_tensors(&mut info.algorithm, f)?;
+    }
+    Ok(())
+}
+
+fn visit_graph_tensors(graph: &mut Graph, f: &mut TensorVisitor) -> Result<(), Error> {
+    for tensor in graph.initializers.values_mut() {
+        f(tensor)?;
+    }
+    for sparse in graph.sparse_initializers.values_mut() {
+        f(&mut sparse.values)?;
+        f(&mut sparse.indices)?;
+    }
+    visit_node_tensors(&mut graph.nodes, f)
+}
+
+fn visit_node_tensors(nodes: &mut [Node], f: &mut TensorVisitor) -> Result<(), Error> {
+    for node in nodes.iter_mut() {
+        for attr in node.attributes.values_mut() {
+            visit_attribute_tensors(attr, f)?;
+        }
+    }
+    Ok(())
+}
+
+fn visit_attribute_tensors(attr: &mut Attribute, f: &mut TensorVisitor) -> Result<(), Error> {
+    match attr {
+        Attribute::Tensor(tensor) => f(tensor)?,
+        Attribute::Tensors(tensors) => {
+            for tensor in tensors.iter_mut() {
+                f(tensor)?;
+            }
+        }
+        Attribute::SparseTensor(sparse) => {
+            f(&mut sparse.values)?;
+            f(&mut sparse.indices)?;
+        }
+        Attribute::SparseTensors(sparse) => {
+            for sparse in sparse.iter_mut() {
+                f(&mut sparse.values)?;
+                f(&mut sparse.indices)?;
+            }
+        }
+        Attribute::Graph(graph) => visit_graph_tensors(graph, f)?,
+        Attribute::Graphs(graphs) => {
+            for graph in graphs.iter_mut() {
+                visit_graph_tensors(graph, f)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
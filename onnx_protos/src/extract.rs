@@ -0,0 +1,220 @@
+//! Extraction of a subgraph between given edges as a model of its own.
+use std::collections::HashSet;
+
+use crate::graph_view::captures;
+use crate::{
+    infer_shapes, Dim, Error, Graph, GraphView, Input, Model, Output, TensorInfo, ValueInfo,
+};
+
+/// Cut the part of `model` computing `outputs` from `inputs` into a
+/// self-contained model.
+///
+/// Only the nodes and initializers needed for `outputs` are kept.
+/// `inputs` may name any edge of the main graph and become the inputs
+/// of the extracted graph; their types are taken from the model or
+/// inferred with [`infer_shapes`]. It is an error if an output depends
+/// on an input of `model` which is not among `inputs`.
+///
+/// ```
+/// use onnx_protos::builder::GraphBuilder;
+/// use onnx_protos::{extract, Dim, Dtype, TensorInfo, ValueInfo};
+///
+/// let mut b = GraphBuilder::new("graph").opset("", 19);
+/// let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+/// let [y] = b.node("Abs", "", &[&x], []);
+/// let [z] = b.node("Relu", "", &[&y], []);
+/// b.output(&z, ValueInfo::Tensor(TensorInfo { shape: None, dtype: Dtype::F32 }));
+/// let model = b.into_model().unwrap();
+///
+/// let slice = extract(&model, &[y.name()], &[z.name()]).unwrap();
+/// assert_eq!(slice.graph.nodes.len(), 1);
+/// assert_eq!(slice.graph.inputs[0].name, y.name());
+/// ```
+pub fn extract(model: &Model, inputs: &[&str], outputs: &[&str]) -> Result<Model, Error> {
+    let mut typed = model.clone();
+    // Types declared in the model suffice if inference is not possible
+    if infer_shapes(&mut typed).is_err() {
+        typed = model.clone();
+    }
+    let graph = &model.graph;
+    let view = GraphView::new(graph);
+    let is_edge = |edge: &str| {
+        view.producer(edge).is_some()
+            || graph.inputs.iter().any(|i| i.name == edge)
+            || graph.initializers.contains_key(edge)
+            || graph.sparse_initializers.contains_key(edge)
+    };
+    if let Some(edge) = inputs.iter().chain(outputs).find(|e| !is_edge(e)) {
+        return Err(Error::new_validation(format!(
+            "`{}` is not an edge of the main graph.",
+            edge
+        )));
+    }
+
+    let cut: HashSet<&str> = inputs.iter().copied().collect();
+    let mut needed = HashSet::new();
+    let mut nodes = HashSet::new();
+    let mut stack: Vec<&str> = outputs.to_vec();
+    while let Some(edge) = stack.pop() {
+        if edge.is_empty() || cut.contains(edge) || !needed.insert(edge) {
+            continue;
+        }
+        if let Some(idx) = view.producer(edge) {
+            if nodes.insert(idx) {
+                let node = &graph.nodes[idx];
+                stack.extend(node.inputs.iter().map(String::as_str));
+                stack.extend(captures(node));
+            }
+        } else if graph.inputs.iter().any(|i| i.name == edge)
+            && !graph.initializers.contains_key(edge)
+        {
+            return Err(Error::new_validation(format!(
+                "Outputs depend on the graph input `{}` which is not extracted.",
+                edge
+            )));
+        }
+    }
+
+    let info = |edge: &str| {
+        info_of(&typed.graph, edge)
+            .ok_or_else(|| Error::new_validation(format!("Cannot infer the type of `{}`.", edge)))
+    };
+    let mut extracted = Graph {
+        name: graph.name.clone(),
+        doc_string: graph.doc_string.clone(),
+        unknown_fields: graph.unknown_fields.clone(),
+        ..Default::default()
+    };
+    for &name in inputs {
+        let original = graph.inputs.iter().find(|i| i.name == name);
+        extracted.inputs.push(Input {
+            name: name.to_string(),
+            info: info(name)?,
+            doc_string: original.and_then(|i| i.doc_string.clone()),
+            denotation: original.and_then(|i| i.denotation.clone()),
+        });
+    }
+    for &name in outputs {
+        let original = graph.outputs.iter().find(|o| o.name == name);
+        extracted.outputs.push(Output {
+            name: name.to_string(),
+            info: info(name)?,
+            doc_string: original.and_then(|o| o.doc_string.clone()),
+            denotation: original.and_then(|o| o.denotation.clone()),
+        });
+    }
+    extracted.nodes = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| nodes.contains(idx))
+        .map(|(_, node)| node.clone())
+        .collect();
+    extracted.initializers = graph
+        .initializers
+        .iter()
+        .filter(|(name, _)| needed.contains(name.as_str()))
+        .map(|(name, tensor)| (name.clone(), tensor.clone()))
+        .collect();
+    extracted.sparse_initializers = graph
+        .sparse_initializers
+        .iter()
+        .filter(|(name, _)| needed.contains(name.as_str()))
+        .map(|(name, sparse)| (name.clone(), sparse.clone()))
+        .collect();
+    extracted.edge_infos = graph
+        .edge_infos
+        .iter()
+        .filter(|(name, _)| needed.contains(name.as_str()) && !outputs.contains(&name.as_str()))
+        .map(|(name, info)| (name.clone(), info.clone()))
+        .collect();
+
+    Ok(Model {
+        graph: extracted,
+        // Bindings refer to initializers which may have been dropped
+        training_info: vec![],
+        ..model.clone()
+    })
+}
+
+fn info_of(graph: &Graph, edge: &str) -> Option<ValueInfo> {
+    let declared = graph
+        .inputs
+        .iter()
+        .find(|i| i.name == edge)
+        .map(|i| &i.info)
+        .or_else(|| {
+            graph
+                .outputs
+                .iter()
+                .find(|o| o.name == edge)
+                .map(|o| &o.info)
+        })
+        .or_else(|| graph.edge_infos.get(edge));
+    if let Some(info) = declared {
+        return Some(info.clone());
+    }
+    let (dtype, dims) = match (
+        graph.initializers.get(edge),
+        graph.sparse_initializers.get(edge),
+    ) {
+        (Some(tensor), _) => (tensor.tensor.dtype(), tensor.tensor.shape()),
+        (None, Some(sparse)) => (sparse.values.tensor.dtype(), sparse.dims.clone()),
+        (None, None) => return None,
+    };
+    Some(ValueInfo::Tensor(TensorInfo {
+        shape: Some(dims.into_iter().map(Dim::Fixed).collect()),
+        dtype,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::GraphBuilder;
+    use crate::Dtype;
+
+    #[test]
+    fn test_extract() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Dynamic("N".into()), Dim::Fixed(3)]);
+        let w = b.initializer("w", ndarray::arr1(&[1f32, 2., 3.]).into_dyn());
+        let unused = b.initializer("unused", ndarray::arr0(1f32).into_dyn());
+        let [a] = b.node("Abs", "", &[&x], []);
+        let [m] = b.node("Mul", "", &[&a, &w], []);
+        let [r] = b.node("Relu", "", &[&m], []);
+        let [n] = b.node("Add", "", &[&a, &unused], []);
+        let info = ValueInfo::Tensor(TensorInfo {
+            shape: None,
+            dtype: Dtype::F32,
+        });
+        b.output(&r, info.clone());
+        b.output(&n, info);
+        let model = b.into_model().unwrap();
+
+        let slice = extract(&model, &[a.name()], &[r.name()]).unwrap();
+        let graph = &slice.graph;
+        let ops: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|n| n.operation.name.as_str())
+            .collect();
+        assert_eq!(ops, ["Mul", "Relu"]);
+        assert_eq!(graph.initializers.keys().collect::<Vec<_>>(), ["w"]);
+        let expected = ValueInfo::Tensor(TensorInfo {
+            shape: Some(vec![Dim::Dynamic("N".into()), Dim::Fixed(3)]),
+            dtype: Dtype::F32,
+        });
+        assert_eq!(graph.inputs[0].info, expected);
+        assert_eq!(graph.outputs[0].info, expected);
+        assert_eq!(slice.opsets, model.opsets);
+
+        // Initializers may be cut as well
+        let slice = extract(&model, &[a.name(), "w"], &[m.name()]).unwrap();
+        assert!(slice.graph.initializers.is_empty());
+        assert_eq!(slice.graph.inputs.len(), 2);
+
+        assert!(extract(&model, &[], &[r.name()]).is_err());
+        assert!(extract(&model, &["missing"], &[r.name()]).is_err());
+    }
+}
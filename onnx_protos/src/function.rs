@@ -1,40 +1,120 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
-use crate::protos::FunctionProto;
+use crate::protos::{FunctionProto, OperatorSetIdProto};
 
-use crate::{Attribute, Error, Node, Operation};
+use crate::{utils::if_not_empty, Attribute, Error, Node, Operation, UnknownFields};
 
 /// Duck-typed local function definition. A frankly rather broken concept.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Function {
-    operation: Operation,
-    doc_string: Option<String>,
-    attributes: HashMap<String, FunctionAttribute>,
-    inputs: Vec<String>,
-    outputs: Vec<String>,
-    nodes: Vec<Node>,
-    opsets: HashMap<String, i64>,
+    /// Name and domain under which nodes may call this function.
+    pub operation: Operation,
+    pub doc_string: Option<String>,
+    pub attributes: IndexMap<String, FunctionAttribute>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub nodes: Vec<Node>,
+    /// Domain and version of opsets used in the body of this function.
+    pub opsets: IndexMap<String, i64>,
+    pub unknown_fields: UnknownFields,
 }
 
-#[allow(unused)]
+/// Attribute declared by a `Function`. Nodes in the function body
+/// refer to it through `Attribute::RefAttrName`.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq)]
-enum FunctionAttribute {
-    Mandatory(String),
+pub enum FunctionAttribute {
+    /// Attribute which must be provided by the calling node.
+    Mandatory,
+    /// Attribute which falls back to the given default value.
     Optional(Attribute),
 }
 
 impl TryFrom<FunctionProto> for Function {
     type Error = Error;
 
-    fn try_from(_value: FunctionProto) -> Result<Self, Self::Error> {
-        todo!()
+    fn try_from(proto: FunctionProto) -> Result<Self, Self::Error> {
+        let unknown_fields = UnknownFields::new(proto.special_fields.unknown_fields(), &[]);
+        let mandatory = proto
+            .attribute
+            .into_iter()
+            .map(|name| (name, FunctionAttribute::Mandatory));
+        let optional = proto
+            .attribute_proto
+            .into_iter()
+            .map(|ap| Ok((ap.name.clone(), FunctionAttribute::Optional(ap.try_into()?))))
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+
+        Ok(Self {
+            operation: Operation {
+                name: proto.name,
+                domain: proto.domain,
+            },
+            doc_string: if_not_empty(proto.doc_string),
+            attributes: mandatory.chain(optional).collect(),
+            inputs: proto.input,
+            outputs: proto.output,
+            nodes: proto
+                .node
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            opsets: proto
+                .opset_import
+                .into_iter()
+                .map(|el| (el.domain, el.version))
+                .collect(),
+            unknown_fields,
+        })
     }
 }
 
 impl TryFrom<Function> for FunctionProto {
     type Error = Error;
 
-    fn try_from(_value: Function) -> Result<Self, Self::Error> {
-        todo!()
+    fn try_from(function: Function) -> Result<Self, Self::Error> {
+        Self::try_from(&function)
+    }
+}
+
+impl TryFrom<&Function> for FunctionProto {
+    type Error = Error;
+
+    fn try_from(function: &Function) -> Result<Self, Self::Error> {
+        let mut attribute = vec![];
+        let mut attribute_proto = vec![];
+        for (name, attr) in function.attributes.iter() {
+            match attr {
+                FunctionAttribute::Mandatory => attribute.push(name.clone()),
+                FunctionAttribute::Optional(default) => {
+                    attribute_proto.push(default.to_proto(name.clone()))
+                }
+            }
+        }
+
+        let mut proto = Self {
+            name: function.operation.name.clone(),
+            domain: function.operation.domain.clone(),
+            doc_string: function.doc_string.clone().unwrap_or_default(),
+            attribute,
+            attribute_proto,
+            input: function.inputs.clone(),
+            output: function.outputs.clone(),
+            node: function.nodes.iter().map(|n| n.into()).collect(),
+            opset_import: function
+                .opsets
+                .iter()
+                .map(|(domain, version)| OperatorSetIdProto {
+                    domain: domain.clone(),
+                    version: *version,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        function
+            .unknown_fields
+            .write_to(proto.special_fields.mut_unknown_fields());
+        Ok(proto)
     }
 }
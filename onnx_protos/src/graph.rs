@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 use crate::protos::GraphProto;
 use crate::protos::ValueInfoProto;
@@ -6,7 +6,9 @@ use crate::protos::ValueInfoProto;
 use crate::utils::if_not_empty;
 use crate::Error;
 use crate::Node;
+use crate::SparseTensor;
 use crate::Tensor;
+use crate::UnknownFields;
 use crate::ValueInfo;
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -15,43 +17,57 @@ pub struct Graph {
     pub doc_string: Option<String>,
     pub nodes: Vec<Node>,
     pub inputs: Vec<Input>,
-    pub initializers: HashMap<String, Tensor>,
+    pub initializers: IndexMap<String, Tensor>,
+    /// Initializers stored in sparse form, keyed by the name of their
+    /// `values`.
+    pub sparse_initializers: IndexMap<String, SparseTensor>,
     pub outputs: Vec<Output>,
     /// Optional information about internal edges of the graph
-    pub edge_infos: HashMap<String, ValueInfo>,
+    pub edge_infos: IndexMap<String, ValueInfo>,
+    pub unknown_fields: UnknownFields,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Input {
     pub name: String,
     pub info: ValueInfo,
+    pub doc_string: Option<String>,
+    /// Semantic description of the type such as `IMAGE` or `TEXT`.
+    pub denotation: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Output {
     pub name: String,
     pub info: ValueInfo,
+    pub doc_string: Option<String>,
+    /// Semantic description of the type such as `IMAGE` or `TEXT`.
+    pub denotation: Option<String>,
 }
 
 impl TryFrom<GraphProto> for Graph {
     type Error = Error;
 
     fn try_from(proto: GraphProto) -> Result<Self, Self::Error> {
-        let initializers: HashMap<String, Tensor> = proto
+        let initializers: IndexMap<String, Tensor> = proto
             .initializer
             .into_iter()
             .map(|tp| Ok((tp.name.clone(), tp.try_into()?)))
             .collect::<Result<_, Self::Error>>()?;
-        let inputs = proto
-            .input
+        let sparse_initializers: IndexMap<String, SparseTensor> = proto
+            .sparse_initializer
             .into_iter()
-            .map(|vip| {
-                Ok(Input {
-                    name: vip.name.clone(),
-                    info: vip.try_into()?,
-                })
+            .map(|sp| {
+                let name = sp.values.name.clone();
+                Ok((name, sp.try_into()?))
             })
             .collect::<Result<_, Self::Error>>()?;
+        let inputs = proto
+            .input
+            .into_iter()
+            .map(|vi| vi.try_into())
+            .collect::<Result<_, _>>()?;
+        let unknown_fields = UnknownFields::new(proto.special_fields.unknown_fields(), &[]);
 
         Ok(Self {
             name: proto.name,
@@ -63,6 +79,7 @@ impl TryFrom<GraphProto> for Graph {
                 .collect::<Result<_, _>>()?,
             inputs,
             initializers,
+            sparse_initializers,
             outputs: proto
                 .output
                 .into_iter()
@@ -73,43 +90,95 @@ impl TryFrom<GraphProto> for Graph {
                 .into_iter()
                 .map(|el| Ok((el.name.clone(), el.try_into()?)))
                 .collect::<Result<_, Self::Error>>()?,
+            unknown_fields,
         })
     }
 }
 
 impl From<Graph> for GraphProto {
     fn from(graph: Graph) -> Self {
+        (&graph).into()
+    }
+}
+
+impl From<&Graph> for GraphProto {
+    fn from(graph: &Graph) -> Self {
         let initializer: Vec<_> = graph
             .initializers
-            .into_iter()
-            .map(|(name, t)| t.tensor_proto(name))
+            .iter()
+            .map(|(name, t)| t.tensor_proto(name.clone()))
             .collect();
-        let mut input = vec![];
-
-        for Input { name, info: kind } in graph.inputs.into_iter() {
-            input.push(kind.value_info_proto(name))
-        }
-        Self {
-            node: graph.nodes.into_iter().map(|n| n.into()).collect(),
-            name: graph.name,
-            input,
+        let mut proto = Self {
+            node: graph.nodes.iter().map(|n| n.into()).collect(),
+            name: graph.name.clone(),
+            input: graph.inputs.iter().map(|el| el.into()).collect(),
             initializer,
-            sparse_initializer: vec![],
-            doc_string: graph.doc_string.unwrap_or_default(),
-            output: graph.outputs.into_iter().map(|el| el.into()).collect(),
+            sparse_initializer: graph
+                .sparse_initializers
+                .iter()
+                .map(|(name, t)| t.sparse_tensor_proto(name.clone()))
+                .collect(),
+            doc_string: graph.doc_string.clone().unwrap_or_default(),
+            output: graph.outputs.iter().map(|el| el.into()).collect(),
             value_info: graph
                 .edge_infos
-                .into_iter()
-                .map(|(name, ty)| ty.value_info_proto(name))
+                .iter()
+                .map(|(name, ty)| ty.clone().value_info_proto(name.clone()))
                 .collect(),
             ..Default::default()
-        }
+        };
+        graph
+            .unknown_fields
+            .write_to(proto.special_fields.mut_unknown_fields());
+        proto
+    }
+}
+
+impl From<Input> for ValueInfoProto {
+    fn from(value: Input) -> Self {
+        annotated_value_info_proto(value.name, value.info, value.doc_string, value.denotation)
+    }
+}
+
+impl From<&Input> for ValueInfoProto {
+    fn from(value: &Input) -> Self {
+        annotated_value_info_proto(
+            value.name.clone(),
+            value.info.clone(),
+            value.doc_string.clone(),
+            value.denotation.clone(),
+        )
+    }
+}
+
+impl TryFrom<ValueInfoProto> for Input {
+    type Error = Error;
+
+    fn try_from(proto: ValueInfoProto) -> Result<Self, Self::Error> {
+        let (doc_string, denotation) = annotations(&proto);
+        Ok(Self {
+            name: proto.name.clone(),
+            info: proto.try_into()?,
+            doc_string,
+            denotation,
+        })
     }
 }
 
 impl From<Output> for ValueInfoProto {
     fn from(value: Output) -> Self {
-        value.info.value_info_proto(value.name.clone())
+        annotated_value_info_proto(value.name, value.info, value.doc_string, value.denotation)
+    }
+}
+
+impl From<&Output> for ValueInfoProto {
+    fn from(value: &Output) -> Self {
+        annotated_value_info_proto(
+            value.name.clone(),
+            value.info.clone(),
+            value.doc_string.clone(),
+            value.denotation.clone(),
+        )
     }
 }
 
@@ -117,10 +186,37 @@ impl TryFrom<ValueInfoProto> for Output {
     type Error = Error;
 
     fn try_from(proto: ValueInfoProto) -> Result<Self, Self::Error> {
-        let name = proto.name.clone();
+        let (doc_string, denotation) = annotations(&proto);
         Ok(Self {
-            name,
+            name: proto.name.clone(),
             info: proto.try_into()?,
+            doc_string,
+            denotation,
         })
     }
 }
+
+fn annotated_value_info_proto(
+    name: String,
+    info: ValueInfo,
+    doc_string: Option<String>,
+    denotation: Option<String>,
+) -> ValueInfoProto {
+    let mut proto = info.value_info_proto(name);
+    proto.doc_string = doc_string.unwrap_or_default();
+    if let Some(typro) = proto.type_.as_mut() {
+        typro.denotation = denotation.unwrap_or_default();
+    }
+    proto
+}
+
+/// Doc string and type denotation of the given `proto`.
+fn annotations(proto: &ValueInfoProto) -> (Option<String>, Option<String>) {
+    (
+        if_not_empty(proto.doc_string.clone()),
+        proto
+            .type_
+            .as_ref()
+            .and_then(|typro| if_not_empty(typro.denotation.clone())),
+    )
+}
@@ -0,0 +1,549 @@
+//! Traversal and rewriting of graphs.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::{Dim, Dtype, GraphView};
+//!
+//! let mut b = GraphBuilder::new("graph");
+//! let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+//! let [y] = b.node("Relu", "", &[&x], []);
+//! let [z] = b.node("Neg", "", &[&y], []);
+//! let mut graph = b.build();
+//!
+//! let view = GraphView::new(&graph);
+//! assert_eq!(view.producer(z.name()), Some(1));
+//! assert_eq!(view.consumers(y.name()), [1]);
+//!
+//! graph.remove_node_and_reconnect(0).unwrap();
+//! assert_eq!(graph.nodes[0].inputs, ["x"]);
+//! ```
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{Attribute, Error, Graph, Node};
+
+/// Producer and consumer maps of the nodes of a [`Graph`].
+///
+/// Nodes are identified by their index in `Graph::nodes`. A node
+/// whose subgraphs capture an edge of the graph counts as a consumer
+/// of that edge.
+#[derive(Debug)]
+pub struct GraphView<'a> {
+    graph: &'a Graph,
+    producers: HashMap<&'a str, usize>,
+    consumers: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> GraphView<'a> {
+    pub fn new(graph: &'a Graph) -> Self {
+        let mut producers = HashMap::new();
+        let mut consumers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, node) in graph.nodes.iter().enumerate() {
+            for output in node.outputs.iter().filter(|o| !o.is_empty()) {
+                producers.insert(output.as_str(), idx);
+            }
+            let mut inputs: Vec<&str> = node.inputs.iter().map(String::as_str).collect();
+            inputs.extend(captures(node));
+            inputs.retain(|input| !input.is_empty());
+            inputs.sort_unstable();
+            inputs.dedup();
+            for input in inputs {
+                consumers.entry(input).or_default().push(idx);
+            }
+        }
+        Self {
+            graph,
+            producers,
+            consumers,
+        }
+    }
+
+    pub fn graph(&self) -> &'a Graph {
+        self.graph
+    }
+
+    /// Index of the node producing `edge`. `None` for graph inputs,
+    /// initializers and edges captured from an outer scope.
+    pub fn producer(&self, edge: &str) -> Option<usize> {
+        self.producers.get(edge).copied()
+    }
+
+    /// Indices of the nodes consuming `edge` in ascending order.
+    pub fn consumers(&self, edge: &str) -> &[usize] {
+        self.consumers.get(edge).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether `edge` is an output of the graph.
+    pub fn is_output(&self, edge: &str) -> bool {
+        self.graph.outputs.iter().any(|o| o.name == edge)
+    }
+
+    /// Indices of all nodes such that every node comes after the
+    /// producers of its inputs. Among the nodes that are ready, the
+    /// one appearing first in the graph is picked, so sorted graphs
+    /// keep their order.
+    pub fn topological_order(&self) -> Result<Vec<usize>, Error> {
+        let dependencies: Vec<HashSet<usize>> = self
+            .graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| {
+                node.inputs
+                    .iter()
+                    .map(String::as_str)
+                    .chain(captures(node))
+                    .filter_map(|input| self.producer(input))
+                    .filter(|producer| *producer != idx)
+                    .collect()
+            })
+            .collect();
+        let mut missing: Vec<usize> = dependencies.iter().map(HashSet::len).collect();
+        let mut dependents = vec![vec![]; self.graph.nodes.len()];
+        for (idx, deps) in dependencies.iter().enumerate() {
+            for dep in deps {
+                dependents[*dep].push(idx);
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..missing.len()).filter(|i| missing[*i] == 0).collect();
+        let mut order = Vec::with_capacity(missing.len());
+        while let Some(idx) = ready.pop_first() {
+            order.push(idx);
+            for dependent in dependents[idx].iter() {
+                missing[*dependent] -= 1;
+                if missing[*dependent] == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+        if order.len() != self.graph.nodes.len() {
+            return Err(Error::new_validation(format!(
+                "Graph `{}` contains a cycle.",
+                self.graph.name
+            )));
+        }
+        Ok(order)
+    }
+
+    /// The nodes in [`topological order`](Self::topological_order).
+    pub fn topological_nodes(&self) -> Result<impl Iterator<Item = &'a Node>, Error> {
+        let nodes = &self.graph.nodes;
+        Ok(self
+            .topological_order()?
+            .into_iter()
+            .map(move |idx| &nodes[idx]))
+    }
+}
+
+/// Edges of the enclosing scopes used by the subgraphs of `node`.
+pub(crate) fn captures(node: &Node) -> Vec<&str> {
+    let mut captured = vec![];
+    for graph in subgraphs(node) {
+        captured_by(graph, &mut captured);
+    }
+    captured.sort_unstable();
+    captured.dedup();
+    captured
+}
+
+fn captured_by<'g>(graph: &'g Graph, captured: &mut Vec<&'g str>) {
+    let defined = defined_edges(graph);
+    let mut used: Vec<&str> = graph.outputs.iter().map(|o| o.name.as_str()).collect();
+    for node in graph.nodes.iter() {
+        used.extend(node.inputs.iter().map(String::as_str));
+        for subgraph in subgraphs(node) {
+            captured_by(subgraph, &mut used);
+        }
+    }
+    captured.extend(
+        used.into_iter()
+            .filter(|edge| !edge.is_empty() && !defined.contains(edge)),
+    );
+}
+
+/// Edges defined inside of `graph` itself.
+fn defined_edges(graph: &Graph) -> HashSet<&str> {
+    graph
+        .inputs
+        .iter()
+        .map(|i| i.name.as_str())
+        .chain(graph.initializers.keys().map(String::as_str))
+        .chain(graph.sparse_initializers.keys().map(String::as_str))
+        .chain(
+            graph
+                .nodes
+                .iter()
+                .flat_map(|n| n.outputs.iter().map(String::as_str)),
+        )
+        .collect()
+}
+
+pub(crate) fn subgraphs(node: &Node) -> impl Iterator<Item = &Graph> {
+    node.attributes.values().flat_map(|attr| match attr {
+        Attribute::Graph(g) => std::slice::from_ref(g),
+        Attribute::Graphs(gs) => gs.as_slice(),
+        _ => &[],
+    })
+}
+
+pub(crate) fn subgraphs_mut(node: &mut Node) -> impl Iterator<Item = &mut Graph> {
+    node.attributes.values_mut().flat_map(|attr| match attr {
+        Attribute::Graph(g) => std::slice::from_mut(g),
+        Attribute::Graphs(gs) => gs.as_mut_slice(),
+        _ => &mut [],
+    })
+}
+
+impl Graph {
+    /// Replace the node at `index`, returning the previous one.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace_node(&mut self, index: usize, node: Node) -> Node {
+        std::mem::replace(&mut self.nodes[index], node)
+    }
+
+    /// Remove the node at `index` and connect the consumers of its
+    /// first output to its first input instead. Fails if any other
+    /// output of the node is used.
+    ///
+    /// If the first output is a graph output, the input edge is
+    /// renamed to it instead, which requires the input to be produced
+    /// by a node of this graph.
+    pub fn remove_node_and_reconnect(&mut self, index: usize) -> Result<Node, Error> {
+        let Some(node) = self.nodes.get(index) else {
+            return Err(Error::new_validation(format!(
+                "Node index {} is out of bounds.",
+                index
+            )));
+        };
+        let (Some(input), Some(output)) = (
+            node.inputs.first().filter(|i| !i.is_empty()),
+            node.outputs.first().filter(|o| !o.is_empty()),
+        ) else {
+            return Err(Error::new_validation(format!(
+                "Node `{}` has no input or output to reconnect.",
+                node.name
+            )));
+        };
+        let (input, output) = (input.clone(), output.clone());
+
+        let view = GraphView::new(self);
+        for other in node.outputs.iter().skip(1).filter(|o| !o.is_empty()) {
+            if !view.consumers(other).is_empty() || view.is_output(other) {
+                return Err(Error::new_validation(format!(
+                    "Output `{}` of node `{}` is still in use.",
+                    other, node.name
+                )));
+            }
+        }
+        let input_is_local = matches!(view.producer(&input), Some(producer) if producer != index);
+        let output_is_graph_output = view.is_output(&output);
+        if output_is_graph_output && (!input_is_local || view.is_output(&input)) {
+            return Err(Error::new_validation(format!(
+                "Cannot connect graph output `{}` to `{}`.",
+                output, input
+            )));
+        }
+        let (from, to) = match output_is_graph_output {
+            true => (&input, &output),
+            false => (&output, &input),
+        };
+        if !self.can_redirect(from, to) {
+            return Err(Error::new_validation(format!(
+                "A subgraph capturing `{}` defines `{}`.",
+                from, to
+            )));
+        }
+
+        let node = self.nodes.remove(index);
+        for output in node.outputs.iter() {
+            self.edge_infos.shift_remove(output);
+        }
+        if output_is_graph_output {
+            self.edge_infos.shift_remove(&input);
+            self.redirect_edge(&input, &output)?;
+        } else {
+            self.redirect_edge(&output, &input)?;
+        }
+        Ok(node)
+    }
+
+    /// Rename the edge `from` to `to` everywhere it is referenced,
+    /// i.e. in nodes, graph inputs and outputs, initializers, edge
+    /// infos and in subgraphs capturing it. Fails if `to` is already
+    /// defined in this graph or in a subgraph capturing `from`.
+    pub fn rename_edge(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if from == to {
+            return Ok(());
+        }
+        if defined_edges(self).contains(to) || self.outputs.iter().any(|o| o.name == to) {
+            return Err(Error::new_validation(format!(
+                "Edge `{}` is already defined.",
+                to
+            )));
+        }
+        self.redirect_edge(from, to)
+    }
+
+    /// Whether no subgraph capturing `from` defines `to`, which would
+    /// otherwise bind the redirected captures.
+    pub(crate) fn can_redirect(&self, from: &str, to: &str) -> bool {
+        !self.nodes.iter().any(|n| defined_in_captures(n, from, to))
+    }
+
+    /// Like [`Graph::rename_edge`], but merges `from` into `to` if it
+    /// is already defined, keeping the edge infos of `to`. Fails
+    /// without changes unless [`Graph::can_redirect`].
+    pub(crate) fn redirect_edge(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if !self.can_redirect(from, to) {
+            return Err(Error::new_validation(format!(
+                "A subgraph capturing `{}` defines `{}`.",
+                from, to
+            )));
+        }
+        for input in self.inputs.iter_mut().filter(|i| i.name == from) {
+            input.name = to.to_string();
+        }
+        for output in self.outputs.iter_mut().filter(|o| o.name == from) {
+            output.name = to.to_string();
+        }
+        rename_key(&mut self.initializers, from, to);
+        rename_key(&mut self.sparse_initializers, from, to);
+        rename_key(&mut self.edge_infos, from, to);
+        rename_in_nodes(&mut self.nodes, from, to);
+        Ok(())
+    }
+
+    /// Insert `node` to consume `edge` and connect all consumers of
+    /// `edge` to the first output of `node` instead. The node is
+    /// placed right after the producer of `edge`. Graph outputs keep
+    /// referring to `edge`; use [`Graph::rename_edge`] to redirect
+    /// them as well.
+    pub fn insert_after(&mut self, edge: &str, node: Node) -> Result<(), Error> {
+        if !node.inputs.iter().any(|i| i == edge) {
+            return Err(Error::new_validation(format!(
+                "Inserted node `{}` does not consume `{}`.",
+                node.name, edge
+            )));
+        }
+        let Some(output) = node.outputs.first().filter(|o| !o.is_empty()).cloned() else {
+            return Err(Error::new_validation(format!(
+                "Inserted node `{}` has no output.",
+                node.name
+            )));
+        };
+        let view = GraphView::new(self);
+        if view.producer(&output).is_some() || defined_edges(self).contains(output.as_str()) {
+            return Err(Error::new_validation(format!(
+                "Edge `{}` is already defined.",
+                output
+            )));
+        }
+        let position = view.producer(edge).map_or(0, |idx| idx + 1);
+
+        for other in self.nodes[position..].iter_mut() {
+            for input in other.inputs.iter_mut().filter(|i| *i == edge) {
+                *input = output.clone();
+            }
+            for subgraph in subgraphs_mut(other) {
+                rename_captured(subgraph, edge, &output);
+            }
+        }
+        self.nodes.insert(position, node);
+        Ok(())
+    }
+
+    /// Reorder the nodes [topologically](GraphView::topological_order).
+    pub fn sort_topologically(&mut self) -> Result<(), Error> {
+        let order = GraphView::new(self).topological_order()?;
+        let mut nodes: Vec<Option<Node>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.nodes = order
+            .into_iter()
+            .map(|idx| nodes[idx].take().unwrap())
+            .collect();
+        Ok(())
+    }
+}
+
+fn rename_in_nodes(nodes: &mut [Node], from: &str, to: &str) {
+    for node in nodes.iter_mut() {
+        for edge in node.inputs.iter_mut().chain(node.outputs.iter_mut()) {
+            if edge == from {
+                *edge = to.to_string();
+            }
+        }
+        for subgraph in subgraphs_mut(node) {
+            rename_captured(subgraph, from, to);
+        }
+    }
+}
+
+/// Rename the outer edge `from` within `graph` unless `graph` defines
+/// an edge of the same name which shadows it.
+fn rename_captured(graph: &mut Graph, from: &str, to: &str) {
+    if defined_edges(graph).contains(from) {
+        return;
+    }
+    for output in graph.outputs.iter_mut().filter(|o| o.name == from) {
+        output.name = to.to_string();
+    }
+    rename_key(&mut graph.edge_infos, from, to);
+    rename_in_nodes(&mut graph.nodes, from, to);
+}
+
+/// Whether a subgraph of `node` capturing `from` defines `to`.
+fn defined_in_captures(node: &Node, from: &str, to: &str) -> bool {
+    subgraphs(node).any(|graph| {
+        let defined = defined_edges(graph);
+        !defined.contains(from)
+            && (defined.contains(to)
+                || graph.nodes.iter().any(|n| defined_in_captures(n, from, to)))
+    })
+}
+
+/// Rename the key `from` to `to`, keeping its position. The entry of
+/// `from` is dropped if `to` is already present.
+fn rename_key<V>(map: &mut indexmap::IndexMap<String, V>, from: &str, to: &str) {
+    if map.contains_key(to) {
+        map.shift_remove(from);
+    } else if let Some((idx, _, value)) = map.shift_remove_full(from) {
+        map.shift_insert(idx, to.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::GraphBuilder;
+    use crate::{Dim, Dtype, Operation, TensorInfo, ValueInfo};
+
+    fn node(op: &str, inputs: &[&str], outputs: &[&str]) -> Node {
+        Node {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            operation: Operation {
+                name: op.into(),
+                domain: "".into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn info() -> ValueInfo {
+        ValueInfo::Tensor(TensorInfo {
+            shape: None,
+            dtype: Dtype::F32,
+        })
+    }
+
+    /// `x -> Relu -> a -> If(then: Neg(a)) -> b -> Identity -> y`
+    fn graph() -> Graph {
+        let mut b = GraphBuilder::new("graph");
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let [a] = b.node("Relu", "", &[&x], []);
+        let branch = Graph {
+            nodes: vec![node("Neg", &[a.name()], &["neg"])],
+            outputs: vec![crate::Output {
+                name: "neg".into(),
+                info: info(),
+                doc_string: None,
+                denotation: None,
+            }],
+            ..Default::default()
+        };
+        let [b_] = b.node("If", "", &[&x], [("then_branch", Attribute::Graph(branch))]);
+        let [y] = b.node("Identity", "", &[&b_], []);
+        b.output(&y, info());
+        b.build()
+    }
+
+    #[test]
+    fn test_view() {
+        let graph = graph();
+        let view = GraphView::new(&graph);
+        let a = &graph.nodes[0].outputs[0];
+        assert_eq!(view.producer(a), Some(0));
+        assert_eq!(view.producer("x"), None);
+        // The `If` node captures `a`
+        assert_eq!(view.consumers(a), [1]);
+        assert_eq!(view.consumers("x"), [0, 1]);
+
+        let mut shuffled = graph.clone();
+        shuffled.nodes.reverse();
+        let order = GraphView::new(&shuffled).topological_order().unwrap();
+        assert_eq!(order, [2, 1, 0]);
+        shuffled.sort_topologically().unwrap();
+        assert_eq!(shuffled, graph);
+
+        let mut cyclic = graph.clone();
+        cyclic.nodes[0].inputs = vec![graph.nodes[2].outputs[0].clone()];
+        assert!(GraphView::new(&cyclic).topological_order().is_err());
+    }
+
+    #[test]
+    fn test_rename_edge() {
+        let mut graph = graph();
+        let a = graph.nodes[0].outputs[0].clone();
+        graph.rename_edge(&a, "renamed").unwrap();
+        assert_eq!(graph.nodes[0].outputs, ["renamed"]);
+        let Attribute::Graph(branch) = &graph.nodes[1].attributes["then_branch"] else {
+            unreachable!()
+        };
+        assert_eq!(branch.nodes[0].inputs, ["renamed"]);
+
+        graph.rename_edge("x", "input").unwrap();
+        assert_eq!(graph.inputs[0].name, "input");
+        assert_eq!(graph.nodes[1].inputs, ["input"]);
+
+        // Renaming onto a defined edge would merge the two
+        let original = graph.clone();
+        assert!(graph.rename_edge("input", "renamed").is_err());
+        let y = graph.outputs[0].name.clone();
+        assert!(graph.rename_edge("renamed", &y).is_err());
+        assert_eq!(graph, original);
+    }
+
+    #[test]
+    fn test_remove_and_insert() {
+        let mut graph = graph();
+        let y = graph.outputs[0].name.clone();
+        let b = graph.nodes[1].outputs[0].clone();
+
+        // Removing the `Identity` producing the graph output renames
+        // the output of the `If`
+        graph.remove_node_and_reconnect(2).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[1].outputs, [y.as_str()]);
+
+        // `Relu` consumes a graph input and produces a captured edge
+        graph.remove_node_and_reconnect(0).unwrap();
+        let Attribute::Graph(branch) = &graph.nodes[0].attributes["then_branch"] else {
+            unreachable!()
+        };
+        assert_eq!(branch.nodes[0].inputs, ["x"]);
+
+        graph
+            .insert_after("x", node("Abs", &["x"], &["abs"]))
+            .unwrap();
+        assert_eq!(graph.nodes[0].operation.name, "Abs");
+        assert_eq!(graph.nodes[1].inputs, ["abs"]);
+        let Attribute::Graph(branch) = &graph.nodes[1].attributes["then_branch"] else {
+            unreachable!()
+        };
+        assert_eq!(branch.nodes[0].inputs, ["abs"]);
+
+        // `x` is a graph input and cannot become the graph output
+        graph.nodes[1] = node("Identity", &["x"], &[&y]);
+        assert!(graph.remove_node_and_reconnect(1).is_err());
+        assert!(graph.remove_node_and_reconnect(10).is_err());
+        assert!(graph.insert_after("x", node("Abs", &["x"], &[&b])).is_ok());
+        assert!(graph
+            .insert_after("x", node("Abs", &["x"], &["abs"]))
+            .is_err());
+    }
+}
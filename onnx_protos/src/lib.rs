@@ -1,21 +1,53 @@
 mod attribute;
+pub mod builder;
+mod checker;
+pub mod compose;
 mod edge_info;
 mod error;
+mod external_data;
+mod extract;
 mod function;
 mod graph;
+mod graph_view;
 mod model;
 mod node;
 mod operation;
+pub mod ops;
+pub mod passes;
 mod protos;
+mod raw_tensor;
+mod render;
+mod shape_inference;
+mod sparse_tensor;
+mod summary;
 mod tensor;
+mod text;
+mod training_info;
+mod unknown_fields;
 mod utils;
+mod version_converter;
 
-pub use attribute::Attribute;
+pub use attribute::{Attribute, AttributeKind};
+pub use checker::Diagnostic;
 pub use edge_info::{Dim, Dtype, TensorInfo, ValueInfo};
 pub use error::Error;
-pub use function::Function;
+pub use external_data::ExternalDataPolicy;
+pub use extract::extract;
+pub use function::{Function, FunctionAttribute};
 pub use graph::{Graph, Input, Output};
+pub use graph_view::GraphView;
+pub use half::{bf16, f16};
+pub use indexmap::IndexMap;
 pub use model::Model;
 pub use node::Node;
+pub use num_complex::{Complex32, Complex64};
 pub use operation::Operation;
-pub use tensor::{Tensor, TensorValue};
+pub use raw_tensor::RawTensor;
+pub use render::RenderOptions;
+pub use shape_inference::infer_shapes;
+pub use sparse_tensor::SparseTensor;
+pub use summary::{Initializer, OpsetUsage, Parameters, Summary};
+pub use tensor::{ExternalData, Tensor, TensorValue};
+pub use training_info::TrainingInfo;
+pub use unknown_fields::UnknownFields;
+pub use version_converter::{convert_opset, MAX_OPSET_VERSION, MIN_OPSET_VERSION};
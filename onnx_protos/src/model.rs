@@ -1,14 +1,23 @@
-use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 use crate::protos::{ModelProto, OperatorSetIdProto, StringStringEntryProto, Version};
+use indexmap::IndexMap;
 use protobuf::{Message, MessageField};
 
-use crate::{utils::if_not_empty, Error, Function, Graph};
+use crate::{
+    checker, external_data, raw_tensor, utils::if_not_empty, Diagnostic, Error, ExternalDataPolicy,
+    Function, Graph, TrainingInfo, UnknownFields,
+};
 
-#[derive(Clone, Debug, PartialEq, Default)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Model {
+    /// IR version the model conforms to. Defaults to the version of
+    /// the vendored `onnx.proto3` for new models, while the version of
+    /// a parsed model is kept as read, even if it is unset (0).
+    pub ir_version: i64,
     /// Domain and version of opsets used in this model.
-    pub opsets: HashMap<String, i64>,
+    pub opsets: IndexMap<String, i64>,
     pub producer_name: Option<String>,
     pub producer_version: Option<String>,
     /// Reverse-DNS name indicating the model namespace or domain, for example, 'org.onnx'
@@ -18,22 +27,134 @@ pub struct Model {
     pub model_version: i64,
     pub doc_string: Option<String>,
     pub graph: Graph,
-    pub metadata: HashMap<String, String>,
+    pub metadata: IndexMap<String, String>,
     pub functions: Vec<Function>,
+    pub training_info: Vec<TrainingInfo>,
+    pub unknown_fields: UnknownFields,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self {
+            ir_version: Version::IR_VERSION as _,
+            opsets: IndexMap::new(),
+            producer_name: None,
+            producer_version: None,
+            domain: None,
+            model_version: 0,
+            doc_string: None,
+            graph: Graph::default(),
+            metadata: IndexMap::new(),
+            functions: vec![],
+            training_info: vec![],
+            unknown_fields: UnknownFields::default(),
+        }
+    }
 }
 
 impl Model {
-    pub fn into_bytes(self) -> Vec<u8> {
-        let proto: ModelProto = self.try_into().unwrap();
-        proto.write_to_bytes().unwrap()
+    /// Like [`Model::to_bytes`] but consumes `self`.
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        self.to_bytes()
+    }
+
+    /// Read a model from `path` without decoding the tensor data.
+    /// Tensors stored in `raw_data` or in external data files become
+    /// [`TensorValue::Raw`](crate::TensorValue::Raw) tensors, the latter
+    /// being backed by memory maps of the files. Such tensors are
+    /// written back out as they are.
+    ///
+    /// The external data files must not be modified by other means
+    /// while the model is alive. Saving the model replaces files
+    /// rather than modifying them and is therefore safe.
+    pub fn from_path_lazy(path: &Path) -> Result<Self, Error> {
+        let mut proto = ModelProto::parse_from_bytes(&fs::read(path)?)?;
+        let detached = raw_tensor::detach(&mut proto, base_dir(path))?;
+        let mut model: Model = proto.try_into()?;
+        raw_tensor::attach(&mut model, detached)?;
+        Ok(model)
+    }
+
+    /// Parse a serialized `ModelProto`.
+    ///
+    /// Tensors referencing external data files are parsed as
+    /// [`TensorValue::Unloaded`](crate::TensorValue::Unloaded). Use
+    /// [`Model::load_external_data`] or [`Model::from_path`] to read
+    /// their data.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        ModelProto::parse_from_bytes(bytes)?.try_into()
+    }
+
+    /// Read a model from `path`. External data files are resolved
+    /// relative to the directory containing the model.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let mut proto = ModelProto::parse_from_bytes(&fs::read(path)?)?;
+        external_data::load(&mut proto, base_dir(path))?;
+        proto.try_into()
+    }
+
+    /// Serialize the model into a self-contained `ModelProto`. Data
+    /// of tensors with an external `path` is stored inline.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut proto = ModelProto::try_from(self)?;
+        external_data::inline(&mut proto)?;
+        Ok(proto.write_to_bytes()?)
+    }
+
+    /// Read the data of all unloaded tensors from their files
+    /// relative to `base_dir`, verifying checksums where given.
+    pub fn load_external_data(&mut self, base_dir: &Path) -> Result<(), Error> {
+        external_data::load_unloaded(self, base_dir)
+    }
+
+    /// Write the model to `path`. Tensors with an external `path` are
+    /// written to their respective files relative to the directory
+    /// containing the model.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut proto = ModelProto::try_from(self)?;
+        external_data::save(&mut proto, base_dir(path), 1)?;
+        fs::write(path, proto.write_to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Like [`Model::save`] but additionally moves the data of all
+    /// tensors of at least `policy.size_threshold` bytes into external
+    /// files next to the model. This allows saving models beyond the
+    /// 2 GB protobuf limit.
+    pub fn save_with_external_data(
+        &self,
+        path: &Path,
+        policy: ExternalDataPolicy,
+    ) -> Result<(), Error> {
+        let model_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "model".to_string());
+        let mut proto = ModelProto::try_from(self)?;
+        external_data::externalize(&mut proto, &policy, &model_name)?;
+        external_data::save(&mut proto, base_dir(path), policy.alignment)?;
+        fs::write(path, proto.write_to_bytes()?)?;
+        Ok(())
     }
+
+    /// Verify that the model is well formed. Returns all problems
+    /// found rather than stopping at the first one.
+    pub fn check(&self) -> Result<(), Vec<Diagnostic>> {
+        checker::check_model(self)
+    }
+}
+
+fn base_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
 }
 
 impl TryFrom<ModelProto> for Model {
     type Error = Error;
 
     fn try_from(proto: ModelProto) -> Result<Self, Self::Error> {
+        let unknown_fields = UnknownFields::new(proto.special_fields.unknown_fields(), &[]);
         Ok(Self {
+            ir_version: proto.ir_version,
             opsets: proto
                 .opset_import
                 .into_iter()
@@ -59,6 +180,12 @@ impl TryFrom<ModelProto> for Model {
                 .into_iter()
                 .map(TryInto::try_into)
                 .collect::<Result<Vec<_>, Error>>()?,
+            training_info: proto
+                .training_info
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            unknown_fields,
         })
     }
 }
@@ -67,40 +194,52 @@ impl TryFrom<Model> for ModelProto {
     type Error = Error;
 
     fn try_from(model: Model) -> Result<Self, Self::Error> {
-        Ok(Self {
-            ir_version: Version::IR_VERSION as _,
+        Self::try_from(&model)
+    }
+}
+
+impl TryFrom<&Model> for ModelProto {
+    type Error = Error;
+
+    fn try_from(model: &Model) -> Result<Self, Self::Error> {
+        let mut proto = Self {
+            ir_version: model.ir_version,
             opset_import: model
                 .opsets
-                .into_iter()
+                .iter()
                 .map(|(domain, version)| OperatorSetIdProto {
-                    domain,
-                    version,
+                    domain: domain.clone(),
+                    version: *version,
                     ..Default::default()
                 })
                 .collect(),
-            producer_name: model.producer_name.unwrap_or_default(),
-            producer_version: model.producer_version.unwrap_or_default(),
-            domain: model.domain.unwrap_or_default(),
+            producer_name: model.producer_name.clone().unwrap_or_default(),
+            producer_version: model.producer_version.clone().unwrap_or_default(),
+            domain: model.domain.clone().unwrap_or_default(),
             model_version: model.model_version,
-            doc_string: model.doc_string.unwrap_or_default(),
-            graph: MessageField::some(model.graph.into()),
+            doc_string: model.doc_string.clone().unwrap_or_default(),
+            graph: MessageField::some((&model.graph).into()),
             metadata_props: model
                 .metadata
-                .into_iter()
+                .iter()
                 .map(|(key, value)| StringStringEntryProto {
-                    key,
-                    value,
+                    key: key.clone(),
+                    value: value.clone(),
                     ..Default::default()
                 })
                 .collect(),
-            training_info: vec![],
+            training_info: model.training_info.iter().map(Into::into).collect(),
             functions: model
                 .functions
-                .into_iter()
+                .iter()
                 .map(|f| f.try_into())
                 .collect::<Result<_, _>>()?,
             ..Default::default()
-        })
+        };
+        model
+            .unknown_fields
+            .write_to(proto.special_fields.mut_unknown_fields());
+        Ok(proto)
     }
 }
 
@@ -109,16 +248,17 @@ mod test {
     use crate::{
         edge_info::{Dim, Dtype, TensorInfo},
         graph::{Input, Output},
-        Attribute, Node, Operation, Tensor, ValueInfo,
+        Attribute, AttributeKind, FunctionAttribute, Node, Operation, Tensor, ValueInfo,
     };
 
     use super::*;
 
-    use crate::protos::ModelProto;
+    use crate::protos::{ModelProto, TrainingInfoProto};
 
     #[test]
     fn test_roundtrip() {
         let expected = Model {
+            ir_version: 7,
             opsets: [("ai.foo".to_string(), 1)].into(),
             producer_name: None,
             producer_version: None,
@@ -131,17 +271,22 @@ mod test {
                 inputs: vec![Input {
                     name: "foo".into(),
                     info: ValueInfo::Tensor(TensorInfo {
-                        shape: vec![Dim::Unknown, Dim::Dynamic("N".into()), Dim::Fixed(42)],
+                        shape: Some(vec![Dim::Unknown, Dim::Dynamic("N".into()), Dim::Fixed(42)]),
                         dtype: Dtype::U8,
                     }),
+                    doc_string: None,
+                    denotation: None,
                 }],
-                initializers: HashMap::new(),
+                initializers: IndexMap::new(),
+                sparse_initializers: IndexMap::new(),
                 outputs: vec![Output {
                     name: "bar".into(),
                     info: ValueInfo::Tensor(TensorInfo {
-                        shape: vec![Dim::Unknown, Dim::Dynamic("N".into()), Dim::Fixed(42)],
+                        shape: Some(vec![Dim::Unknown, Dim::Dynamic("N".into()), Dim::Fixed(42)]),
                         dtype: Dtype::U8,
                     }),
+                    doc_string: None,
+                    denotation: None,
                 }],
                 nodes: vec![Node {
                     name: "baz".into(),
@@ -167,13 +312,264 @@ mod test {
                     .into_iter()
                     .collect(),
                     doc_string: None,
+                    overload: Some("v2".into()),
+                    metadata: [("source".to_string(), "layer.1".to_string())].into(),
+                    attribute_doc_strings: [("attr_int".to_string(), "Answer".to_string())].into(),
+                    unknown_fields: UnknownFields::default(),
                 }],
-                edge_infos: HashMap::new(),
+                edge_infos: IndexMap::new(),
+                unknown_fields: UnknownFields::default(),
             },
             metadata: [("foo".to_string(), "oof".to_string())]
                 .into_iter()
                 .collect(),
             functions: vec![],
+            training_info: vec![TrainingInfo {
+                initialization: None,
+                algorithm: Graph {
+                    name: "step".into(),
+                    ..Default::default()
+                },
+                initialization_binding: IndexMap::new(),
+                update_binding: [("w".to_string(), "w_new".to_string())].into(),
+                unknown_fields: UnknownFields::default(),
+            }],
+            unknown_fields: UnknownFields::default(),
+        };
+
+        let proto: ModelProto = expected.clone().try_into().unwrap();
+        let candidate: Model = proto.try_into().unwrap();
+
+        assert_eq!(expected, candidate);
+    }
+
+    #[test]
+    fn test_roundtrip_functions() {
+        let call = |name: &str, domain: &str, attributes| Node {
+            name: String::new(),
+            inputs: vec!["x".into()],
+            outputs: vec!["y".into()],
+            operation: Operation {
+                name: name.into(),
+                domain: domain.into(),
+            },
+            attributes,
+            ..Default::default()
+        };
+        let function = Function {
+            operation: Operation {
+                name: "Scale".into(),
+                domain: "local".into(),
+            },
+            doc_string: Some("Multiply by `alpha` and add `beta`.".into()),
+            attributes: [
+                ("alpha".to_string(), FunctionAttribute::Mandatory),
+                (
+                    "beta".to_string(),
+                    FunctionAttribute::Optional(Attribute::F32(0.0)),
+                ),
+            ]
+            .into(),
+            inputs: vec!["x".into()],
+            outputs: vec!["y".into()],
+            nodes: vec![call(
+                "Affine",
+                "ai.onnx",
+                [
+                    (
+                        "alpha".to_string(),
+                        Attribute::RefAttrName("alpha".into(), AttributeKind::F32),
+                    ),
+                    (
+                        "beta".to_string(),
+                        Attribute::RefAttrName("beta".into(), AttributeKind::F32),
+                    ),
+                ]
+                .into(),
+            )],
+            opsets: [("".to_string(), 9)].into(),
+            unknown_fields: UnknownFields::default(),
+        };
+        let expected = Model {
+            opsets: [("".to_string(), 9), ("local".to_string(), 1)].into(),
+            graph: Graph {
+                nodes: vec![call(
+                    "Scale",
+                    "local",
+                    [("alpha".to_string(), Attribute::F32(2.0))].into(),
+                )],
+                ..Default::default()
+            },
+            functions: vec![function],
+            ..Default::default()
+        };
+
+        let proto: ModelProto = expected.clone().try_into().unwrap();
+        let candidate: Model = proto.try_into().unwrap();
+
+        assert_eq!(expected, candidate);
+    }
+
+    #[test]
+    fn test_roundtrip_subgraphs() {
+        let info = ValueInfo::Tensor(TensorInfo {
+            shape: Some(vec![Dim::Fixed(2)]),
+            dtype: Dtype::F32,
+        });
+        let node = |op: &str, inputs: &[&str], outputs: &[&str], attributes| Node {
+            name: String::new(),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            operation: Operation {
+                name: op.into(),
+                domain: "".into(),
+            },
+            attributes,
+            ..Default::default()
+        };
+        // Branches capture `x` from the outer scope
+        let branch = |op: &str| Graph {
+            name: op.to_lowercase(),
+            nodes: vec![node(op, &["x"], &["y"], IndexMap::new())],
+            outputs: vec![Output {
+                name: "y".into(),
+                info: info.clone(),
+                doc_string: None,
+                denotation: None,
+            }],
+            ..Default::default()
+        };
+        let tensor = |arr: ndarray::ArrayD<i64>| Tensor {
+            tensor: crate::tensor::TensorValue::I64(arr),
+            path: None,
+        };
+        let expected = Model {
+            opsets: [("".to_string(), 19)].into(),
+            graph: Graph {
+                inputs: vec![
+                    Input {
+                        name: "cond".into(),
+                        info: ValueInfo::Tensor(TensorInfo {
+                            shape: Some(vec![]),
+                            dtype: Dtype::Bool,
+                        }),
+                        doc_string: None,
+                        denotation: None,
+                    },
+                    Input {
+                        name: "x".into(),
+                        info: info.clone(),
+                        doc_string: None,
+                        denotation: None,
+                    },
+                ],
+                outputs: vec![Output {
+                    name: "y".into(),
+                    info: info.clone(),
+                    doc_string: None,
+                    denotation: None,
+                }],
+                nodes: vec![node(
+                    "If",
+                    &["cond"],
+                    &["y"],
+                    [
+                        ("then_branch".to_string(), Attribute::Graph(branch("Abs"))),
+                        ("else_branch".to_string(), Attribute::Graph(branch("Neg"))),
+                        (
+                            "graphs".to_string(),
+                            Attribute::Graphs(vec![branch("Relu"), branch("Exp")]),
+                        ),
+                        (
+                            "tensors".to_string(),
+                            Attribute::Tensors(vec![tensor(ndarray::array![1, 2].into_dyn())]),
+                        ),
+                        (
+                            "sparse_tensor".to_string(),
+                            Attribute::SparseTensor(crate::SparseTensor {
+                                values: tensor(ndarray::array![7].into_dyn()),
+                                indices: tensor(ndarray::array![3].into_dyn()),
+                                dims: vec![2, 2],
+                            }),
+                        ),
+                        (
+                            "sparse_tensors".to_string(),
+                            Attribute::SparseTensors(vec![crate::SparseTensor {
+                                values: tensor(ndarray::array![1, 2].into_dyn()),
+                                indices: tensor(ndarray::array![[0, 0], [1, 1]].into_dyn()),
+                                dims: vec![2, 2],
+                            }]),
+                        ),
+                        ("type_proto".to_string(), Attribute::TypeProto(info.clone())),
+                    ]
+                    .into(),
+                )],
+                sparse_initializers: [(
+                    "w".to_string(),
+                    crate::SparseTensor {
+                        values: tensor(ndarray::array![5].into_dyn()),
+                        indices: tensor(ndarray::array![1].into_dyn()),
+                        dims: vec![4],
+                    },
+                )]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let proto: ModelProto = expected.clone().try_into().unwrap();
+        let candidate: Model = proto.try_into().unwrap();
+
+        assert_eq!(expected, candidate);
+    }
+
+    #[test]
+    fn test_roundtrip_value_infos() {
+        let tensor = |shape| TensorInfo {
+            shape,
+            dtype: Dtype::F32,
+        };
+        let output = |name: &str, info| Output {
+            name: name.into(),
+            info,
+            doc_string: None,
+            denotation: None,
+        };
+        let expected = Model {
+            graph: Graph {
+                inputs: vec![Input {
+                    name: "image".into(),
+                    info: ValueInfo::Tensor(tensor(Some(vec![Dim::Dynamic("N".into())]))),
+                    doc_string: Some("An image".into()),
+                    denotation: Some("IMAGE".into()),
+                }],
+                outputs: vec![
+                    output(
+                        "detections",
+                        ValueInfo::Sequence(Box::new(ValueInfo::Tensor(tensor(None)))),
+                    ),
+                    output(
+                        "probabilities",
+                        ValueInfo::Sequence(Box::new(ValueInfo::Map(
+                            Dtype::I64,
+                            Box::new(ValueInfo::Tensor(tensor(Some(vec![])))),
+                        ))),
+                    ),
+                    output(
+                        "maybe",
+                        ValueInfo::Optional(Box::new(ValueInfo::Sequence(Box::new(
+                            ValueInfo::Tensor(tensor(Some(vec![Dim::Unknown]))),
+                        )))),
+                    ),
+                    output(
+                        "sparse",
+                        ValueInfo::SparseTensor(tensor(Some(vec![Dim::Fixed(3)]))),
+                    ),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let proto: ModelProto = expected.clone().try_into().unwrap();
@@ -181,4 +577,281 @@ mod test {
 
         assert_eq!(expected, candidate);
     }
+
+    #[test]
+    fn test_save_external_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.onnx");
+
+        let make_tensor = |arr: ndarray::ArrayD<f32>| Tensor {
+            tensor: crate::tensor::TensorValue::F32(arr),
+            path: Some(crate::ExternalData::new("weights.bin")),
+        };
+        let expected = Model {
+            graph: Graph {
+                initializers: [
+                    (
+                        "a".to_string(),
+                        make_tensor(ndarray::array![1.0].into_dyn()),
+                    ),
+                    (
+                        "b".to_string(),
+                        make_tensor(ndarray::array![[2.0, 3.0]].into_dyn()),
+                    ),
+                ]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        expected.save(&path).unwrap();
+
+        assert_eq!(
+            std::fs::metadata(dir.path().join("weights.bin"))
+                .unwrap()
+                .len(),
+            12
+        );
+
+        let candidate = Model::from_path(&path).unwrap();
+        let initializers = &candidate.graph.initializers;
+        assert_eq!(
+            initializers["a"].tensor,
+            expected.graph.initializers["a"].tensor
+        );
+        assert_eq!(
+            initializers["b"].tensor,
+            expected.graph.initializers["b"].tensor
+        );
+        assert!(initializers["a"].path.is_some());
+
+        // Without the model directory the data cannot be resolved
+        let bytes = std::fs::read(&path).unwrap();
+        let mut unloaded = Model::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            unloaded.graph.initializers["b"].tensor,
+            crate::TensorValue::Unloaded {
+                dtype: Dtype::F32,
+                shape: vec![1, 2]
+            }
+        );
+        assert!(unloaded.to_bytes().is_err());
+        unloaded.load_external_data(dir.path()).unwrap();
+        assert_eq!(unloaded, candidate);
+
+        // Serializing to bytes inlines all data
+        let inlined = Model::from_bytes(&candidate.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            inlined.graph.initializers["b"].tensor,
+            expected.graph.initializers["b"].tensor
+        );
+        assert_eq!(inlined.graph.initializers["b"].path, None);
+    }
+
+    #[test]
+    fn test_save_with_external_data() {
+        use sha1::{Digest, Sha1};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.onnx");
+
+        let tensor = |len: usize| Tensor {
+            tensor: crate::TensorValue::F32(ndarray::Array::range(0.0, len as f32, 1.0).into_dyn()),
+            path: None,
+        };
+        let model = Model {
+            graph: Graph {
+                initializers: [
+                    ("big".to_string(), tensor(512)),
+                    ("w/2".to_string(), tensor(300)),
+                    ("small".to_string(), tensor(1)),
+                ]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        model
+            .save_with_external_data(&path, ExternalDataPolicy::default())
+            .unwrap();
+        let data = dir.path().join("model.onnx.data");
+        assert_eq!(std::fs::metadata(&data).unwrap().len(), 4096 + 1200);
+
+        let candidate = Model::from_path(&path).unwrap();
+        let initializers = &candidate.graph.initializers;
+        assert_eq!(initializers["big"].path.as_ref().unwrap().offset, Some(0));
+        assert_eq!(
+            initializers["w/2"].path.as_ref().unwrap().offset,
+            Some(4096)
+        );
+        assert_eq!(initializers["small"].path, None);
+        for (name, tensor) in initializers.iter() {
+            assert_eq!(tensor.tensor, model.graph.initializers[name].tensor);
+        }
+
+        // Checksums are verified when loading
+        let digest: String = Sha1::digest(std::fs::read(&data).unwrap())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let mut unloaded = Model::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        let external_data = unloaded.graph.initializers["big"].path.as_mut().unwrap();
+        external_data.checksum = Some(b"0123".to_vec());
+        assert!(unloaded.clone().load_external_data(dir.path()).is_err());
+        let external_data = unloaded.graph.initializers["big"].path.as_mut().unwrap();
+        external_data.checksum = Some(digest.into_bytes());
+        unloaded.load_external_data(dir.path()).unwrap();
+
+        // One file per tensor named after the tensor
+        let policy = ExternalDataPolicy {
+            single_file: false,
+            alignment: 1,
+            ..Default::default()
+        };
+        model.save_with_external_data(&path, policy).unwrap();
+        assert_eq!(
+            std::fs::metadata(dir.path().join("big")).unwrap().len(),
+            2048
+        );
+        assert_eq!(
+            std::fs::metadata(dir.path().join("w_2")).unwrap().len(),
+            1200
+        );
+        let candidate = Model::from_path(&path).unwrap();
+        assert_eq!(
+            candidate.graph.initializers["w/2"].tensor,
+            model.graph.initializers["w/2"].tensor
+        );
+    }
+
+    #[test]
+    fn test_external_data_outside_model_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("model")).unwrap();
+        std::fs::write(dir.path().join("secret"), [0u8; 4]).unwrap();
+        let path = dir.path().join("model").join("model.onnx");
+
+        let outside = dir.path().join("secret");
+        for location in [Path::new("../secret"), outside.as_path()] {
+            let model = Model {
+                graph: Graph {
+                    initializers: [(
+                        "a".to_string(),
+                        Tensor {
+                            tensor: crate::TensorValue::F32(ndarray::array![1.0].into_dyn()),
+                            path: Some(crate::ExternalData::new(location)),
+                        },
+                    )]
+                    .into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            // Nothing is written outside of the model directory
+            assert!(model.save(&path).is_err());
+            assert_eq!(std::fs::read(&outside).unwrap(), [0u8; 4]);
+
+            let proto: ModelProto = model.try_into().unwrap();
+            std::fs::write(&path, proto.write_to_bytes().unwrap()).unwrap();
+            assert!(Model::from_path(&path).is_err());
+            assert!(Model::from_path_lazy(&path).is_err());
+            let mut unloaded = Model::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+            unloaded.graph.initializers["a"].tensor = crate::TensorValue::Unloaded {
+                dtype: Dtype::F32,
+                shape: vec![1],
+            };
+            assert!(unloaded
+                .load_external_data(&dir.path().join("model"))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_unknown_fields() {
+        let model = Model {
+            graph: Graph {
+                nodes: vec![Node {
+                    operation: Operation {
+                        name: "Abs".into(),
+                        domain: "".into(),
+                    },
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut expected: ModelProto = model.try_into().unwrap();
+        expected.ir_version = 3;
+        let fields = expected.special_fields.mut_unknown_fields();
+        fields.add_varint(100, 1);
+        fields.add_length_delimited(101, b"future".to_vec());
+        let node = &mut expected.graph.as_mut().unwrap().node[0];
+        node.special_fields
+            .mut_unknown_fields()
+            .add_length_delimited(8, b"overload".to_vec());
+        node.special_fields.mut_unknown_fields().add_fixed32(100, 7);
+        let mut training = TrainingInfoProto::new();
+        training.algorithm = MessageField::some(Graph::default().into());
+        training
+            .special_fields
+            .mut_unknown_fields()
+            .add_varint(100, 2);
+        expected.training_info.push(training);
+
+        let candidate = Model::from_bytes(&expected.write_to_bytes().unwrap()).unwrap();
+        assert_eq!(candidate.ir_version, 3);
+        assert!(!candidate.training_info[0].unknown_fields.is_empty());
+        assert_eq!(
+            candidate.graph.nodes[0].overload.as_deref(),
+            Some("overload")
+        );
+        assert!(!candidate.unknown_fields.is_empty());
+
+        let candidate = ModelProto::parse_from_bytes(&candidate.to_bytes().unwrap()).unwrap();
+        assert_eq!(expected, candidate);
+
+        // A missing IR version is kept rather than replaced by the latest
+        expected.ir_version = 0;
+        let candidate = Model::from_bytes(&expected.write_to_bytes().unwrap()).unwrap();
+        assert_eq!(candidate.ir_version, 0);
+    }
+
+    #[test]
+    fn test_deterministic_order() {
+        let tensor = |value: f32| Tensor {
+            tensor: crate::tensor::TensorValue::F32(ndarray::arr1(&[value]).into_dyn()),
+            path: None,
+        };
+        let info = ValueInfo::Tensor(TensorInfo {
+            shape: None,
+            dtype: Dtype::F32,
+        });
+        let names = ["z", "a", "m", "b", "y", "c"];
+        let model = Model {
+            opsets: [("ai.foo".to_string(), 1), ("".to_string(), 19)].into(),
+            metadata: names.map(|k| (k.to_string(), k.to_string())).into(),
+            graph: Graph {
+                initializers: names.map(|k| (k.to_string(), tensor(1.0))).into(),
+                edge_infos: names.map(|k| (k.to_string(), info.clone())).into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let bytes = model.to_bytes().unwrap();
+        assert_eq!(bytes, model.clone().into_bytes().unwrap());
+
+        let proto = ModelProto::parse_from_bytes(&bytes).unwrap();
+        let initializers: Vec<_> = proto.graph.initializer.iter().map(|t| &t.name).collect();
+        let value_infos: Vec<_> = proto.graph.value_info.iter().map(|v| &v.name).collect();
+        assert_eq!(initializers, names);
+        assert_eq!(value_infos, names);
+
+        let candidate = Model::from_bytes(&bytes).unwrap();
+        assert!(candidate.graph.initializers.keys().eq(names));
+        assert!(candidate.metadata.keys().eq(names));
+        assert_eq!(candidate.to_bytes().unwrap(), bytes);
+    }
 }
@@ -1,42 +1,101 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
-use crate::protos::NodeProto;
+use crate::protos::{NodeProto, StringStringEntryProto};
+use protobuf::Message;
 
+use crate::unknown_fields::{length_delimited, UnknownFields};
 use crate::{utils::if_not_empty, Attribute, Error, Operation};
 
-#[derive(Clone, Debug, PartialEq)]
+/// `NodeProto` fields newer than the vendored `onnx.proto3`.
+const OVERLOAD: u32 = 8;
+const METADATA_PROPS: u32 = 9;
+
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct Node {
     pub name: String,
     pub inputs: Vec<String>,
     pub outputs: Vec<String>,
     pub operation: Operation,
-    pub attributes: HashMap<String, Attribute>,
+    pub attributes: IndexMap<String, Attribute>,
     pub doc_string: Option<String>,
+    /// Overload of the called function, if it is overloaded.
+    pub overload: Option<String>,
+    pub metadata: IndexMap<String, String>,
+    /// Doc strings of the `attributes` by name.
+    pub attribute_doc_strings: IndexMap<String, String>,
+    pub unknown_fields: UnknownFields,
 }
 
 impl From<Node> for NodeProto {
     fn from(node: Node) -> Self {
-        Self {
-            name: node.name,
-            input: node.inputs,
-            output: node.outputs,
-            op_type: node.operation.name,
-            domain: node.operation.domain,
-            doc_string: node.doc_string.unwrap_or_default(),
+        (&node).into()
+    }
+}
+
+impl From<&Node> for NodeProto {
+    fn from(node: &Node) -> Self {
+        let mut proto = Self {
+            name: node.name.clone(),
+            input: node.inputs.clone(),
+            output: node.outputs.clone(),
+            op_type: node.operation.name.clone(),
+            domain: node.operation.domain.clone(),
+            doc_string: node.doc_string.clone().unwrap_or_default(),
             attribute: node
                 .attributes
-                .into_iter()
-                .map(|(name, attr)| attr.into_proto(name))
+                .iter()
+                .map(|(name, attr)| {
+                    let mut ap = attr.to_proto(name.clone());
+                    ap.doc_string =
+                        (node.attribute_doc_strings.get(name).cloned()).unwrap_or_default();
+                    ap
+                })
                 .collect(),
             ..Default::default()
+        };
+
+        let fields = proto.special_fields.mut_unknown_fields();
+        if let Some(overload) = &node.overload {
+            fields.add_length_delimited(OVERLOAD, overload.as_bytes().to_vec());
         }
+        for (key, value) in node.metadata.iter() {
+            let entry = StringStringEntryProto {
+                key: key.clone(),
+                value: value.clone(),
+                ..Default::default()
+            };
+            fields.add_length_delimited(METADATA_PROPS, entry.write_to_bytes().unwrap());
+        }
+        node.unknown_fields.write_to(fields);
+        proto
     }
 }
 
 impl TryFrom<NodeProto> for Node {
     type Error = Error;
 
-    fn try_from(proto: NodeProto) -> Result<Self, Self::Error> {
+    fn try_from(mut proto: NodeProto) -> Result<Self, Self::Error> {
+        let fields = proto.special_fields.unknown_fields();
+        let overload = length_delimited(fields, OVERLOAD)
+            .last()
+            .map(|bytes| String::from_utf8(bytes.to_vec()))
+            .transpose()
+            .map_err(|e| Error::new_validation(format!("Invalid node overload: `{}`", e)))?;
+        let metadata = length_delimited(fields, METADATA_PROPS)
+            .into_iter()
+            .map(|bytes| {
+                let entry = StringStringEntryProto::parse_from_bytes(bytes)?;
+                Ok((entry.key, entry.value))
+            })
+            .collect::<Result<_, Self::Error>>()?;
+        let unknown_fields = UnknownFields::new(fields, &[OVERLOAD, METADATA_PROPS]);
+        let attribute_doc_strings = proto
+            .attribute
+            .iter_mut()
+            .filter(|ap| !ap.doc_string.is_empty())
+            .map(|ap| (ap.name.clone(), std::mem::take(&mut ap.doc_string)))
+            .collect();
+
         Ok(Node {
             name: proto.name,
             inputs: proto.input,
@@ -51,6 +110,10 @@ impl TryFrom<NodeProto> for Node {
                 .map(|ap| Ok((ap.name.clone(), ap.try_into()?)))
                 .collect::<Result<_, Self::Error>>()?,
             doc_string: if_not_empty(proto.doc_string),
+            overload,
+            metadata,
+            attribute_doc_strings,
+            unknown_fields,
         })
     }
 }
@@ -0,0 +1,253 @@
+//! Typed constructors for the operators of the default domain.
+//!
+//! The module `vN` holds the operators as defined in opset `N`. Each
+//! operator is a function adding a node to a [`GraphBuilder`], taking
+//! its inputs as [`Handle`]s and, if it has any attributes, a struct
+//! with one field per attribute. Optional attributes left at `None`
+//! are not set on the node and fall back to their default.
+//!
+//! The functions are generated by `build.rs` from the vendored schema
+//! in `protobuf_gen/op_schema.json`, which covers every operator of the
+//! default domain in the opsets [`MIN_OPSET_VERSION`] to
+//! [`MAX_OPSET_VERSION`]. Deprecated operators such as `Upsample` are
+//! left out of the opsets deprecating them. Operators with a variadic
+//! output such as `If`, `Loop` and `Split` take the number of outputs
+//! as a const generic. Operators such as `LSTM`, whose outputs are all
+//! optional, produce all of them.
+//!
+//! [`MIN_OPSET_VERSION`]: crate::MIN_OPSET_VERSION
+//! [`MAX_OPSET_VERSION`]: crate::MAX_OPSET_VERSION
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::ops::v19::*;
+//! use onnx_protos::{Dim, Dtype, TensorInfo, ValueInfo};
+//!
+//! let mut g = GraphBuilder::new("graph").opset("", 19);
+//! let x = g.input("x", Dtype::F32, &[Dim::Fixed(1), Dim::Fixed(3)]);
+//! let y = relu(&mut g, &x);
+//! let z = softmax(&mut g, &y, SoftmaxAttrs { axis: Some(1) });
+//! g.output(
+//!     &z,
+//!     ValueInfo::Tensor(TensorInfo {
+//!         shape: Some(vec![Dim::Fixed(1), Dim::Fixed(3)]),
+//!         dtype: Dtype::F32,
+//!     }),
+//! );
+//! assert_eq!(g.into_model().unwrap().check(), Ok(()));
+//! ```
+#![allow(clippy::too_many_arguments)]
+
+use crate::builder::{GraphBuilder, Handle};
+use crate::Attribute;
+
+include!(concat!(env!("OUT_DIR"), "/ops.rs"));
+
+/// Add a node of the default domain. Omitted optional inputs in the
+/// middle are passed as empty names, trailing ones are dropped.
+fn add_node<const N: usize>(
+    g: &mut GraphBuilder,
+    op: &str,
+    mut inputs: Vec<Option<&Handle>>,
+    attributes: Vec<(&str, Attribute)>,
+) -> [Handle; N] {
+    while let Some(None) = inputs.last() {
+        inputs.pop();
+    }
+    let omitted = Handle::omitted();
+    let inputs: Vec<&Handle> = inputs.into_iter().map(|h| h.unwrap_or(&omitted)).collect();
+    g.node(op, "", &inputs, attributes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dim, Dtype, TensorInfo, ValueInfo};
+
+    #[test]
+    fn test_conv() {
+        use v19::*;
+
+        let mut g = GraphBuilder::new("graph").opset("", 19);
+        let x = g.input(
+            "x",
+            Dtype::F32,
+            &[Dim::Fixed(1), Dim::Fixed(1), Dim::Fixed(4)],
+        );
+        let w = g.initializer("w", ndarray::Array3::<f32>::ones((2, 1, 3)).into_dyn());
+        let scale = g.initializer("scale", ndarray::arr1(&[0.5f32, 2.0]).into_dyn());
+        let y = conv(
+            &mut g,
+            &x,
+            &w,
+            None,
+            ConvAttrs {
+                pads: Some(vec![1, 1]),
+                ..Default::default()
+            },
+        );
+        let y = mul(&mut g, &y, &scale);
+        let [a, b] = split::<2>(
+            &mut g,
+            &y,
+            None,
+            SplitAttrs {
+                axis: Some(1),
+                num_outputs: Some(2),
+            },
+        );
+        let c = concat(&mut g, &[&a, &b], ConcatAttrs { axis: 2 });
+        let clipped = clip(&mut g, &c, None, Some(&scale));
+        g.output(
+            &clipped,
+            ValueInfo::Tensor(TensorInfo {
+                shape: None,
+                dtype: Dtype::F32,
+            }),
+        );
+
+        let model = g.into_model().unwrap();
+        assert_eq!(model.check(), Ok(()));
+
+        let nodes = &model.graph.nodes;
+        assert_eq!(nodes[0].operation.name, "Conv");
+        assert_eq!(nodes[0].inputs, ["x", "w"]);
+        assert_eq!(nodes[0].attributes["pads"], Attribute::I64s(vec![1, 1]));
+        assert!(!nodes[0].attributes.contains_key("group"));
+        assert_eq!(nodes[2].outputs.len(), 2);
+        assert_eq!(nodes[3].attributes["axis"], Attribute::I64(2));
+        assert_eq!(nodes[4].inputs, [c.name(), "", "scale"]);
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut g = GraphBuilder::new("graph").opset("", 13);
+        let x = g.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let axes = g.initializer("axes", ndarray::arr1(&[0i64]).into_dyn());
+
+        // `ReduceMean` takes the axes as an attribute until opset 18
+        v13::reduce_mean(
+            &mut g,
+            &x,
+            v13::ReduceMeanAttrs {
+                axes: Some(vec![0]),
+                keepdims: Some(0),
+            },
+        );
+        v18::reduce_mean(&mut g, &x, Some(&axes), Default::default());
+        v19::cast(
+            &mut g,
+            &x,
+            v19::CastAttrs {
+                to: 7,
+                saturate: None,
+            },
+        );
+
+        let graph = g.build();
+        assert_eq!(graph.nodes[0].inputs, ["x"]);
+        assert_eq!(graph.nodes[1].inputs, ["x", "axes"]);
+        assert_eq!(graph.nodes[2].attributes["to"], Attribute::I64(7));
+    }
+
+    #[test]
+    fn test_opsets_10_to_12() {
+        let mut g = GraphBuilder::new("graph").opset("", 10);
+        let x = g.input(
+            "x",
+            Dtype::F32,
+            &[Dim::Fixed(1), Dim::Fixed(2), Dim::Fixed(3)],
+        );
+        let scales = g.initializer("scales", ndarray::arr1(&[1.0f32, 1.0, 2.0]).into_dyn());
+        let pads = g.initializer("pads", ndarray::arr1(&[0i64, 0, 1, 0, 0, 1]).into_dyn());
+
+        // `Resize` and `Pad` take more of their arguments as inputs from opset 11
+        v10::resize(&mut g, &x, &scales, Default::default());
+        v11::resize(
+            &mut g,
+            &x,
+            &scales,
+            &scales,
+            None,
+            v11::ResizeAttrs {
+                mode: Some("linear".into()),
+                ..Default::default()
+            },
+        );
+        v10::pad(
+            &mut g,
+            &x,
+            v10::PadAttrs {
+                pads: vec![0, 0, 1, 0, 0, 1],
+                mode: None,
+                value: Some(1.0),
+            },
+        );
+        v12::pad(&mut g, &x, &pads, None, Default::default());
+
+        let graph = g.build();
+        assert_eq!(graph.nodes[0].inputs, ["x", "scales"]);
+        assert_eq!(graph.nodes[1].inputs, ["x", "scales", "scales"]);
+        assert_eq!(graph.nodes[2].attributes["value"], Attribute::F32(1.0));
+        assert_eq!(graph.nodes[3].inputs, ["x", "pads"]);
+    }
+
+    #[test]
+    fn test_control_flow_and_recurrent() {
+        let mut g = GraphBuilder::new("graph").opset("", 11);
+        let x = g.input(
+            "x",
+            Dtype::F32,
+            &[Dim::Fixed(1), Dim::Fixed(1), Dim::Fixed(2)],
+        );
+        let c = g.input("c", Dtype::Bool, &[]);
+        let w = g.initializer("w", ndarray::Array3::<f32>::ones((1, 4, 2)).into_dyn());
+        let r = g.initializer("r", ndarray::Array3::<f32>::ones((1, 4, 1)).into_dyn());
+
+        let [a, b] = v11::r#if::<2>(
+            &mut g,
+            &c,
+            v11::IfAttrs {
+                then_branch: GraphBuilder::new("then").build(),
+                else_branch: GraphBuilder::new("else").build(),
+            },
+        );
+        let [v] = v11::r#loop::<1>(
+            &mut g,
+            None,
+            Some(&c),
+            &[&a],
+            v11::LoopAttrs {
+                body: GraphBuilder::new("body").build(),
+            },
+        );
+        let [y, y_h, y_c] = v10::lstm(
+            &mut g,
+            &x,
+            &w,
+            &r,
+            None,
+            None,
+            None,
+            None,
+            None,
+            v10::LSTMAttrs {
+                hidden_size: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let graph = g.build();
+        assert_eq!(graph.nodes[0].outputs, [a.name(), b.name()]);
+        assert!(matches!(
+            graph.nodes[0].attributes["then_branch"],
+            Attribute::Graph(_)
+        ));
+        // The omitted trip count is kept in front of the condition
+        assert_eq!(graph.nodes[1].inputs, ["", "c", a.name()]);
+        assert_eq!(graph.nodes[1].outputs, [v.name()]);
+        assert_eq!(graph.nodes[2].inputs, ["x", "w", "r"]);
+        assert_eq!(graph.nodes[2].outputs, [y.name(), y_h.name(), y_c.name()]);
+        assert_eq!(graph.nodes[2].attributes["hidden_size"], Attribute::I64(1));
+    }
+}
@@ -0,0 +1,980 @@
+//! Graph optimizations which rewrite a [`Model`] in place.
+//!
+//! Every pass reports the changes it made, so the effect of an
+//! optimization can be inspected without diffing the serialized
+//! models. Passes are applied to subgraphs as well unless noted
+//! otherwise.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::passes::{Change, PassManager};
+//! use onnx_protos::{Dim, Dtype, TensorInfo, ValueInfo};
+//!
+//! let mut b = GraphBuilder::new("graph").opset("", 19);
+//! let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+//! let [y] = b.node("Identity", "", &[&x], []);
+//! let [z] = b.node("Relu", "", &[&y], []);
+//! b.node::<1>("Neg", "", &[&y], []);
+//! let info = ValueInfo::Tensor(TensorInfo {
+//!     shape: None,
+//!     dtype: Dtype::F32,
+//! });
+//! b.output(&z, info);
+//! let mut model = b.into_model().unwrap();
+//!
+//! let report = PassManager::with_default_passes().run(&mut model).unwrap();
+//! assert_eq!(model.graph.nodes.len(), 1);
+//! assert_eq!(model.graph.nodes[0].inputs, ["x"]);
+//! assert_eq!(report.changes.len(), 2);
+//! println!("{}", report);
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use ndarray::ArrayD;
+
+use crate::graph_view::{captures, subgraphs};
+use crate::{Attribute, Dim, Error, Graph, GraphView, Model, Node, Tensor, TensorValue, ValueInfo};
+
+/// A single rewrite performed by a [`Pass`].
+///
+/// Nodes are identified by their name or, if they are unnamed, by
+/// their operator type and first output. Names inside of subgraphs
+/// are prefixed by the path of the parent node and the attribute
+/// holding the subgraph.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    RemovedNode {
+        node: String,
+    },
+    RemovedInitializer {
+        name: String,
+    },
+    /// All consumers of `from` now consume the equivalent `to`.
+    Redirected {
+        from: String,
+        to: String,
+    },
+    /// `node` was replaced by initializers holding its outputs.
+    Folded {
+        node: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::RemovedNode { node } => write!(f, "Removed node `{}`", node),
+            Change::RemovedInitializer { name } => write!(f, "Removed initializer `{}`", name),
+            Change::Redirected { from, to } => write!(f, "Redirected `{}` to `{}`", from, to),
+            Change::Folded { node } => write!(f, "Folded node `{}` into constants", node),
+        }
+    }
+}
+
+/// A rewrite of a model.
+pub trait Pass {
+    fn name(&self) -> &str;
+
+    /// Rewrite `model`, returning all changes made.
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error>;
+}
+
+/// The changes made by a [`PassManager`], each with the name of the
+/// pass which made it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub changes: Vec<(String, Change)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pass, change) in self.changes.iter() {
+            writeln!(f, "{}: {}", pass, change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a sequence of passes repeatedly until none of them changes
+/// the model any more.
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    max_iterations: usize,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            max_iterations: 8,
+        }
+    }
+
+    /// All built-in passes.
+    pub fn with_default_passes() -> Self {
+        Self::new()
+            .pass(IdentityElimination)
+            .pass(ShapeFolding)
+            .pass(InitializerDeduplication)
+            .pass(CommonSubexpressionElimination)
+            .pass(DeadCodeElimination)
+    }
+
+    /// Append `pass` to the passes to run.
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Limit the number of rounds over all passes, 8 by default.
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn run(&self, model: &mut Model) -> Result<Report, Error> {
+        let mut report = Report::default();
+        for _ in 0..self.max_iterations {
+            let before = report.changes.len();
+            for pass in self.passes.iter() {
+                let changes = pass.run(model)?;
+                report
+                    .changes
+                    .extend(changes.into_iter().map(|c| (pass.name().to_string(), c)));
+            }
+            if report.changes.len() == before {
+                break;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Removes nodes none of whose outputs are used and initializers
+/// which are not used. Initializers which are also graph inputs are
+/// kept as they are part of the interface.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &str {
+        "dead-code-elimination"
+    }
+
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error> {
+        let mut changes = vec![];
+        for_each_graph(&mut model.graph, "", &mut |graph, prefix| {
+            eliminate_dead_code(graph, prefix, &mut changes);
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+}
+
+fn eliminate_dead_code(graph: &mut Graph, prefix: &str, changes: &mut Vec<Change>) {
+    let mut used: HashSet<String> = graph.outputs.iter().map(|o| o.name.clone()).collect();
+    let mut live = vec![false; graph.nodes.len()];
+    for (idx, node) in graph.nodes.iter().enumerate().rev() {
+        if node.outputs.iter().any(|o| used.contains(o)) {
+            live[idx] = true;
+            used.extend(node.inputs.iter().cloned());
+            used.extend(captures(node).into_iter().map(String::from));
+        }
+    }
+
+    let mut idx = 0;
+    graph.nodes.retain(|node| {
+        idx += 1;
+        if !live[idx - 1] {
+            changes.push(Change::RemovedNode {
+                node: node_label(prefix, node),
+            });
+            for output in node.outputs.iter() {
+                graph.edge_infos.shift_remove(output);
+            }
+        }
+        live[idx - 1]
+    });
+
+    let inputs: HashSet<&str> = graph.inputs.iter().map(|i| i.name.as_str()).collect();
+    let unused = |name: &String| !used.contains(name) && !inputs.contains(name.as_str());
+    for name in graph.initializers.keys().filter(|n| unused(n)) {
+        changes.push(Change::RemovedInitializer {
+            name: format!("{}{}", prefix, name),
+        });
+    }
+    for name in graph.sparse_initializers.keys().filter(|n| unused(n)) {
+        changes.push(Change::RemovedInitializer {
+            name: format!("{}{}", prefix, name),
+        });
+    }
+    graph.initializers.retain(|name, _| !unused(name));
+    graph.sparse_initializers.retain(|name, _| !unused(name));
+}
+
+/// Removes `Identity` nodes by connecting their consumers to their
+/// input. Identities between a graph input and a graph output are
+/// kept.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IdentityElimination;
+
+impl Pass for IdentityElimination {
+    fn name(&self) -> &str {
+        "identity-elimination"
+    }
+
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error> {
+        let mut changes = vec![];
+        for_each_graph(&mut model.graph, "", &mut |graph, prefix| {
+            let mut idx = 0;
+            while idx < graph.nodes.len() {
+                let node = &graph.nodes[idx];
+                if is_op(node, "Identity") && node.inputs.len() == 1 {
+                    let label = node_label(prefix, node);
+                    if graph.remove_node_and_reconnect(idx).is_ok() {
+                        changes.push(Change::RemovedNode { node: label });
+                        continue;
+                    }
+                }
+                idx += 1;
+            }
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+}
+
+/// Merges nodes which apply the same operator with the same
+/// attributes to the same inputs. Nodes with subgraphs and
+/// nondeterministic operators are never merged.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommonSubexpressionElimination;
+
+const NONDETERMINISTIC: [&str; 6] = [
+    "Bernoulli",
+    "Multinomial",
+    "RandomNormal",
+    "RandomNormalLike",
+    "RandomUniform",
+    "RandomUniformLike",
+];
+
+impl Pass for CommonSubexpressionElimination {
+    fn name(&self) -> &str {
+        "common-subexpression-elimination"
+    }
+
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error> {
+        let mut changes = vec![];
+        for_each_graph(&mut model.graph, "", &mut |graph, prefix| {
+            // (domain, operator, inputs) -> nodes kept so far
+            let mut seen: HashMap<(String, String, Vec<String>), Vec<Node>> = HashMap::new();
+            let mut idx = 0;
+            while idx < graph.nodes.len() {
+                let node = &graph.nodes[idx];
+                let mergeable = subgraphs(node).next().is_none()
+                    && !(is_default_domain(&node.operation.domain)
+                        && NONDETERMINISTIC.contains(&node.operation.name.as_str()))
+                    && !node
+                        .outputs
+                        .iter()
+                        .any(|o| graph.outputs.iter().any(|out| out.name == *o));
+                if !mergeable {
+                    idx += 1;
+                    continue;
+                }
+                let key = (
+                    node.operation.domain.clone(),
+                    node.operation.name.clone(),
+                    node.inputs.clone(),
+                );
+                let candidates = seen.entry(key).or_default();
+                // Every output in use must have a counterpart which
+                // subgraphs capturing the output do not shadow
+                let Some(original) = candidates.iter().find(|other| {
+                    other.attributes == node.attributes
+                        && other.outputs.len() == node.outputs.len()
+                        && (other.outputs.iter().zip(node.outputs.iter())).all(|(to, from)| {
+                            from.is_empty() || (!to.is_empty() && graph.can_redirect(from, to))
+                        })
+                }) else {
+                    candidates.push(node.clone());
+                    idx += 1;
+                    continue;
+                };
+
+                let original_outputs = original.outputs.clone();
+                let node = graph.nodes.remove(idx);
+                changes.push(Change::RemovedNode {
+                    node: node_label(prefix, &node),
+                });
+                for (from, to) in node.outputs.iter().zip(original_outputs.iter()) {
+                    if from.is_empty() {
+                        continue;
+                    }
+                    graph.edge_infos.shift_remove(from);
+                    graph.redirect_edge(from, to)?;
+                    changes.push(Change::Redirected {
+                        from: format!("{}{}", prefix, from),
+                        to: format!("{}{}", prefix, to),
+                    });
+                }
+                // Renaming invalidates the keys of later nodes only,
+                // which are not in `seen` yet
+            }
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+}
+
+/// Merges initializers holding the same data. Initializers which are
+/// also graph inputs or outputs and unloaded tensors are left alone.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InitializerDeduplication;
+
+impl Pass for InitializerDeduplication {
+    fn name(&self) -> &str {
+        "initializer-deduplication"
+    }
+
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error> {
+        let mut changes = vec![];
+        for_each_graph(&mut model.graph, "", &mut |graph, prefix| {
+            let interface: HashSet<&str> = graph
+                .inputs
+                .iter()
+                .map(|i| i.name.as_str())
+                .chain(graph.outputs.iter().map(|o| o.name.as_str()))
+                .collect();
+            // (dtype, shape) -> names of distinct initializers
+            let mut distinct: HashMap<_, Vec<&String>> = HashMap::new();
+            let mut duplicates = vec![];
+            for (name, tensor) in graph.initializers.iter() {
+                if interface.contains(name.as_str())
+                    || matches!(tensor.tensor, TensorValue::Unloaded { .. })
+                {
+                    continue;
+                }
+                let candidates = distinct
+                    .entry((tensor.tensor.dtype(), tensor.tensor.shape()))
+                    .or_default();
+                match candidates.iter().find(|other| {
+                    identical(&graph.initializers[other.as_str()].tensor, &tensor.tensor)
+                }) {
+                    Some(original) => duplicates.push((name.clone(), (*original).clone())),
+                    None => candidates.push(name),
+                }
+            }
+
+            for (duplicate, original) in duplicates {
+                if !graph.can_redirect(&duplicate, &original) {
+                    continue;
+                }
+                graph.initializers.shift_remove(&duplicate);
+                graph.edge_infos.shift_remove(&duplicate);
+                graph.redirect_edge(&duplicate, &original)?;
+                changes.push(Change::RemovedInitializer {
+                    name: format!("{}{}", prefix, duplicate),
+                });
+                changes.push(Change::Redirected {
+                    from: format!("{}{}", prefix, duplicate),
+                    to: format!("{}{}", prefix, original),
+                });
+            }
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+}
+
+/// Whether `a` and `b` hold bitwise identical data. Unlike `==`, this
+/// tells `0.0` and `-0.0` apart and matches NaNs with equal payloads.
+fn identical(a: &TensorValue, b: &TensorValue) -> bool {
+    fn bits<T, U: PartialEq>(a: &ArrayD<T>, b: &ArrayD<T>, f: impl Fn(&T) -> U) -> bool {
+        a.shape() == b.shape() && a.iter().zip(b.iter()).all(|(a, b)| f(a) == f(b))
+    }
+    match (a, b) {
+        (TensorValue::F32(a), TensorValue::F32(b)) => bits(a, b, |v| v.to_bits()),
+        (TensorValue::F64(a), TensorValue::F64(b)) => bits(a, b, |v| v.to_bits()),
+        (TensorValue::F16(a), TensorValue::F16(b)) => bits(a, b, |v| v.to_bits()),
+        (TensorValue::BF16(a), TensorValue::BF16(b)) => bits(a, b, |v| v.to_bits()),
+        (TensorValue::C64(a), TensorValue::C64(b)) => {
+            bits(a, b, |v| (v.re.to_bits(), v.im.to_bits()))
+        }
+        (TensorValue::C128(a), TensorValue::C128(b)) => {
+            bits(a, b, |v| (v.re.to_bits(), v.im.to_bits()))
+        }
+        // Integers, strings and raw bytes compare bitwise anyway
+        (a, b) => a == b,
+    }
+}
+
+/// Evaluates computations on tensor shapes such as
+/// `Shape`→`Gather`→`Concat` chains and replaces them by
+/// initializers. Only dimensions which are statically known from
+/// graph inputs, outputs, edge infos and initializers are used, so
+/// `Shape` of a tensor with dynamic dimensions can still feed a
+/// `Gather` of its fixed dimensions. Only the main graph is folded.
+///
+/// Supported are `Shape`, `Size`, `Gather`, `Concat`, `Unsqueeze`,
+/// `Squeeze`, `Slice`, `Cast` to `INT64` and `Constant` on `I64`
+/// tensors of rank 0 and 1.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShapeFolding;
+
+impl Pass for ShapeFolding {
+    fn name(&self) -> &str {
+        "shape-folding"
+    }
+
+    fn run(&self, model: &mut Model) -> Result<Vec<Change>, Error> {
+        let graph = &mut model.graph;
+        let shapes = static_shapes(graph);
+        let inputs: HashSet<&str> = graph.inputs.iter().map(|i| i.name.as_str()).collect();
+        let outputs: HashSet<&str> = graph.outputs.iter().map(|o| o.name.as_str()).collect();
+
+        let mut values: HashMap<&str, Ints> = graph
+            .initializers
+            .iter()
+            .filter(|(name, _)| !inputs.contains(name.as_str()))
+            .filter_map(|(name, tensor)| Some((name.as_str(), Ints::from_tensor(&tensor.tensor)?)))
+            .collect();
+        let order = GraphView::new(graph).topological_order()?;
+        let mut folded = vec![];
+        for idx in order {
+            let node = &graph.nodes[idx];
+            if !is_default_domain(&node.operation.domain) || node.outputs.len() != 1 {
+                continue;
+            }
+            let Some(value) = evaluate(node, &values, &shapes) else {
+                continue;
+            };
+            let output = node.outputs[0].as_str();
+            if value.known().is_some() && !is_op(node, "Constant") && !outputs.contains(output) {
+                folded.push(idx);
+            }
+            values.insert(output, value);
+        }
+
+        let mut initializers = vec![];
+        for idx in folded.iter() {
+            let output = graph.nodes[*idx].outputs[0].clone();
+            let tensor = values[output.as_str()].to_tensor().unwrap();
+            initializers.push((output, tensor));
+        }
+        folded.sort_unstable();
+        let mut changes = vec![];
+        for idx in folded.into_iter().rev() {
+            let node = graph.nodes.remove(idx);
+            changes.push(Change::Folded {
+                node: node_label("", &node),
+            });
+        }
+        changes.reverse();
+        for (name, tensor) in initializers {
+            graph.edge_infos.shift_remove(&name);
+            graph.initializers.insert(name, tensor);
+        }
+        Ok(changes)
+    }
+}
+
+/// `I64` tensor of rank 0 or 1 of which some elements may be unknown.
+#[derive(Clone, Debug, PartialEq)]
+struct Ints {
+    scalar: bool,
+    values: Vec<Option<i64>>,
+}
+
+impl Ints {
+    fn vector(values: Vec<Option<i64>>) -> Self {
+        Self {
+            scalar: false,
+            values,
+        }
+    }
+
+    fn from_tensor(tensor: &TensorValue) -> Option<Self> {
+        let TensorValue::I64(arr) = tensor else {
+            return None;
+        };
+        (arr.ndim() <= 1).then(|| Self {
+            scalar: arr.ndim() == 0,
+            values: arr.iter().map(|el| Some(*el)).collect(),
+        })
+    }
+
+    fn known(&self) -> Option<Vec<i64>> {
+        self.values.iter().copied().collect()
+    }
+
+    fn to_tensor(&self) -> Option<Tensor> {
+        let values = self.known()?;
+        let shape: &[usize] = if self.scalar { &[] } else { &[values.len()] };
+        Some(Tensor {
+            tensor: ndarray::ArrayD::from_shape_vec(shape, values).ok()?.into(),
+            path: None,
+        })
+    }
+}
+
+/// Shapes of all edges with a statically known rank.
+fn static_shapes(graph: &Graph) -> HashMap<&str, Vec<Dim>> {
+    let infos = graph
+        .inputs
+        .iter()
+        .map(|i| (i.name.as_str(), &i.info))
+        .chain(graph.outputs.iter().map(|o| (o.name.as_str(), &o.info)))
+        .chain(
+            graph
+                .edge_infos
+                .iter()
+                .map(|(name, info)| (name.as_str(), info)),
+        );
+    let mut shapes: HashMap<&str, Vec<Dim>> = infos
+        .filter_map(|(name, info)| match info {
+            ValueInfo::Tensor(t) => Some((name, t.shape.clone()?)),
+            _ => None,
+        })
+        .collect();
+    for (name, tensor) in graph.initializers.iter() {
+        shapes
+            .entry(name.as_str())
+            .or_insert_with(|| tensor.tensor.shape().into_iter().map(Dim::Fixed).collect());
+    }
+    shapes
+}
+
+fn evaluate(
+    node: &Node,
+    values: &HashMap<&str, Ints>,
+    shapes: &HashMap<&str, Vec<Dim>>,
+) -> Option<Ints> {
+    let input = |idx: usize| -> Option<&Ints> {
+        let name = node.inputs.get(idx)?;
+        values.get(name.as_str())
+    };
+    let known_input = |idx: usize| input(idx).and_then(Ints::known);
+    let int_attr = |name: &str| match node.attributes.get(name) {
+        Some(Attribute::I64(i)) => Some(*i),
+        _ => None,
+    };
+    let ints_attr = |name: &str| match node.attributes.get(name) {
+        Some(Attribute::I64s(ints)) => Some(ints.clone()),
+        _ => None,
+    };
+
+    match node.operation.name.as_str() {
+        "Constant" => match node.attributes.get("value") {
+            Some(Attribute::Tensor(t)) => Ints::from_tensor(&t.tensor),
+            _ => match (int_attr("value_int"), ints_attr("value_ints")) {
+                (Some(i), _) => Some(Ints {
+                    scalar: true,
+                    values: vec![Some(i)],
+                }),
+                (_, Some(ints)) => Some(Ints::vector(ints.into_iter().map(Some).collect())),
+                _ => None,
+            },
+        },
+        "Shape" => {
+            let dims = shapes.get(node.inputs.first()?.as_str())?;
+            let rank = dims.len() as i64;
+            let clamp = |i: i64| (if i < 0 { i + rank } else { i }).clamp(0, rank) as usize;
+            let start = clamp(int_attr("start").unwrap_or(0));
+            let end = clamp(int_attr("end").unwrap_or(rank));
+            let dims = dims.get(start..end.max(start))?;
+            Some(Ints::vector(
+                dims.iter()
+                    .map(|dim| match dim {
+                        Dim::Fixed(d) => i64::try_from(*d).ok(),
+                        _ => None,
+                    })
+                    .collect(),
+            ))
+        }
+        "Size" => {
+            let dims = shapes.get(node.inputs.first()?.as_str())?;
+            let size = dims.iter().try_fold(1i64, |acc, dim| match dim {
+                Dim::Fixed(d) => i64::try_from(*d).ok().and_then(|d| acc.checked_mul(d)),
+                _ => None,
+            });
+            Some(Ints {
+                scalar: true,
+                values: vec![size],
+            })
+        }
+        // To `INT64`
+        "Cast" if int_attr("to")? == 7 => input(0).cloned(),
+        "Gather" => {
+            let data = input(0)?;
+            let indices = input(1)?;
+            if data.scalar || int_attr("axis").unwrap_or(0) != 0 {
+                return None;
+            }
+            let len = data.values.len() as i64;
+            let values = indices
+                .known()?
+                .into_iter()
+                .map(|i| {
+                    let i = if i < 0 { i + len } else { i };
+                    data.values.get(usize::try_from(i).ok()?).copied()
+                })
+                .collect::<Option<_>>()?;
+            Some(Ints {
+                scalar: indices.scalar,
+                values,
+            })
+        }
+        "Concat" => {
+            if !matches!(int_attr("axis"), Some(0 | -1)) {
+                return None;
+            }
+            let mut values = vec![];
+            for idx in 0..node.inputs.len() {
+                let part = input(idx)?;
+                if part.scalar {
+                    return None;
+                }
+                values.extend(part.values.iter().copied());
+            }
+            Some(Ints::vector(values))
+        }
+        "Unsqueeze" => {
+            let data = input(0)?;
+            let axes = ints_attr("axes").or_else(|| known_input(1))?;
+            (data.scalar && matches!(axes.as_slice(), [0] | [-1]))
+                .then(|| Ints::vector(data.values.clone()))
+        }
+        "Squeeze" => {
+            let data = input(0)?;
+            let axes = ints_attr("axes").or_else(|| known_input(1));
+            let squeezable = matches!(axes.as_deref(), None | Some([0] | [-1]));
+            (!data.scalar && data.values.len() == 1 && squeezable).then(|| Ints {
+                scalar: true,
+                values: data.values.clone(),
+            })
+        }
+        "Slice" => {
+            let data = input(0)?;
+            let (starts, ends, axes, steps) = if node.inputs.len() > 1 {
+                let optional = |idx: usize| match node.inputs.get(idx).map(String::as_str) {
+                    None | Some("") => Some(None),
+                    Some(_) => known_input(idx).map(Some),
+                };
+                (known_input(1)?, known_input(2)?, optional(3)?, optional(4)?)
+            } else {
+                (
+                    ints_attr("starts")?,
+                    ints_attr("ends")?,
+                    ints_attr("axes"),
+                    None,
+                )
+            };
+            let single = |v: Vec<i64>| <[i64; 1]>::try_from(v).ok().map(|[el]| el);
+            let (start, end) = (single(starts)?, single(ends)?);
+            if data.scalar
+                || !matches!(axes.as_deref(), None | Some([0] | [-1]))
+                || !matches!(steps.as_deref(), None | Some([1]))
+            {
+                return None;
+            }
+            let len = data.values.len() as i64;
+            let clamp = |i: i64| (if i < 0 { i + len } else { i }).clamp(0, len) as usize;
+            let (start, end) = (clamp(start), clamp(end));
+            Some(Ints::vector(
+                data.values.get(start..end.max(start))?.to_vec(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn is_default_domain(domain: &str) -> bool {
+    domain.is_empty() || domain == "ai.onnx"
+}
+
+fn is_op(node: &Node, op: &str) -> bool {
+    node.operation.name == op && is_default_domain(&node.operation.domain)
+}
+
+pub(crate) fn node_label(prefix: &str, node: &Node) -> String {
+    match (node.name.as_str(), node.outputs.first()) {
+        ("", Some(output)) => format!("{}{}({})", prefix, node.operation.name, output),
+        ("", None) => format!("{}{}", prefix, node.operation.name),
+        (name, _) => format!("{}{}", prefix, name),
+    }
+}
+
+/// Call `f` on every subgraph, innermost first, and then on `graph`.
+fn for_each_graph(
+    graph: &mut Graph,
+    prefix: &str,
+    f: &mut dyn FnMut(&mut Graph, &str) -> Result<(), Error>,
+) -> Result<(), Error> {
+    for node in graph.nodes.iter_mut() {
+        let node_prefix = format!("{}{}/", prefix, node_label("", node));
+        for (attr_name, attr) in node.attributes.iter_mut() {
+            let graphs = match attr {
+                Attribute::Graph(g) => std::slice::from_mut(g),
+                Attribute::Graphs(gs) => gs.as_mut_slice(),
+                _ => continue,
+            };
+            for g in graphs {
+                for_each_graph(g, &format!("{}{}/", node_prefix, attr_name), f)?;
+            }
+        }
+    }
+    f(graph, prefix)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::GraphBuilder;
+    use crate::{Dtype, TensorInfo};
+
+    fn info() -> ValueInfo {
+        ValueInfo::Tensor(TensorInfo {
+            shape: None,
+            dtype: Dtype::F32,
+        })
+    }
+
+    fn run(pass: impl Pass, model: &mut Model) -> Vec<Change> {
+        let changes = pass.run(model).unwrap();
+        assert_eq!(model.check(), Ok(()));
+        changes
+    }
+
+    #[test]
+    fn test_dead_code_elimination() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let unused = b.initializer("unused", ndarray::arr1(&[1.0f32]).into_dyn());
+        let [captured] = b.node("Neg", "", &[&x], []);
+        let [dead] = b.node("Add", "", &[&x, &unused], []);
+        b.node::<1>("Relu", "", &[&dead], []);
+        let branch = Graph {
+            nodes: vec![Node {
+                name: "abs".into(),
+                inputs: vec![captured.name().into()],
+                outputs: vec!["y".into()],
+                operation: crate::Operation {
+                    name: "Abs".into(),
+                    domain: "".into(),
+                },
+                ..Default::default()
+            }],
+            outputs: vec![crate::Output {
+                name: "y".into(),
+                info: info(),
+                doc_string: None,
+                denotation: None,
+            }],
+            ..Default::default()
+        };
+        let [y] = b.node("Loop", "", &[], [("body", Attribute::Graph(branch))]);
+        b.output(&y, info());
+        let mut model = b.into_model().unwrap();
+        let node = |idx: usize| model.graph.nodes[idx].name.clone();
+        let (add, relu) = (node(1), node(2));
+
+        let changes = run(DeadCodeElimination, &mut model);
+        assert_eq!(
+            changes,
+            [
+                Change::RemovedNode { node: add },
+                Change::RemovedNode { node: relu },
+                Change::RemovedInitializer {
+                    name: "unused".into()
+                },
+            ]
+        );
+        let ops: Vec<_> = model
+            .graph
+            .nodes
+            .iter()
+            .map(|n| &n.operation.name)
+            .collect();
+        assert_eq!(ops, ["Neg", "Loop"]);
+        assert!(run(DeadCodeElimination, &mut model).is_empty());
+    }
+
+    #[test]
+    fn test_common_subexpression_elimination() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let [a] = b.node("Relu", "", &[&x], []);
+        let [a2] = b.node("Relu", "", &[&x], []);
+        let [c] = b.node("Add", "", &[&a, &a2], []);
+        let [c2] = b.node("Add", "", &[&a2, &a], []);
+        let [r] = b.node("RandomNormalLike", "", &[&x], []);
+        let [r2] = b.node("RandomNormalLike", "", &[&x], []);
+        let [d] = b.node("Sum", "", &[&c, &c2, &r, &r2], []);
+        b.output(&d, info());
+        let mut model = b.into_model().unwrap();
+
+        let changes = run(CommonSubexpressionElimination, &mut model);
+        assert_eq!(
+            changes[1],
+            Change::Redirected {
+                from: a2.name().into(),
+                to: a.name().into()
+            }
+        );
+        // Merging the `Relu`s made the `Add`s equal
+        assert_eq!(
+            changes[3],
+            Change::Redirected {
+                from: c2.name().into(),
+                to: c.name().into()
+            }
+        );
+        let ops: Vec<_> = model
+            .graph
+            .nodes
+            .iter()
+            .map(|n| &n.operation.name)
+            .collect();
+        assert_eq!(
+            ops,
+            ["Relu", "Add", "RandomNormalLike", "RandomNormalLike", "Sum"]
+        );
+        assert_eq!(model.graph.nodes[1].inputs, [a.name(), a.name()]);
+        assert!(run(CommonSubexpressionElimination, &mut model).is_empty());
+
+        // The first `Dropout` leaves out the mask used by the second
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let [y, _] = b.node("Dropout", "", &[&x], []);
+        let [y2, mask] = b.node("Dropout", "", &[&x], []);
+        let [z] = b.node("Where", "", &[&mask, &y, &y2], []);
+        b.output(&z, info());
+        let mut model = b.into_model().unwrap();
+        model.graph.nodes[0].outputs[1].clear();
+        assert!(run(CommonSubexpressionElimination, &mut model).is_empty());
+        assert_eq!(model.graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_shape_folding() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input(
+            "x",
+            Dtype::F32,
+            &[Dim::Dynamic("N".into()), Dim::Fixed(3), Dim::Fixed(4)],
+        );
+        let zero = b.initializer("zero", ndarray::arr0(0i64).into_dyn());
+        let one = b.initializer("one", ndarray::arr0(1i64).into_dyn());
+        let axes = b.initializer("axes", ndarray::arr1(&[0i64]).into_dyn());
+        let minus_one = b.initializer("minus_one", ndarray::arr1(&[-1i64]).into_dyn());
+        let [shape] = b.node("Shape", "", &[&x], []);
+        let [batch] = b.node("Gather", "", &[&shape, &zero], []);
+        let [channels] = b.node("Gather", "", &[&shape, &one], []);
+        let [channels] = b.node("Unsqueeze", "", &[&channels, &axes], []);
+        let [new_shape] = b.node(
+            "Concat",
+            "",
+            &[&channels, &minus_one],
+            [("axis", Attribute::I64(0))],
+        );
+        let [y] = b.node("Reshape", "", &[&x, &new_shape], []);
+        b.output(&y, info());
+        b.output(&batch, info());
+        let mut model = b.into_model().unwrap();
+
+        let changes = run(ShapeFolding, &mut model);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            model.graph.initializers[new_shape.name()].tensor,
+            ndarray::arr1(&[3i64, -1]).into_dyn().into()
+        );
+        run(DeadCodeElimination, &mut model);
+        let ops: Vec<_> = model
+            .graph
+            .nodes
+            .iter()
+            .map(|n| &n.operation.name)
+            .collect();
+        // The batch size is dynamic
+        assert_eq!(ops, ["Shape", "Gather", "Reshape"]);
+
+        // The number of elements overflows and is left unfolded
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let huge = Dim::Fixed(1 << 40);
+        let x = b.input("x", Dtype::F32, &[huge.clone(), huge]);
+        let [size] = b.node("Size", "", &[&x], []);
+        b.output(&size, info());
+        let mut model = b.into_model().unwrap();
+        assert!(run(ShapeFolding, &mut model).is_empty());
+    }
+
+    #[test]
+    fn test_initializer_deduplication() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let w = b.initializer("w", ndarray::arr1(&[1.0f32, 2.0]).into_dyn());
+        let w2 = b.initializer("w2", ndarray::arr1(&[1.0f32, 2.0]).into_dyn());
+        let w3 = b.initializer("w3", ndarray::arr1(&[1.0f64, 2.0]).into_dyn());
+        let [y] = b.node("Add", "", &[&x, &w], []);
+        let [y] = b.node("Add", "", &[&y, &w2], []);
+        let [y] = b.node("Add", "", &[&y, &w3], []);
+        b.output(&y, info());
+        let mut model = b.into_model().unwrap();
+
+        let changes = run(InitializerDeduplication, &mut model);
+        assert_eq!(
+            changes,
+            [
+                Change::RemovedInitializer { name: "w2".into() },
+                Change::Redirected {
+                    from: "w2".into(),
+                    to: "w".into()
+                },
+            ]
+        );
+        assert!(model.graph.initializers.keys().eq(["w", "w3"]));
+        assert_eq!(model.graph.nodes[1].inputs[1], "w");
+
+        // Signed zeros are equal but divide differently
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(1)]);
+        let a = b.initializer("a", ndarray::arr1(&[0.0f32]).into_dyn());
+        let n = b.initializer("b", ndarray::arr1(&[-0.0f32]).into_dyn());
+        let [y] = b.node("Div", "", &[&x, &a], []);
+        let [y] = b.node("Div", "", &[&y, &n], []);
+        b.output(&y, info());
+        let mut model = b.into_model().unwrap();
+        assert_eq!(run(InitializerDeduplication, &mut model), []);
+        assert!(model.graph.initializers.keys().eq(["a", "b"]));
+
+        // The branch capturing `w2` shadows `w`, which the checker
+        // rejects, but passes must still not change its meaning
+        let mut model: Model = r#"
+            <opset_import: ["" : 19]>
+            main (bool c, float[2] x) => (float[2] y)
+               <float[2] w = {1.0, 2.0}, float[2] w2 = {1.0, 2.0}>
+            {
+               a = Add (x, w)
+               y = If <then_branch = then () => (float[2] z) {
+                  w = Neg (a)
+                  z = Add (w, w2)
+               }, else_branch = else () => (float[2] z2) {
+                  z2 = Identity (a)
+               }> (c)
+            }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(InitializerDeduplication.run(&mut model).unwrap(), []);
+        assert!(model.graph.initializers.keys().eq(["w", "w2"]));
+    }
+}
@@ -0,0 +1,259 @@
+//! Tensors whose data is kept in its serialized form until needed.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::external_data::{is_external, resolve, visit_model, visit_model_tensors};
+use crate::protos::{tensor_proto::DataLocation, ModelProto, TensorProto};
+use crate::tensor::ExternalData;
+use crate::utils::{dims_to_shape, num_elements};
+use crate::{Dtype, Error, Model, Tensor, TensorValue};
+
+/// Undecoded tensor data in the little-endian layout of `raw_data`,
+/// either owned or backed by a memory map of an external data file.
+///
+/// Cloning is cheap as the data is shared.
+#[derive(Clone)]
+pub struct RawTensor {
+    dtype: Dtype,
+    shape: Vec<usize>,
+    data: RawData,
+}
+
+#[derive(Clone)]
+enum RawData {
+    Owned(Arc<Vec<u8>>),
+    Mapped { map: Arc<Mmap>, range: Range<usize> },
+}
+
+impl RawTensor {
+    pub fn dtype(&self) -> Dtype {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match &self.data {
+            RawData::Owned(data) => data,
+            RawData::Mapped { map, range } => &map[range.clone()],
+        }
+    }
+
+    /// Decode the data into one of the array variants of
+    /// [`TensorValue`].
+    pub fn decode(&self) -> Result<TensorValue, Error> {
+        let tp = TensorProto {
+            dims: self.shape.iter().map(|el| *el as i64).collect(),
+            data_type: self.dtype.into(),
+            raw_data: self.bytes().to_vec(),
+            ..Default::default()
+        };
+        Ok(Tensor::try_from(tp)?.tensor)
+    }
+}
+
+impl PartialEq for RawTensor {
+    fn eq(&self, other: &Self) -> bool {
+        self.dtype == other.dtype && self.shape == other.shape && self.bytes() == other.bytes()
+    }
+}
+
+impl fmt::Debug for RawTensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawTensor")
+            .field("dtype", &self.dtype)
+            .field("shape", &self.shape)
+            .field("bytes", &self.bytes().len())
+            .finish()
+    }
+}
+
+/// Tensors taken out of a `ModelProto` by [`detach`], each with its
+/// original external data reference.
+pub(crate) struct Detached(Vec<(RawTensor, Option<ExternalData>)>);
+
+/// Replace the data of every tensor stored in `raw_data` or in an
+/// external file by a placeholder which converts to
+/// [`TensorValue::Unloaded`]. Note that checksums of external files
+/// are not verified as that would require reading them entirely.
+pub(crate) fn detach(proto: &mut ModelProto, base_dir: &Path) -> Result<Detached, Error> {
+    let mut maps: HashMap<PathBuf, Arc<Mmap>> = HashMap::new();
+    let mut detached = vec![];
+    visit_model(proto, &mut |tp| {
+        // Tensors of unsupported types are left for the regular
+        // conversion to reject
+        let Ok(dtype) = Dtype::try_from(tp.data_type) else {
+            return Ok(());
+        };
+        let shape = dims_to_shape(&tp.dims)?;
+        if dtype == Dtype::String || num_elements(&shape)? == 0 {
+            return Ok(());
+        }
+
+        let (data, external_data) = if is_external(tp) {
+            let external_data: ExternalData = tp.external_data.clone().try_into()?;
+            let path = resolve(base_dir, &external_data.location)?;
+            let map = match maps.get(&path) {
+                Some(map) => map.clone(),
+                None => {
+                    let file = File::open(&path)?;
+                    // SAFETY: The file must not be modified while the
+                    // model is alive, as documented on
+                    // `Model::from_path_lazy`. `Model::save` replaces
+                    // files rather than writing to them in place.
+                    let map = Arc::new(unsafe { Mmap::map(&file)? });
+                    maps.entry(path.clone()).or_insert(map).clone()
+                }
+            };
+            let start = external_data.offset.unwrap_or_default();
+            let end = match external_data.length {
+                Some(length) => start.checked_add(length),
+                None => Some(map.len()),
+            };
+            let Some(end) = end.filter(|end| start <= *end && *end <= map.len()) else {
+                return Err(Error::new_validation(format!(
+                    "External data of tensor `{}` exceeds `{}`.",
+                    tp.name,
+                    path.display()
+                )));
+            };
+            let range = start..end;
+            (RawData::Mapped { map, range }, Some(external_data))
+        } else if !tp.raw_data.is_empty() {
+            (
+                RawData::Owned(Arc::new(std::mem::take(&mut tp.raw_data))),
+                None,
+            )
+        } else {
+            // Data in the typed fields is decoded right away
+            return Ok(());
+        };
+
+        tp.external_data = ExternalData::new(placeholder(detached.len())).into();
+        tp.data_location = DataLocation::EXTERNAL.into();
+        detached.push((RawTensor { dtype, shape, data }, external_data));
+        Ok(())
+    })?;
+    Ok(Detached(detached))
+}
+
+/// Put the tensors taken out by [`detach`] back into the converted
+/// `model`.
+pub(crate) fn attach(model: &mut Model, detached: Detached) -> Result<(), Error> {
+    visit_model_tensors(model, &mut |tensor| {
+        let index = match &tensor.path {
+            Some(ExternalData { location, .. }) => placeholder_index(location),
+            None => None,
+        };
+        if let Some((raw, external_data)) = index.and_then(|idx| detached.0.get(idx)) {
+            tensor.tensor = TensorValue::Raw(raw.clone());
+            tensor.path = external_data.clone();
+        }
+        Ok(())
+    })
+}
+
+/// Location referring to the `index`th detached tensor. The NUL byte
+/// cannot occur in actual paths.
+fn placeholder(index: usize) -> String {
+    format!("\0{}", index)
+}
+
+fn placeholder_index(location: &Path) -> Option<usize> {
+    location.to_str()?.strip_prefix('\0')?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ExternalDataPolicy, Graph};
+    use protobuf::Message;
+
+    #[test]
+    fn test_lazy_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.onnx");
+
+        let tensor = |value: TensorValue| Tensor {
+            tensor: value,
+            path: None,
+        };
+        let expected = Model {
+            graph: Graph {
+                initializers: [
+                    (
+                        "big".to_string(),
+                        tensor(ndarray::Array::range(0.0, 2048.0, 1.0).into_dyn().into()),
+                    ),
+                    (
+                        "small".to_string(),
+                        tensor(ndarray::arr1(&[1i8, -2]).into_dyn().into()),
+                    ),
+                ]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        expected
+            .save_with_external_data(&path, ExternalDataPolicy::default())
+            .unwrap();
+
+        let lazy = Model::from_path_lazy(&path).unwrap();
+        for (name, tensor) in lazy.graph.initializers.iter() {
+            let TensorValue::Raw(raw) = &tensor.tensor else {
+                panic!("`{}` was decoded", name);
+            };
+            assert_eq!(
+                raw.decode().unwrap(),
+                expected.graph.initializers[name].tensor
+            );
+        }
+        assert!(lazy.graph.initializers["big"].path.is_some());
+        assert!(lazy.graph.initializers["small"].path.is_none());
+
+        // Overwriting the mapped files keeps the lazy model intact
+        lazy.save(&path).unwrap();
+        lazy.save(&path).unwrap();
+        let candidate = Model::from_path(&path).unwrap();
+        for (name, tensor) in candidate.graph.initializers.iter() {
+            assert_eq!(tensor.tensor, expected.graph.initializers[name].tensor);
+        }
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.onnx");
+        std::fs::write(dir.path().join("data"), [0u8; 8]).unwrap();
+
+        for (offset, length) in [(usize::MAX, 4), (4, usize::MAX), (4, 8)] {
+            let tp = TensorProto {
+                name: "a".into(),
+                dims: vec![1],
+                data_type: Dtype::F32.into(),
+                data_location: DataLocation::EXTERNAL.into(),
+                external_data: ExternalData {
+                    offset: Some(offset),
+                    length: Some(length),
+                    ..ExternalData::new("data")
+                }
+                .into(),
+                ..Default::default()
+            };
+            let mut proto: ModelProto = Model::default().try_into().unwrap();
+            proto.graph.mut_or_insert_default().initializer.push(tp);
+            std::fs::write(&path, proto.write_to_bytes().unwrap()).unwrap();
+            assert!(Model::from_path_lazy(&path).is_err());
+            assert!(Model::from_path(&path).is_err());
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Rendering graphs as Graphviz DOT and Mermaid flowcharts.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::passes::node_label;
+use crate::text::{dtype_name, Type};
+use crate::utils::{byte_size, human_bytes, num_elements};
+use crate::{Attribute, Graph, Node, Tensor, ValueInfo};
+
+/// What [`Graph::to_dot_with`] and [`Graph::to_mermaid_with`] show
+/// besides the nodes and edges.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// Types of the graph inputs and outputs and of the edges where
+    /// known from `edge_infos`.
+    pub types: bool,
+    /// Type and size of the initializers consumed by a node, including
+    /// those captured from an outer graph.
+    pub initializers: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            types: true,
+            initializers: true,
+        }
+    }
+}
+
+/// Format independent description of a rendered graph.
+enum Item {
+    Vertex {
+        id: String,
+        lines: Vec<String>,
+        kind: Kind,
+    },
+    Edge {
+        from: String,
+        to: String,
+        label: Vec<String>,
+        /// Connects the outputs of a subgraph to its node.
+        dashed: bool,
+    },
+    ClusterStart {
+        id: String,
+        label: String,
+    },
+    ClusterEnd,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Input,
+    Output,
+    Operator,
+}
+
+/// Edge name -> id of the vertex producing it and its annotation.
+type Scope = HashMap<String, (String, Option<String>)>;
+
+/// Name -> description of the initializers visible in a graph.
+type Initializers = HashMap<String, String>;
+
+struct Collector<'a> {
+    items: Vec<Item>,
+    clusters: usize,
+    options: &'a RenderOptions,
+}
+
+impl Collector<'_> {
+    fn graph(
+        &mut self,
+        graph: &Graph,
+        prefix: &str,
+        label: &str,
+        outer: &Scope,
+        outer_initializers: &Initializers,
+    ) -> Vec<String> {
+        let mut scope = outer.clone();
+        let initializers = self.initializers(graph, outer_initializers);
+        let annotation = |name: &str| {
+            if !self.options.types {
+                return None;
+            }
+            graph
+                .inputs
+                .iter()
+                .find(|i| i.name == name)
+                .map(|i| &i.info)
+                .or_else(|| graph.edge_infos.get(name))
+                .or_else(|| {
+                    graph
+                        .outputs
+                        .iter()
+                        .find(|o| o.name == name)
+                        .map(|o| &o.info)
+                })
+                .map(|info| Type(info).to_string())
+        };
+
+        for (i, input) in graph.inputs.iter().enumerate() {
+            let id = format!("{}i{}", prefix, i);
+            self.items.push(Item::Vertex {
+                id: id.clone(),
+                lines: self.io_lines(&input.name, &input.info),
+                kind: Kind::Input,
+            });
+            scope.insert(input.name.clone(), (id, annotation(&input.name)));
+        }
+        for (n, node) in graph.nodes.iter().enumerate() {
+            for output in node.outputs.iter().filter(|o| !o.is_empty()) {
+                let id = format!("{}n{}", prefix, n);
+                scope.insert(output.clone(), (id, annotation(output)));
+            }
+        }
+
+        for (n, node) in graph.nodes.iter().enumerate() {
+            let id = format!("{}n{}", prefix, n);
+            self.items.push(Item::Vertex {
+                id: id.clone(),
+                lines: operator_lines(node, &initializers),
+                kind: Kind::Operator,
+            });
+            for input in node.inputs.iter() {
+                if let Some((from, annotation)) = scope.get(input) {
+                    let mut label = vec![input.clone()];
+                    label.extend(annotation.clone());
+                    self.items.push(Item::Edge {
+                        from: from.clone(),
+                        to: id.clone(),
+                        label,
+                        dashed: false,
+                    });
+                }
+            }
+            for (name, attr) in node.attributes.iter() {
+                let graphs = match attr {
+                    Attribute::Graph(g) => std::slice::from_ref(g),
+                    Attribute::Graphs(gs) => gs.as_slice(),
+                    _ => continue,
+                };
+                for subgraph in graphs {
+                    self.clusters += 1;
+                    let cluster = format!("c{}", self.clusters);
+                    let label = format!("{}/{}", node_label(label, node), name);
+                    self.items.push(Item::ClusterStart {
+                        id: cluster.clone(),
+                        label: label.clone(),
+                    });
+                    let outputs = self.graph(
+                        subgraph,
+                        &format!("{}_", cluster),
+                        &format!("{}/", label),
+                        &scope,
+                        &initializers,
+                    );
+                    self.items.push(Item::ClusterEnd);
+                    for output in outputs {
+                        self.items.push(Item::Edge {
+                            from: output,
+                            to: id.clone(),
+                            label: vec![],
+                            dashed: true,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut output_ids = vec![];
+        for (i, output) in graph.outputs.iter().enumerate() {
+            let id = format!("{}o{}", prefix, i);
+            self.items.push(Item::Vertex {
+                id: id.clone(),
+                lines: self.io_lines(&output.name, &output.info),
+                kind: Kind::Output,
+            });
+            if let Some((from, _)) = scope.get(&output.name) {
+                self.items.push(Item::Edge {
+                    from: from.clone(),
+                    to: id.clone(),
+                    label: vec![],
+                    dashed: false,
+                });
+            }
+            output_ids.push(id);
+        }
+        output_ids
+    }
+
+    /// The initializers of `graph` and those of the outer graphs
+    /// which are not shadowed by an edge of `graph`.
+    fn initializers(&self, graph: &Graph, outer: &Initializers) -> Initializers {
+        if !self.options.initializers {
+            return Initializers::new();
+        }
+        let mut initializers = outer.clone();
+        for (name, tensor) in graph.initializers.iter() {
+            initializers.insert(name.clone(), initializer(tensor));
+        }
+        for (name, sparse) in graph.sparse_initializers.iter() {
+            initializers.insert(name.clone(), format!("sparse {:?}", sparse.dims));
+        }
+        let defined = (graph.inputs.iter().map(|i| &i.name))
+            .chain(graph.nodes.iter().flat_map(|n| n.outputs.iter()));
+        for name in defined {
+            initializers.remove(name);
+        }
+        initializers
+    }
+
+    /// Name and, if enabled, type of a graph input or output.
+    fn io_lines(&self, name: &str, info: &ValueInfo) -> Vec<String> {
+        let mut lines = vec![name.to_string()];
+        if self.options.types {
+            lines.push(Type(info).to_string());
+        }
+        lines
+    }
+}
+
+/// Operator type, node name and the initializers consumed by `node`.
+fn operator_lines(node: &Node, initializers: &Initializers) -> Vec<String> {
+    let mut lines = vec![match node.operation.domain.as_str() {
+        "" => node.operation.name.clone(),
+        domain => format!("{}.{}", domain, node.operation.name),
+    }];
+    if !node.name.is_empty() {
+        lines.push(node.name.clone());
+    }
+    for input in node.inputs.iter() {
+        if let Some(description) = initializers.get(input) {
+            lines.push(format!("{}: {}", input, description));
+        }
+    }
+    lines
+}
+
+/// Type, shape and size of an initializer, e.g. `float[3,3] (36 B)`.
+/// The size is left out if the number of elements overflows.
+fn initializer(tensor: &Tensor) -> String {
+    let shape = tensor.tensor.shape();
+    let dims: Vec<_> = shape.iter().map(|d| d.to_string()).collect();
+    let dtype = tensor.tensor.dtype();
+    let ty = format!("{}[{}]", dtype_name(dtype), dims.join(","));
+    let size = num_elements(&shape)
+        .ok()
+        .and_then(|count| byte_size(dtype, count));
+    match size {
+        Some(bytes) => format!("{} ({})", ty, human_bytes(bytes)),
+        None => ty,
+    }
+}
+
+fn collect(graph: &Graph, options: &RenderOptions) -> Vec<Item> {
+    let mut collector = Collector {
+        items: vec![],
+        clusters: 0,
+        options,
+    };
+    collector.graph(graph, "", "", &Scope::new(), &Initializers::new());
+    collector.items
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('&', "#amp;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+impl Graph {
+    /// Render the graph in the Graphviz DOT language, e.g. for
+    /// `dot -Tsvg`.
+    ///
+    /// Edges are annotated with their types where known from the
+    /// graph inputs, outputs and `edge_infos`. Initializers are listed
+    /// with their size on the nodes consuming them. Subgraphs are
+    /// drawn as clusters whose outputs point to their node.
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&RenderOptions::default())
+    }
+
+    /// Like [`Graph::to_dot`] but only showing the annotations enabled
+    /// in `options`.
+    pub fn to_dot_with(&self, options: &RenderOptions) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape_dot(&self.name)).unwrap();
+        writeln!(dot, "  node [shape=box];").unwrap();
+        let mut depth = 1;
+        for item in collect(self, options) {
+            let indent = "  ".repeat(depth);
+            match item {
+                Item::Vertex { id, lines, kind } => {
+                    let shape = match kind {
+                        Kind::Input | Kind::Output => ", shape=ellipse",
+                        Kind::Operator => "",
+                    };
+                    let label: Vec<_> = lines.iter().map(|l| escape_dot(l)).collect();
+                    writeln!(
+                        dot,
+                        "{}{} [label=\"{}\"{}];",
+                        indent,
+                        id,
+                        label.join("\\n"),
+                        shape
+                    )
+                    .unwrap();
+                }
+                Item::Edge {
+                    from,
+                    to,
+                    label,
+                    dashed,
+                } => {
+                    let mut attrs = vec![];
+                    if !label.is_empty() {
+                        let label: Vec<_> = label.iter().map(|l| escape_dot(l)).collect();
+                        attrs.push(format!("label=\"{}\"", label.join("\\n")));
+                    }
+                    if dashed {
+                        attrs.push("style=dashed".to_string());
+                    }
+                    let attrs = match attrs.is_empty() {
+                        true => String::new(),
+                        false => format!(" [{}]", attrs.join(", ")),
+                    };
+                    writeln!(dot, "{}{} -> {}{};", indent, from, to, attrs).unwrap();
+                }
+                Item::ClusterStart { id, label } => {
+                    writeln!(dot, "{}subgraph cluster_{} {{", indent, id).unwrap();
+                    writeln!(dot, "{}  label=\"{}\";", indent, escape_dot(&label)).unwrap();
+                    depth += 1;
+                }
+                Item::ClusterEnd => {
+                    depth -= 1;
+                    writeln!(dot, "{}}}", "  ".repeat(depth)).unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as a Mermaid flowchart, e.g. for embedding in
+    /// Markdown. Contains the same information as [`Graph::to_dot`].
+    pub fn to_mermaid(&self) -> String {
+        self.to_mermaid_with(&RenderOptions::default())
+    }
+
+    /// Like [`Graph::to_mermaid`] but only showing the annotations
+    /// enabled in `options`.
+    pub fn to_mermaid_with(&self, options: &RenderOptions) -> String {
+        let mut mermaid = String::from("flowchart TD\n");
+        let mut depth = 1;
+        for item in collect(self, options) {
+            let indent = "  ".repeat(depth);
+            match item {
+                Item::Vertex { id, lines, kind } => {
+                    let label: Vec<_> = lines.iter().map(|l| escape_mermaid(l)).collect();
+                    let label = label.join("<br/>");
+                    let (open, close) = match kind {
+                        Kind::Input | Kind::Output => ("([", "])"),
+                        Kind::Operator => ("[", "]"),
+                    };
+                    writeln!(mermaid, "{}{}{}\"{}\"{}", indent, id, open, label, close).unwrap();
+                }
+                Item::Edge {
+                    from,
+                    to,
+                    label,
+                    dashed,
+                } => {
+                    let arrow = if dashed { "-.->" } else { "-->" };
+                    let label: Vec<_> = label.iter().map(|l| escape_mermaid(l)).collect();
+                    let label = match label.is_empty() {
+                        true => String::new(),
+                        false => format!("|\"{}\"|", label.join("<br/>")),
+                    };
+                    writeln!(mermaid, "{}{} {}{} {}", indent, from, arrow, label, to).unwrap();
+                }
+                Item::ClusterStart { id, label } => {
+                    writeln!(
+                        mermaid,
+                        "{}subgraph {} [\"{}\"]",
+                        indent,
+                        id,
+                        escape_mermaid(&label)
+                    )
+                    .unwrap();
+                    depth += 1;
+                }
+                Item::ClusterEnd => {
+                    depth -= 1;
+                    writeln!(mermaid, "{}end", "  ".repeat(depth)).unwrap();
+                }
+            }
+        }
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{initializer, RenderOptions};
+    use crate::{Dtype, Graph, Tensor, TensorValue};
+
+    fn graph() -> Graph {
+        r#"
+        main (bool c, float[N] x) => (float[N] y) <float[2] w = {1.0, 2.0}, float[N] a> {
+           a = Mul (x, w)
+           [cond] y = If <then_branch = then () => (float[N] z) {
+              z = Neg (a)
+           }> (c)
+        }
+        "#
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = graph().to_dot();
+        assert!(dot.starts_with("digraph \"main\" {"));
+        assert!(
+            dot.contains("n0 [label=\"Mul\\nw: float[2] (8 B)\"];"),
+            "{}",
+            dot
+        );
+        assert!(
+            dot.contains("i1 -> n0 [label=\"x\\nfloat[N]\"];"),
+            "{}",
+            dot
+        );
+        assert!(dot.contains("subgraph cluster_c1 {"), "{}", dot);
+        assert!(dot.contains("label=\"cond/then_branch\";"), "{}", dot);
+        // The subgraph captures `a`
+        assert!(
+            dot.contains("n0 -> c1_n0 [label=\"a\\nfloat[N]\"];"),
+            "{}",
+            dot
+        );
+        assert!(dot.contains("c1_o0 -> n1 [style=dashed];"), "{}", dot);
+        assert!(dot.contains("n1 -> o0;"), "{}", dot);
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        let mermaid = graph().to_mermaid();
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains("i0([\"c<br/>bool\"])"), "{}", mermaid);
+        assert!(
+            mermaid.contains("subgraph c1 [\"cond/then_branch\"]"),
+            "{}",
+            mermaid
+        );
+        assert!(mermaid.contains("c1_o0 -.-> n1"), "{}", mermaid);
+        assert!(
+            mermaid.contains("n0 -->|\"a<br/>float[N]\"| c1_n0"),
+            "{}",
+            mermaid
+        );
+    }
+
+    #[test]
+    fn test_render_options() {
+        let graph: Graph = r#"
+        main (bool c) => (float[2] y) <float[2] w = {1.0, 2.0}> {
+           [cond] y = If <then_branch = then () => (float[2] z) {
+              z = Neg (w)
+           }> (c)
+        }
+        "#
+        .parse()
+        .unwrap();
+        // `w` is captured by the subgraph
+        let dot = graph.to_dot();
+        assert!(
+            dot.contains("c1_n0 [label=\"Neg\\nw: float[2] (8 B)\"];"),
+            "{}",
+            dot
+        );
+
+        let options = RenderOptions {
+            types: false,
+            initializers: false,
+        };
+        let dot = graph.to_dot_with(&options);
+        assert!(dot.contains("c1_n0 [label=\"Neg\"];"), "{}", dot);
+        assert!(dot.contains("i0 [label=\"c\", shape=ellipse];"), "{}", dot);
+        let mermaid = self::graph().to_mermaid_with(&options);
+        assert!(mermaid.contains("n0 -->|\"a\"| c1_n0"), "{}", mermaid);
+        assert!(mermaid.contains("n0[\"Mul\"]"), "{}", mermaid);
+    }
+
+    #[test]
+    fn test_overflowing_initializer() {
+        let tensor = Tensor {
+            tensor: TensorValue::Unloaded {
+                dtype: Dtype::F32,
+                shape: vec![usize::MAX, 2],
+            },
+            path: None,
+        };
+        let expected = format!("float[{},2]", usize::MAX);
+        assert_eq!(initializer(&tensor), expected);
+    }
+}
@@ -0,0 +1,1599 @@
+//! Static type and shape inference for the common operators of the
+//! default domain.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::{infer_shapes, Dim, Dtype, TensorInfo, ValueInfo};
+//!
+//! let mut b = GraphBuilder::new("graph").opset("", 19);
+//! let x = b.input("x", Dtype::F32, &[Dim::Dynamic("N".into()), Dim::Fixed(4)]);
+//! let w = b.initializer("w", ndarray::Array2::<f32>::zeros((4, 8)).into_dyn());
+//! let [y] = b.node("MatMul", "", &[&x, &w], []);
+//! let [z] = b.node("Relu", "", &[&y], []);
+//! let mut model = b.into_model().unwrap();
+//!
+//! infer_shapes(&mut model).unwrap();
+//! assert_eq!(
+//!     model.graph.edge_infos[z.name()],
+//!     ValueInfo::Tensor(TensorInfo {
+//!         shape: Some(vec![Dim::Dynamic("N".into()), Dim::Fixed(8)]),
+//!         dtype: Dtype::F32,
+//!     })
+//! );
+//! ```
+use std::collections::HashMap;
+
+use crate::passes::node_label;
+use crate::{Attribute, Dim, Dtype, Error, Graph, GraphView, Model, Node, TensorInfo, TensorValue};
+use crate::{ValueInfo, ValueInfo::Tensor};
+
+/// Infer the types and shapes of the edges of `model` and record them
+/// in `Graph::edge_infos` of the main graph and all subgraphs.
+///
+/// Shapes are propagated through the elementwise operators, `MatMul`,
+/// `Gemm`, convolutions and pooling, reductions and the common shape
+/// manipulating operators. Integer tensors computed from initializers,
+/// `Constant`s and `Shape`s are tracked, so e.g. the target shape of a
+/// `Reshape` does not need to be an initializer. Dimensions which
+/// cannot be determined statically are [`Dim::Unknown`].
+///
+/// Outputs of unsupported operators keep the info declared in
+/// `edge_infos` or the graph outputs, which is then propagated like an
+/// inferred one. Without a declaration they get no info, and the
+/// operators consuming them get an info with an unknown shape wherever
+/// their output type can still be determined, e.g. from another input.
+///
+/// Declared infos of graph outputs and existing edge infos are refined
+/// with the inferred ones. A contradiction between the two, or between
+/// the inputs of a node, is reported as a validation error.
+pub fn infer_shapes(model: &mut Model) -> Result<(), Error> {
+    Scope::default().infer(&mut model.graph, "")
+}
+
+/// Element of a small integer tensor such as the target shape of a
+/// `Reshape`.
+#[derive(Clone, Debug, PartialEq)]
+enum Int {
+    Known(i64),
+    /// The value of a dynamic dimension.
+    Symbol(String),
+    Unknown,
+}
+
+impl From<&Dim> for Int {
+    fn from(dim: &Dim) -> Self {
+        match dim {
+            Dim::Fixed(v) => Int::Known(*v as i64),
+            Dim::Dynamic(name) => Int::Symbol(name.clone()),
+            Dim::Unknown => Int::Unknown,
+        }
+    }
+}
+
+impl Int {
+    fn known(&self) -> Option<i64> {
+        match self {
+            Int::Known(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn dim(&self) -> Dim {
+        match self {
+            Int::Known(v) if *v >= 0 => Dim::Fixed(*v as usize),
+            Int::Symbol(name) => Dim::Dynamic(name.clone()),
+            _ => Dim::Unknown,
+        }
+    }
+}
+
+/// Integer tensors with more elements are not tracked.
+const MAX_VALUES: usize = 64;
+
+/// Everything known about the edges visible in a graph.
+#[derive(Clone, Default)]
+struct Scope {
+    infos: HashMap<String, ValueInfo>,
+    values: HashMap<String, Vec<Int>>,
+}
+
+impl Scope {
+    fn infer(mut self, graph: &mut Graph, prefix: &str) -> Result<(), Error> {
+        for input in graph.inputs.iter() {
+            self.infos.insert(input.name.clone(), input.info.clone());
+        }
+        for (name, tensor) in graph.initializers.iter() {
+            // Initializers which are also inputs are mere defaults
+            if graph.inputs.iter().any(|i| i.name == *name) {
+                continue;
+            }
+            let info = TensorInfo {
+                shape: Some(tensor.tensor.shape().into_iter().map(Dim::Fixed).collect()),
+                dtype: tensor.tensor.dtype(),
+            };
+            self.infos.insert(name.clone(), Tensor(info));
+            if let Some(values) = int_values(&tensor.tensor) {
+                self.values.insert(name.clone(), values);
+            }
+        }
+        for (name, sparse) in graph.sparse_initializers.iter() {
+            let info = TensorInfo {
+                shape: Some(sparse.dims.iter().copied().map(Dim::Fixed).collect()),
+                dtype: sparse.values.tensor.dtype(),
+            };
+            self.infos.insert(name.clone(), Tensor(info));
+        }
+        for (name, info) in graph.edge_infos.iter() {
+            self.infos.insert(name.clone(), info.clone());
+        }
+        for output in graph.outputs.iter() {
+            self.infos
+                .entry(output.name.clone())
+                .or_insert_with(|| output.info.clone());
+        }
+
+        for idx in GraphView::new(graph).topological_order()? {
+            let node = &mut graph.nodes[idx];
+            let label = node_label(prefix, node);
+            for (attr_name, attr) in node.attributes.iter_mut() {
+                let graphs = match attr {
+                    Attribute::Graph(g) => std::slice::from_mut(g),
+                    Attribute::Graphs(gs) => gs.as_mut_slice(),
+                    _ => continue,
+                };
+                for g in graphs {
+                    let prefix = format!("{}/{}/", label, attr_name);
+                    self.clone().infer(g, &prefix)?;
+                }
+            }
+
+            let node = &graph.nodes[idx];
+            let invalid = |msg: String| Error::new_validation(format!("Node `{}`: {}", label, msg));
+            let mut outputs = self.node(node).map_err(invalid)?;
+            outputs.resize(node.outputs.len(), None);
+            self.propagate_values(node);
+            for (output, inferred) in node.outputs.iter().zip(outputs) {
+                let Some(inferred) = inferred.filter(|_| !output.is_empty()) else {
+                    continue;
+                };
+                let info = match self.infos.get(output) {
+                    Some(Tensor(declared)) => merge(declared, &inferred)
+                        .map_err(|msg| invalid(format!("Output `{}`: {}", output, msg)))?,
+                    Some(_) => continue,
+                    None => inferred,
+                };
+                self.infos.insert(output.clone(), Tensor(info));
+            }
+        }
+
+        for output in graph.outputs.iter_mut() {
+            if let (Tensor(declared), Some(Tensor(inferred))) =
+                (&output.info, self.infos.get(&output.name))
+            {
+                output.info = Tensor(merge(declared, inferred).map_err(|msg| {
+                    Error::new_validation(format!(
+                        "Graph output `{}{}`: {}",
+                        prefix, output.name, msg
+                    ))
+                })?);
+            }
+        }
+        let produced: Vec<String> = graph
+            .nodes
+            .iter()
+            .flat_map(|node| node.outputs.iter())
+            .filter(|o| !o.is_empty() && !graph.outputs.iter().any(|out| out.name == **o))
+            .cloned()
+            .collect();
+        for edge in produced {
+            if let Some(info) = self.infos.remove(&edge) {
+                graph.edge_infos.insert(edge, info);
+            }
+        }
+        Ok(())
+    }
+
+    fn tensor(&self, node: &Node, idx: usize) -> Option<&TensorInfo> {
+        match self.infos.get(node.inputs.get(idx)?)? {
+            Tensor(info) => Some(info),
+            _ => None,
+        }
+    }
+
+    fn dtype(&self, node: &Node, idx: usize) -> Option<Dtype> {
+        self.tensor(node, idx).map(|info| info.dtype)
+    }
+
+    fn shape(&self, node: &Node, idx: usize) -> Option<&[Dim]> {
+        self.tensor(node, idx)?.shape.as_deref()
+    }
+
+    fn ints(&self, node: &Node, idx: usize) -> Option<&[Int]> {
+        self.values.get(node.inputs.get(idx)?).map(Vec::as_slice)
+    }
+
+    /// Known values of the integer input `idx`, or of the attribute
+    /// `name` which older opsets use instead.
+    fn known_ints(&self, node: &Node, name: &str, idx: usize) -> Option<Vec<i64>> {
+        if let Some(values) = attr_i64s(node, name) {
+            return Some(values.to_vec());
+        }
+        self.ints(node, idx)?.iter().map(Int::known).collect()
+    }
+
+    /// Whether the optional input `idx` or attribute `name` is given.
+    fn has_input(&self, node: &Node, name: &str, idx: usize) -> bool {
+        node.attributes.contains_key(name) || node.inputs.get(idx).is_some_and(|i| !i.is_empty())
+    }
+
+    /// Infos of the outputs of `node`. Missing trailing entries stand
+    /// for unknown outputs.
+    fn node(&self, node: &Node) -> Result<Vec<Option<TensorInfo>>, String> {
+        if !matches!(node.operation.domain.as_str(), "" | "ai.onnx") {
+            return Ok(vec![]);
+        }
+        let dtype = |idx| self.dtype(node, idx);
+        let shape = |idx| self.shape(node, idx).map(<[Dim]>::to_vec);
+        let first = match node.operation.name.as_str() {
+            "Abs"
+            | "Acos"
+            | "Acosh"
+            | "Asin"
+            | "Asinh"
+            | "Atan"
+            | "Atanh"
+            | "BatchNormalization"
+            | "BitwiseNot"
+            | "Ceil"
+            | "Celu"
+            | "Clip"
+            | "Cos"
+            | "Cosh"
+            | "CumSum"
+            | "Elu"
+            | "Erf"
+            | "Exp"
+            | "Floor"
+            | "Gelu"
+            | "GroupNormalization"
+            | "HardSigmoid"
+            | "HardSwish"
+            | "Hardmax"
+            | "Identity"
+            | "InstanceNormalization"
+            | "LayerNormalization"
+            | "LeakyRelu"
+            | "Log"
+            | "LogSoftmax"
+            | "LpNormalization"
+            | "LRN"
+            | "MeanVarianceNormalization"
+            | "Mish"
+            | "Neg"
+            | "Reciprocal"
+            | "Relu"
+            | "ReverseSequence"
+            | "Round"
+            | "Scatter"
+            | "ScatterElements"
+            | "ScatterND"
+            | "Selu"
+            | "Shrink"
+            | "Sigmoid"
+            | "Sign"
+            | "Sin"
+            | "Sinh"
+            | "Softmax"
+            | "Softplus"
+            | "Softsign"
+            | "Sqrt"
+            | "Tan"
+            | "Tanh"
+            | "ThresholdedRelu"
+            | "Trilu" => info(dtype(0), shape(0)),
+            "Not" | "IsNaN" | "IsInf" => info(Some(Dtype::Bool), shape(0)),
+            "Cast" => info(attr_dtype(node, "to"), shape(0)),
+            "CastLike" => info(dtype(1), shape(0)),
+            "EyeLike" => info(attr_dtype(node, "dtype").or(dtype(0)), shape(0)),
+            "QuantizeLinear" => info(dtype(2).or(Some(Dtype::U8)), shape(0)),
+            "DequantizeLinear" => info(dtype(1), shape(0)),
+            "Add" | "Sub" | "Mul" | "Div" | "Mod" | "Max" | "Min" | "Sum" | "Mean" | "PRelu"
+            | "BitShift" | "BitwiseAnd" | "BitwiseOr" | "BitwiseXor" => {
+                let inputs: Vec<usize> = (0..node.inputs.len()).collect();
+                self.same_dtypes(node, &inputs)?;
+                info(
+                    self.any_dtype(node, &inputs),
+                    self.broadcast(node, &inputs)?,
+                )
+            }
+            "Pow" => info(dtype(0), self.broadcast(node, &[0, 1])?),
+            "Equal" | "Greater" | "GreaterOrEqual" | "Less" | "LessOrEqual" | "And" | "Or"
+            | "Xor" => {
+                self.same_dtypes(node, &[0, 1])?;
+                info(Some(Dtype::Bool), self.broadcast(node, &[0, 1])?)
+            }
+            "Where" => {
+                self.same_dtypes(node, &[1, 2])?;
+                info(
+                    self.any_dtype(node, &[1, 2]),
+                    self.broadcast(node, &[0, 1, 2])?,
+                )
+            }
+            "MatMul" => {
+                self.same_dtypes(node, &[0, 1])?;
+                let shape = match (self.shape(node, 0), self.shape(node, 1)) {
+                    (Some(a), Some(b)) => Some(matmul(a, b)?),
+                    _ => None,
+                };
+                info(self.any_dtype(node, &[0, 1]), shape)
+            }
+            "Gemm" => info(dtype(0), self.gemm(node)?),
+            "Conv" => info(dtype(0), self.conv(node)?),
+            "MaxPool" => {
+                let shape = self.pool(node);
+                return Ok(vec![
+                    info(dtype(0), shape.clone()),
+                    info(Some(Dtype::I64), shape),
+                ]);
+            }
+            "AveragePool" | "LpPool" => info(dtype(0), self.pool(node)),
+            "GlobalAveragePool" | "GlobalMaxPool" | "GlobalLpPool" => {
+                let shape = self.shape(node, 0).filter(|s| s.len() >= 2).map(|s| {
+                    let mut shape = s[..2].to_vec();
+                    shape.resize(s.len(), Dim::Fixed(1));
+                    shape
+                });
+                info(dtype(0), shape)
+            }
+            "Dropout" => {
+                return Ok(vec![
+                    info(dtype(0), shape(0)),
+                    info(Some(Dtype::Bool), shape(0)),
+                ])
+            }
+            "Reshape" => info(dtype(0), self.reshape(node)?),
+            "Flatten" => info(dtype(0), self.flatten(node)?),
+            "Squeeze" => info(dtype(0), self.squeeze(node)?),
+            "Unsqueeze" => info(dtype(0), self.unsqueeze(node)?),
+            "Concat" => info(dtype(0), self.concat(node)?),
+            "Transpose" => info(dtype(0), self.transpose(node)?),
+            "Gather" => info(dtype(0), self.gather(node)?),
+            "GatherElements" => info(dtype(0), shape(1)),
+            "Shape" => {
+                let len = self.shape(node, 0).map(|s| {
+                    let (start, end) = shape_range(node, s.len());
+                    end.saturating_sub(start)
+                });
+                info(
+                    Some(Dtype::I64),
+                    Some(vec![len.map_or(Dim::Unknown, Dim::Fixed)]),
+                )
+            }
+            "Size" => info(Some(Dtype::I64), Some(vec![])),
+            "ReduceSum" | "ReduceMean" | "ReduceMax" | "ReduceMin" | "ReduceProd" | "ReduceL1"
+            | "ReduceL2" | "ReduceLogSum" | "ReduceLogSumExp" | "ReduceSumSquare" => {
+                info(dtype(0), self.reduce(node)?)
+            }
+            "ArgMax" | "ArgMin" => {
+                let shape = match self.shape(node, 0) {
+                    Some(s) => {
+                        let axis = normalize(attr_i64(node, "axis").unwrap_or(0), s.len())?;
+                        Some(reduced(s, &[axis], attr_i64(node, "keepdims") != Some(0)))
+                    }
+                    None => None,
+                };
+                info(Some(Dtype::I64), shape)
+            }
+            "Constant" => constant_info(node),
+            "ConstantOfShape" => {
+                let dtype = match node.attributes.get("value") {
+                    Some(Attribute::Tensor(t)) => Some(t.tensor.dtype()),
+                    Some(_) => None,
+                    None => Some(Dtype::F32),
+                };
+                info(dtype, self.dims_from_values(node, 0))
+            }
+            "Expand" => {
+                let shape = match (self.shape(node, 0), self.dims_from_values(node, 1)) {
+                    (Some(input), Some(target)) => Some(broadcast(&[input, &target])?),
+                    _ => None,
+                };
+                info(dtype(0), shape)
+            }
+            "Slice" => info(dtype(0), self.slice(node)?),
+            "Tile" => {
+                let shape = self.shape(node, 0).map(|s| {
+                    let repeats = self.known_ints(node, "", 1);
+                    s.iter()
+                        .enumerate()
+                        .map(
+                            |(i, dim)| match (dim, repeats.as_ref().and_then(|r| r.get(i))) {
+                                (dim, Some(1)) => dim.clone(),
+                                (Dim::Fixed(d), Some(r)) => usize::try_from(*r)
+                                    .ok()
+                                    .and_then(|r| d.checked_mul(r))
+                                    .map_or(Dim::Unknown, Dim::Fixed),
+                                _ => Dim::Unknown,
+                            },
+                        )
+                        .collect()
+                });
+                info(dtype(0), shape)
+            }
+            "Pad" => info(dtype(0), self.pad(node)),
+            "Resize" => {
+                let shape = self.shape(node, 0).map(|s| {
+                    match self
+                        .ints(node, 3)
+                        .filter(|_| !node.attributes.contains_key("axes"))
+                    {
+                        Some(sizes) if sizes.len() == s.len() => {
+                            sizes.iter().map(Int::dim).collect()
+                        }
+                        _ => vec![Dim::Unknown; s.len()],
+                    }
+                });
+                info(dtype(0), shape)
+            }
+            "NonZero" => {
+                let rank = self
+                    .shape(node, 0)
+                    .map_or(Dim::Unknown, |s| Dim::Fixed(s.len()));
+                info(Some(Dtype::I64), Some(vec![rank, Dim::Unknown]))
+            }
+            "Range" => {
+                let len = match (0..3)
+                    .map(|idx| self.ints(node, idx)?.first()?.known())
+                    .collect::<Option<Vec<_>>>()
+                    .as_deref()
+                {
+                    Some([start, limit, delta]) if *delta != 0 => limit
+                        .checked_sub(*start)
+                        .and_then(|d| d.checked_add(delta - delta.signum()))
+                        .and_then(|d| d.checked_div(*delta))
+                        .map_or(Dim::Unknown, |len| Dim::Fixed(len.max(0) as usize)),
+                    _ => Dim::Unknown,
+                };
+                info(dtype(0), Some(vec![len]))
+            }
+            "Split" => return self.split(node),
+            "TopK" => {
+                let shape = match self.shape(node, 0) {
+                    Some(s) => {
+                        let axis = normalize(attr_i64(node, "axis").unwrap_or(-1), s.len())?;
+                        let k = self.ints(node, 1).and_then(|k| k.first()?.known());
+                        let mut shape = s.to_vec();
+                        shape[axis] = k
+                            .and_then(|k| usize::try_from(k).ok())
+                            .map_or(Dim::Unknown, Dim::Fixed);
+                        Some(shape)
+                    }
+                    None => None,
+                };
+                return Ok(vec![
+                    info(dtype(0), shape.clone()),
+                    info(Some(Dtype::I64), shape),
+                ]);
+            }
+            "If" => return branches(node),
+            "Loop" => return Ok(loop_outputs(node)),
+            _ => None,
+        };
+        Ok(vec![first])
+    }
+
+    /// Fail if the known types of the inputs `indices` differ.
+    fn same_dtypes(&self, node: &Node, indices: &[usize]) -> Result<(), String> {
+        let mut dtypes = indices.iter().filter_map(|idx| self.dtype(node, *idx));
+        if let Some(first) = dtypes.next() {
+            if let Some(other) = dtypes.find(|dtype| *dtype != first) {
+                return Err(format!(
+                    "Inputs of different types `{:?}` and `{:?}`",
+                    first, other
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The first known type of the inputs `indices`, which are
+    /// required to be of the same type.
+    fn any_dtype(&self, node: &Node, indices: &[usize]) -> Option<Dtype> {
+        indices.iter().find_map(|idx| self.dtype(node, *idx))
+    }
+
+    fn broadcast(&self, node: &Node, indices: &[usize]) -> Result<Option<Vec<Dim>>, String> {
+        let shapes: Option<Vec<&[Dim]>> = indices
+            .iter()
+            .filter(|idx| node.inputs.get(**idx).is_some_and(|i| !i.is_empty()))
+            .map(|idx| self.shape(node, *idx))
+            .collect();
+        shapes.map(|shapes| broadcast(&shapes)).transpose()
+    }
+
+    /// Dimensions given by the values of the 1-D input `idx`, or as
+    /// many unknown dimensions as it has elements.
+    fn dims_from_values(&self, node: &Node, idx: usize) -> Option<Vec<Dim>> {
+        if let Some(values) = self.ints(node, idx) {
+            return Some(values.iter().map(Int::dim).collect());
+        }
+        match self.shape(node, idx) {
+            Some([Dim::Fixed(rank)]) => Some(vec![Dim::Unknown; *rank]),
+            _ => None,
+        }
+    }
+
+    fn gemm(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let (Some(a), Some(b)) = (self.shape(node, 0), self.shape(node, 1)) else {
+            return Ok(None);
+        };
+        if a.len() != 2 || b.len() != 2 {
+            return Err("Inputs must be matrices".into());
+        }
+        let trans = |name| attr_i64(node, name).unwrap_or(0) != 0;
+        let (m, k1) = if trans("transA") {
+            (&a[1], &a[0])
+        } else {
+            (&a[0], &a[1])
+        };
+        let (k2, n) = if trans("transB") {
+            (&b[1], &b[0])
+        } else {
+            (&b[0], &b[1])
+        };
+        merge_dim(k1, k2).map_err(|_| format!("Inner dimensions {:?} and {:?} differ", k1, k2))?;
+        Ok(Some(vec![m.clone(), n.clone()]))
+    }
+
+    fn conv(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let (Some(x), Some(w)) = (self.shape(node, 0), self.shape(node, 1)) else {
+            return Ok(None);
+        };
+        if x.len() < 3 || x.len() != w.len() {
+            return Err(format!(
+                "Input of rank {} and weight of rank {} do not fit",
+                x.len(),
+                w.len()
+            ));
+        }
+        let group = attr_i64(node, "group").unwrap_or(1);
+        let group = usize::try_from(group)
+            .ok()
+            .filter(|g| *g > 0)
+            .ok_or_else(|| format!("Invalid number of groups {}", group))?;
+        if let (Dim::Fixed(c), Dim::Fixed(wc)) = (&x[1], &w[1]) {
+            if wc.checked_mul(group) != Some(*c) {
+                return Err(format!(
+                    "Input has {} channels, but the weight expects {} in each of {} groups",
+                    c, wc, group
+                ));
+            }
+        }
+        let kernel: Vec<Option<usize>> = match attr_i64s(node, "kernel_shape") {
+            Some(kernel) => kernel.iter().map(|k| usize::try_from(*k).ok()).collect(),
+            None => w[2..].iter().map(fixed).collect(),
+        };
+        let mut shape = vec![x[0].clone(), w[0].clone()];
+        shape.extend(spatial(node, &x[2..], &kernel, false));
+        Ok(Some(shape))
+    }
+
+    fn pool(&self, node: &Node) -> Option<Vec<Dim>> {
+        let x = self.shape(node, 0).filter(|x| x.len() >= 2)?;
+        let kernel: Vec<Option<usize>> = match attr_i64s(node, "kernel_shape") {
+            Some(kernel) => kernel.iter().map(|k| usize::try_from(*k).ok()).collect(),
+            None => vec![None; x.len() - 2],
+        };
+        let ceil_mode = attr_i64(node, "ceil_mode").unwrap_or(0) != 0;
+        let mut shape = x[..2].to_vec();
+        shape.extend(spatial(node, &x[2..], &kernel, ceil_mode));
+        Some(shape)
+    }
+
+    fn reshape(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let input = self.shape(node, 0);
+        let Some(target) = self.ints(node, 1) else {
+            return Ok(self.dims_from_values(node, 1));
+        };
+        let allowzero = attr_i64(node, "allowzero").unwrap_or(0) != 0;
+        let mut shape: Vec<Dim> = target
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match value {
+                Int::Known(0) if !allowzero => input
+                    .and_then(|s| s.get(i))
+                    .cloned()
+                    .unwrap_or(Dim::Unknown),
+                value => value.dim(),
+            })
+            .collect();
+        let total = input.and_then(product);
+        if let Some(pos) = target.iter().position(|v| *v == Int::Known(-1)) {
+            let rest = product(
+                shape
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != pos)
+                    .map(|(_, dim)| dim),
+            );
+            if let (Some(total), Some(rest)) = (total, rest) {
+                if rest == 0 || !total.is_multiple_of(rest) {
+                    return Err(format!(
+                        "Cannot reshape {} elements into {:?}",
+                        total, target
+                    ));
+                }
+                shape[pos] = Dim::Fixed(total / rest);
+            }
+        } else if let (Some(total), Some(new)) = (total, product(&shape)) {
+            if total != new {
+                return Err(format!(
+                    "Cannot reshape {} elements into {} elements",
+                    total, new
+                ));
+            }
+        }
+        Ok(Some(shape))
+    }
+
+    fn flatten(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(None);
+        };
+        let axis = attr_i64(node, "axis").unwrap_or(1);
+        let axis = if axis < 0 {
+            axis + s.len() as i64
+        } else {
+            axis
+        };
+        if axis < 0 || axis as usize > s.len() {
+            return Err(format!("Axis {} out of range for rank {}", axis, s.len()));
+        }
+        let (outer, inner) = s.split_at(axis as usize);
+        let dim = |dims: &[Dim]| {
+            let mut rest = dims.iter().filter(|d| **d != Dim::Fixed(1));
+            match (rest.next(), rest.next()) {
+                (Some(dim), None) => dim.clone(),
+                _ => product(dims).map_or(Dim::Unknown, Dim::Fixed),
+            }
+        };
+        Ok(Some(vec![dim(outer), dim(inner)]))
+    }
+
+    fn squeeze(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(None);
+        };
+        if !self.has_input(node, "axes", 1) {
+            if s.iter().any(|dim| fixed(dim).is_none()) {
+                return Ok(None);
+            }
+            return Ok(Some(
+                s.iter().filter(|d| **d != Dim::Fixed(1)).cloned().collect(),
+            ));
+        }
+        let Some(axes) = self.known_ints(node, "axes", 1) else {
+            return Ok(None);
+        };
+        let axes = normalize_all(&axes, s.len())?;
+        for axis in axes.iter() {
+            if let Dim::Fixed(d) = s[*axis] {
+                if d != 1 {
+                    return Err(format!("Cannot squeeze axis {} of size {}", axis, d));
+                }
+            }
+        }
+        Ok(Some(
+            s.iter()
+                .enumerate()
+                .filter(|(i, _)| !axes.contains(i))
+                .map(|(_, dim)| dim.clone())
+                .collect(),
+        ))
+    }
+
+    fn unsqueeze(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let (Some(s), Some(axes)) = (self.shape(node, 0), self.known_ints(node, "axes", 1)) else {
+            return Ok(None);
+        };
+        let rank = s.len() + axes.len();
+        let axes = normalize_all(&axes, rank)?;
+        let mut dims = s.iter();
+        Ok(Some(
+            (0..rank)
+                .map(|i| match axes.contains(&i) {
+                    true => Dim::Fixed(1),
+                    false => dims.next().cloned().unwrap_or(Dim::Unknown),
+                })
+                .collect(),
+        ))
+    }
+
+    fn concat(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let shapes: Option<Vec<&[Dim]>> = (0..node.inputs.len())
+            .map(|idx| self.shape(node, idx))
+            .collect();
+        let Some(shapes) = shapes.filter(|s| !s.is_empty()) else {
+            return Ok(None);
+        };
+        let rank = shapes[0].len();
+        if let Some(other) = shapes.iter().find(|s| s.len() != rank) {
+            return Err(format!(
+                "Inputs of different ranks {} and {}",
+                rank,
+                other.len()
+            ));
+        }
+        let axis = normalize(attr_i64(node, "axis").unwrap_or(0), rank)?;
+        let mut shape = shapes[0].to_vec();
+        for s in shapes[1..].iter() {
+            for (i, dim) in s.iter().enumerate().filter(|(i, _)| *i != axis) {
+                shape[i] = merge_dim(&shape[i], dim)
+                    .map_err(|msg| format!("Dimension {} of inputs: {}", i, msg))?;
+            }
+        }
+        shape[axis] = product_sum(shapes.iter().map(|s| &s[axis])).map_or(Dim::Unknown, Dim::Fixed);
+        Ok(Some(shape))
+    }
+
+    fn transpose(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(None);
+        };
+        let perm = match attr_i64s(node, "perm") {
+            Some(perm) => normalize_all(perm, s.len())?,
+            None => (0..s.len()).rev().collect(),
+        };
+        if perm.len() != s.len() {
+            return Err(format!(
+                "Permutation of length {} for rank {}",
+                perm.len(),
+                s.len()
+            ));
+        }
+        Ok(Some(perm.iter().map(|i| s[*i].clone()).collect()))
+    }
+
+    fn gather(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let (Some(data), Some(indices)) = (self.shape(node, 0), self.shape(node, 1)) else {
+            return Ok(None);
+        };
+        let axis = normalize(attr_i64(node, "axis").unwrap_or(0), data.len())?;
+        let mut shape = data[..axis].to_vec();
+        shape.extend(indices.iter().cloned());
+        shape.extend(data[axis + 1..].iter().cloned());
+        Ok(Some(shape))
+    }
+
+    fn reduce(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(None);
+        };
+        let keepdims = attr_i64(node, "keepdims").unwrap_or(1) != 0;
+        let axes = if self.has_input(node, "axes", 1) {
+            match self.known_ints(node, "axes", 1) {
+                Some(axes) => axes,
+                None if keepdims => return Ok(Some(vec![Dim::Unknown; s.len()])),
+                None => return Ok(None),
+            }
+        } else {
+            vec![]
+        };
+        let axes = match axes.is_empty() {
+            true if attr_i64(node, "noop_with_empty_axes").unwrap_or(0) != 0 => {
+                return Ok(Some(s.to_vec()))
+            }
+            true => (0..s.len()).collect(),
+            false => normalize_all(&axes, s.len())?,
+        };
+        Ok(Some(reduced(s, &axes, keepdims)))
+    }
+
+    fn slice(&self, node: &Node) -> Result<Option<Vec<Dim>>, String> {
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(None);
+        };
+        let starts = self.known_ints(node, "starts", 1);
+        let ends = self.known_ints(node, "ends", 2);
+        let axes = match self.has_input(node, "axes", 3) {
+            true => self.known_ints(node, "axes", 3),
+            false => starts
+                .as_ref()
+                .map(|starts| (0..starts.len() as i64).collect()),
+        };
+        let steps = match node.inputs.get(4).is_some_and(|i| !i.is_empty()) {
+            true => self.known_ints(node, "", 4),
+            false => starts.as_ref().map(|starts| vec![1; starts.len()]),
+        };
+        let (Some(starts), Some(ends), Some(axes), Some(steps)) = (starts, ends, axes, steps)
+        else {
+            return Ok(Some(vec![Dim::Unknown; s.len()]));
+        };
+        let mut shape = s.to_vec();
+        for (i, axis) in normalize_all(&axes, s.len())?.into_iter().enumerate() {
+            let (Some(start), Some(end), Some(step)) = (starts.get(i), ends.get(i), steps.get(i))
+            else {
+                return Err("Starts, ends, axes and steps differ in length".into());
+            };
+            shape[axis] = match &s[axis] {
+                Dim::Fixed(d) => {
+                    let d = i64::try_from(*d).map_err(|_| format!("Dimension {} too large", d))?;
+                    Dim::Fixed(slice_len(d, *start, *end, *step)?)
+                }
+                _ => Dim::Unknown,
+            };
+        }
+        Ok(Some(shape))
+    }
+
+    fn pad(&self, node: &Node) -> Option<Vec<Dim>> {
+        let s = self.shape(node, 0)?;
+        let pads = self
+            .known_ints(node, "pads", 1)
+            .filter(|pads| pads.len() == 2 * s.len() && !self.has_input(node, "", 3));
+        Some(
+            s.iter()
+                .enumerate()
+                .map(|(i, dim)| match (dim, &pads) {
+                    (Dim::Fixed(d), Some(pads)) => i64::try_from(*d)
+                        .ok()
+                        .and_then(|d| d.checked_add(pads[i])?.checked_add(pads[i + s.len()]))
+                        .map_or(Dim::Unknown, |d| Dim::Fixed(d.max(0) as usize)),
+                    _ => Dim::Unknown,
+                })
+                .collect(),
+        )
+    }
+
+    fn split(&self, node: &Node) -> Result<Vec<Option<TensorInfo>>, String> {
+        let dtype = self.dtype(node, 0);
+        let Some(s) = self.shape(node, 0) else {
+            return Ok(vec![info(dtype, None); node.outputs.len()]);
+        };
+        let axis = normalize(attr_i64(node, "axis").unwrap_or(0), s.len())?;
+        let n = node.outputs.len();
+        let sizes: Vec<Dim> = if self.has_input(node, "split", 1) {
+            match self.known_ints(node, "split", 1) {
+                Some(sizes) => sizes.iter().map(|v| Int::Known(*v).dim()).collect(),
+                None => vec![Dim::Unknown; n],
+            }
+        } else {
+            match s[axis] {
+                Dim::Fixed(d) => {
+                    let chunk = d.div_ceil(n.max(1));
+                    (0..n)
+                        .map(|i| Dim::Fixed(chunk.min(d.saturating_sub(i.saturating_mul(chunk)))))
+                        .collect()
+                }
+                _ => vec![Dim::Unknown; n],
+            }
+        };
+        if let (Dim::Fixed(d), Some(total)) = (&s[axis], product_sum(&sizes)) {
+            if *d != total {
+                return Err(format!("Split sizes add up to {} instead of {}", total, d));
+            }
+        }
+        Ok(sizes
+            .into_iter()
+            .map(|size| {
+                let mut shape = s.to_vec();
+                shape[axis] = size;
+                info(dtype, Some(shape))
+            })
+            .collect())
+    }
+
+    /// Track the values of small integer tensors.
+    fn propagate_values(&mut self, node: &Node) {
+        let Some(output) = node.outputs.first().filter(|o| !o.is_empty()) else {
+            return;
+        };
+        if !matches!(node.operation.domain.as_str(), "" | "ai.onnx") {
+            return;
+        }
+        let ints = |idx| self.ints(node, idx);
+        let values = match node.operation.name.as_str() {
+            "Constant" => match node.attributes.values().next() {
+                Some(Attribute::Tensor(t)) => int_values(&t.tensor),
+                Some(Attribute::I64(v)) => Some(vec![Int::Known(*v)]),
+                Some(Attribute::I64s(vs)) => Some(vs.iter().map(|v| Int::Known(*v)).collect()),
+                _ => None,
+            },
+            "Shape" => self.shape(node, 0).map(|s| {
+                let (start, end) = shape_range(node, s.len());
+                s[start..end.max(start)].iter().map(Int::from).collect()
+            }),
+            "Size" => self
+                .shape(node, 0)
+                .map(|s| vec![product(s).map_or(Int::Unknown, |n| Int::Known(n as i64))]),
+            "Identity" | "Squeeze" | "Unsqueeze" => ints(0).map(<[Int]>::to_vec),
+            "Cast" if matches!(attr_dtype(node, "to"), Some(Dtype::I64 | Dtype::I32)) => {
+                ints(0).map(<[Int]>::to_vec)
+            }
+            "Concat" => (0..node.inputs.len())
+                .map(ints)
+                .collect::<Option<Vec<_>>>()
+                .map(|parts| parts.concat()),
+            "Gather" if attr_i64(node, "axis").unwrap_or(0) == 0 => match (ints(0), ints(1)) {
+                (Some(data), Some(indices)) => indices
+                    .iter()
+                    .map(|idx| {
+                        let idx = idx.known()?;
+                        let idx = if idx < 0 {
+                            idx + data.len() as i64
+                        } else {
+                            idx
+                        };
+                        data.get(usize::try_from(idx).ok()?).cloned()
+                    })
+                    .collect(),
+                _ => None,
+            },
+            "Slice" => {
+                let data = ints(0);
+                let bounds = [1, 2].map(|idx| ints(idx).and_then(|v| v.first()?.known()));
+                let step = match node.inputs.get(4).is_some_and(|i| !i.is_empty()) {
+                    true => ints(4).and_then(|v| v.first()?.known()),
+                    false => Some(1),
+                };
+                match (data, bounds, step) {
+                    (Some(data), [Some(start), Some(end)], Some(step)) => {
+                        slice_indices(data.len() as i64, start, end, step)
+                            .ok()
+                            .map(|indices| indices.map(|idx| data[idx].clone()).collect())
+                    }
+                    _ => None,
+                }
+            }
+            op @ ("Add" | "Sub" | "Mul" | "Div") => match (ints(0), ints(1)) {
+                (Some(a), Some(b))
+                    if !a.is_empty()
+                        && !b.is_empty()
+                        && (a.len() == b.len() || a.len() == 1 || b.len() == 1) =>
+                {
+                    let len = a.len().max(b.len());
+                    let get = |v: &[Int], i: usize| v[if v.len() == 1 { 0 } else { i }].known();
+                    Some(
+                        (0..len)
+                            .map(|i| {
+                                match (get(a, i), get(b, i)) {
+                                    (Some(a), Some(b)) => arithmetic(op, a, b),
+                                    _ => None,
+                                }
+                                .map_or(Int::Unknown, Int::Known)
+                            })
+                            .collect(),
+                    )
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(values) = values.filter(|v| v.len() <= MAX_VALUES) {
+            self.values.insert(output.clone(), values);
+        }
+    }
+}
+
+fn info(dtype: Option<Dtype>, shape: Option<Vec<Dim>>) -> Option<TensorInfo> {
+    dtype.map(|dtype| TensorInfo { shape, dtype })
+}
+
+fn attr_i64(node: &Node, name: &str) -> Option<i64> {
+    match node.attributes.get(name) {
+        Some(Attribute::I64(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn attr_i64s<'a>(node: &'a Node, name: &str) -> Option<&'a [i64]> {
+    match node.attributes.get(name) {
+        Some(Attribute::I64s(v)) => Some(v),
+        _ => None,
+    }
+}
+
+fn attr_dtype(node: &Node, name: &str) -> Option<Dtype> {
+    Dtype::try_from(attr_i64(node, name)? as i32).ok()
+}
+
+fn fixed(dim: &Dim) -> Option<usize> {
+    match dim {
+        Dim::Fixed(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Product of the dimensions if all are fixed and it does not
+/// overflow.
+fn product<'a>(dims: impl IntoIterator<Item = &'a Dim>) -> Option<usize> {
+    dims.into_iter()
+        .try_fold(1usize, |acc, dim| acc.checked_mul(fixed(dim)?))
+}
+
+/// Sum of the dimensions if all are fixed and it does not overflow.
+fn product_sum<'a>(dims: impl IntoIterator<Item = &'a Dim>) -> Option<usize> {
+    dims.into_iter()
+        .try_fold(0usize, |acc, dim| acc.checked_add(fixed(dim)?))
+}
+
+fn normalize(axis: i64, rank: usize) -> Result<usize, String> {
+    let normalized = if axis < 0 { axis + rank as i64 } else { axis };
+    if normalized < 0 || normalized >= rank as i64 {
+        return Err(format!("Axis {} out of range for rank {}", axis, rank));
+    }
+    Ok(normalized as usize)
+}
+
+fn normalize_all(axes: &[i64], rank: usize) -> Result<Vec<usize>, String> {
+    axes.iter().map(|axis| normalize(*axis, rank)).collect()
+}
+
+/// The `start` and `end` attributes of a `Shape` node clamped to
+/// `rank`.
+fn shape_range(node: &Node, rank: usize) -> (usize, usize) {
+    let clamp = |v: i64| {
+        let v = if v < 0 { v + rank as i64 } else { v };
+        v.clamp(0, rank as i64) as usize
+    };
+    (
+        clamp(attr_i64(node, "start").unwrap_or(0)),
+        clamp(attr_i64(node, "end").unwrap_or(rank as i64)),
+    )
+}
+
+fn reduced(shape: &[Dim], axes: &[usize], keepdims: bool) -> Vec<Dim> {
+    shape
+        .iter()
+        .enumerate()
+        .filter_map(|(i, dim)| match (axes.contains(&i), keepdims) {
+            (false, _) => Some(dim.clone()),
+            (true, true) => Some(Dim::Fixed(1)),
+            (true, false) => None,
+        })
+        .collect()
+}
+
+/// `start` and `end` of slicing an axis of length `len`, clamped as
+/// specified by `Slice`.
+fn slice_bounds(len: i64, start: i64, end: i64, step: i64) -> (i64, i64) {
+    let resolve = |v: i64, lo: i64, hi: i64| {
+        let v = if v < 0 { v.saturating_add(len) } else { v };
+        v.clamp(lo, hi)
+    };
+    match step > 0 {
+        true => (resolve(start, 0, len), resolve(end, 0, len)),
+        false => (resolve(start, -1, len - 1), resolve(end, -1, len - 1)),
+    }
+}
+
+/// Number of elements selected by slicing an axis of length `len`.
+fn slice_len(len: i64, start: i64, end: i64, step: i64) -> Result<usize, String> {
+    if step == 0 {
+        return Err("Slice step must not be zero".into());
+    }
+    let (start, end) = slice_bounds(len, start, end, step);
+    // Both bounds lie within `-1..=len`, so neither the distance nor
+    // its absolute value overflow
+    let distance = if step > 0 { end - start } else { start - end };
+    Ok(match distance > 0 {
+        true => ((distance as u64 - 1) / step.unsigned_abs() + 1) as usize,
+        false => 0,
+    })
+}
+
+/// Indices selected by slicing an axis of length `len`.
+fn slice_indices(
+    len: i64,
+    start: i64,
+    end: i64,
+    step: i64,
+) -> Result<impl Iterator<Item = usize>, String> {
+    let count = slice_len(len, start, end, step)?;
+    let (start, _) = slice_bounds(len, start, end, step);
+    // All selected indices lie within `0..len`
+    Ok((0..count).map(move |i| (start as i128 + i as i128 * step as i128) as usize))
+}
+
+fn arithmetic(op: &str, a: i64, b: i64) -> Option<i64> {
+    match op {
+        "Add" => a.checked_add(b),
+        "Sub" => a.checked_sub(b),
+        "Mul" => a.checked_mul(b),
+        _ => a.checked_div(b),
+    }
+}
+
+fn broadcast(shapes: &[&[Dim]]) -> Result<Vec<Dim>, String> {
+    let rank = shapes.iter().map(|s| s.len()).max().unwrap_or(0);
+    (0..rank)
+        .map(|i| {
+            let dims: Vec<&Dim> = shapes
+                .iter()
+                .filter(|s| s.len() + i >= rank)
+                .map(|s| &s[s.len() + i - rank])
+                .collect();
+            let mut result = None;
+            for dim in dims.iter().filter(|d| ***d != Dim::Fixed(1)) {
+                result = match (result, dim) {
+                    (None, dim) => Some((*dim).clone()),
+                    (Some(Dim::Fixed(a)), Dim::Fixed(b)) if a != *b => {
+                        return Err(format!("Cannot broadcast dimensions {} and {}", a, b))
+                    }
+                    (Some(Dim::Fixed(a)), _) => Some(Dim::Fixed(a)),
+                    (Some(_), Dim::Fixed(b)) => Some(Dim::Fixed(*b)),
+                    (Some(a), b) if a == **b => Some(a),
+                    (Some(_), _) => Some(Dim::Unknown),
+                };
+            }
+            Ok(result.unwrap_or(Dim::Fixed(1)))
+        })
+        .collect()
+}
+
+fn matmul(a: &[Dim], b: &[Dim]) -> Result<Vec<Dim>, String> {
+    if a.is_empty() || b.is_empty() {
+        return Err("Inputs must not be scalars".into());
+    }
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    let (a_vector, b_vector) = (a.len() == 1, b.len() == 1);
+    if a_vector {
+        a.insert(0, Dim::Fixed(1));
+    }
+    if b_vector {
+        b.push(Dim::Fixed(1));
+    }
+    let (k1, k2) = (&a[a.len() - 1], &b[b.len() - 2]);
+    merge_dim(k1, k2).map_err(|_| format!("Inner dimensions {:?} and {:?} differ", k1, k2))?;
+    let mut shape = broadcast(&[&a[..a.len() - 2], &b[..b.len() - 2]])?;
+    if !a_vector {
+        shape.push(a[a.len() - 2].clone());
+    }
+    if !b_vector {
+        shape.push(b[b.len() - 1].clone());
+    }
+    Ok(shape)
+}
+
+/// Spatial output dimensions of a convolution or pooling.
+fn spatial(node: &Node, dims: &[Dim], kernel: &[Option<usize>], ceil_mode: bool) -> Vec<Dim> {
+    let n = dims.len();
+    let get = |name, idx: usize, default: i64| {
+        let value = attr_i64s(node, name)
+            .and_then(|v| v.get(idx).copied())
+            .unwrap_or(default);
+        usize::try_from(value).ok()
+    };
+    let auto_pad = match node.attributes.get("auto_pad") {
+        Some(Attribute::String(s)) => s.as_str(),
+        _ => "NOTSET",
+    };
+    dims.iter()
+        .enumerate()
+        .map(|(i, dim)| {
+            let (Some(stride), Some(dilation)) = (get("strides", i, 1), get("dilations", i, 1))
+            else {
+                return Dim::Unknown;
+            };
+            let stride = stride.max(1);
+            match (auto_pad, dim, kernel.get(i).copied().flatten()) {
+                ("SAME_UPPER" | "SAME_LOWER", Dim::Fixed(d), _) => Dim::Fixed(d.div_ceil(stride)),
+                ("SAME_UPPER" | "SAME_LOWER", dim, _) if stride == 1 => dim.clone(),
+                (_, Dim::Fixed(d), Some(k)) => {
+                    let pad = match auto_pad {
+                        "VALID" => Some(0),
+                        _ => get("pads", i, 0)
+                            .zip(get("pads", i + n, 0))
+                            .and_then(|(begin, end)| begin.checked_add(end)),
+                    };
+                    let span = dilation
+                        .checked_mul(k.max(1) - 1)
+                        .and_then(|span| span.checked_add(1));
+                    let rest = pad
+                        .zip(span)
+                        .and_then(|(pad, span)| d.checked_add(pad)?.checked_sub(span));
+                    match rest {
+                        Some(rest) if ceil_mode => Dim::Fixed(rest.div_ceil(stride) + 1),
+                        Some(rest) => Dim::Fixed(rest / stride + 1),
+                        None => Dim::Unknown,
+                    }
+                }
+                _ => Dim::Unknown,
+            }
+        })
+        .collect()
+}
+
+/// The more specific of two dimensions of the same size.
+fn merge_dim(a: &Dim, b: &Dim) -> Result<Dim, String> {
+    Ok(match (a, b) {
+        (Dim::Fixed(x), Dim::Fixed(y)) if x != y => {
+            return Err(format!("Dimensions {} and {} differ", x, y))
+        }
+        (Dim::Fixed(_), _) | (Dim::Dynamic(_), Dim::Unknown) => a.clone(),
+        (Dim::Unknown, _) | (_, Dim::Fixed(_)) => b.clone(),
+        (Dim::Dynamic(_), Dim::Dynamic(_)) => a.clone(),
+    })
+}
+
+/// Refine a `declared` info with an `inferred` one.
+fn merge(declared: &TensorInfo, inferred: &TensorInfo) -> Result<TensorInfo, String> {
+    if declared.dtype != inferred.dtype {
+        return Err(format!(
+            "Declared as `{:?}` but inferred as `{:?}`",
+            declared.dtype, inferred.dtype
+        ));
+    }
+    let shape = match (&declared.shape, &inferred.shape) {
+        (Some(d), Some(i)) if d.len() != i.len() => {
+            return Err(format!(
+                "Declared with rank {} but inferred with rank {}",
+                d.len(),
+                i.len()
+            ))
+        }
+        (Some(d), Some(i)) => Some(
+            d.iter()
+                .zip(i)
+                .map(|(d, i)| merge_dim(d, i))
+                .collect::<Result<_, _>>()?,
+        ),
+        (d, i) => d.clone().or(i.clone()),
+    };
+    Ok(TensorInfo {
+        shape,
+        dtype: declared.dtype,
+    })
+}
+
+fn constant_info(node: &Node) -> Option<TensorInfo> {
+    let (dtype, shape) = match node.attributes.values().next()? {
+        Attribute::Tensor(t) => (t.tensor.dtype(), t.tensor.shape()),
+        Attribute::SparseTensor(t) => (t.values.tensor.dtype(), t.dims.clone()),
+        Attribute::F32(_) => (Dtype::F32, vec![]),
+        Attribute::F32s(v) => (Dtype::F32, vec![v.len()]),
+        Attribute::I64(_) => (Dtype::I64, vec![]),
+        Attribute::I64s(v) => (Dtype::I64, vec![v.len()]),
+        Attribute::String(_) => (Dtype::String, vec![]),
+        Attribute::Strings(v) => (Dtype::String, vec![v.len()]),
+        _ => return None,
+    };
+    info(
+        Some(dtype),
+        Some(shape.into_iter().map(Dim::Fixed).collect()),
+    )
+}
+
+fn int_values(tensor: &TensorValue) -> Option<Vec<Int>> {
+    let values: Vec<i64> = match tensor {
+        TensorValue::I64(arr) if arr.ndim() <= 1 => arr.iter().copied().collect(),
+        TensorValue::I32(arr) if arr.ndim() <= 1 => arr.iter().map(|v| *v as i64).collect(),
+        TensorValue::Raw(raw)
+            if raw.shape().len() <= 1 && matches!(raw.dtype(), Dtype::I64 | Dtype::I32) =>
+        {
+            return int_values(&raw.decode().ok()?)
+        }
+        _ => return None,
+    };
+    (values.len() <= MAX_VALUES).then(|| values.into_iter().map(Int::Known).collect())
+}
+
+fn subgraph<'a>(node: &'a Node, name: &str) -> Option<&'a Graph> {
+    match node.attributes.get(name) {
+        Some(Attribute::Graph(g)) => Some(g),
+        _ => None,
+    }
+}
+
+fn tensor_output(graph: &Graph, idx: usize) -> Option<&TensorInfo> {
+    match &graph.outputs.get(idx)?.info {
+        Tensor(info) => Some(info),
+        _ => None,
+    }
+}
+
+/// Outputs of an `If` node, which agree with both branches.
+fn branches(node: &Node) -> Result<Vec<Option<TensorInfo>>, String> {
+    let (Some(then), Some(otherwise)) =
+        (subgraph(node, "then_branch"), subgraph(node, "else_branch"))
+    else {
+        return Ok(vec![]);
+    };
+    (0..node.outputs.len())
+        .map(|idx| {
+            let (Some(a), Some(b)) = (tensor_output(then, idx), tensor_output(otherwise, idx))
+            else {
+                return Ok(None);
+            };
+            if a.dtype != b.dtype {
+                return Err(format!(
+                    "Output {} is `{:?}` in one branch and `{:?}` in the other",
+                    idx, a.dtype, b.dtype
+                ));
+            }
+            let shape = match (&a.shape, &b.shape) {
+                (Some(a), Some(b)) if a.len() == b.len() => Some(
+                    a.iter()
+                        .zip(b)
+                        .map(|(a, b)| if a == b { a.clone() } else { Dim::Unknown })
+                        .collect(),
+                ),
+                _ => None,
+            };
+            Ok(info(Some(a.dtype), shape))
+        })
+        .collect()
+}
+
+/// Outputs of a `Loop` node: the final loop carried values followed by
+/// the scan outputs stacked along a new first axis.
+fn loop_outputs(node: &Node) -> Vec<Option<TensorInfo>> {
+    let Some(body) = subgraph(node, "body") else {
+        return vec![];
+    };
+    let carried = node.inputs.len().saturating_sub(2);
+    (0..node.outputs.len())
+        .map(|idx| {
+            let mut info = tensor_output(body, idx + 1)?.clone();
+            if idx >= carried {
+                if let Some(shape) = info.shape.as_mut() {
+                    shape.insert(0, Dim::Unknown);
+                }
+            }
+            Some(info)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use super::*;
+    use crate::builder::GraphBuilder;
+
+    fn dynamic(name: &str) -> Dim {
+        Dim::Dynamic(name.to_string())
+    }
+
+    fn tensor(dtype: Dtype, shape: Vec<Dim>) -> ValueInfo {
+        Tensor(TensorInfo {
+            shape: Some(shape),
+            dtype,
+        })
+    }
+
+    #[test]
+    fn test_elementwise_and_matmul() {
+        let mut b = GraphBuilder::new("graph").opset("", 19).opset("custom", 1);
+        let x = b.input(
+            "x",
+            Dtype::F32,
+            &[dynamic("N"), Dim::Fixed(1), Dim::Fixed(4)],
+        );
+        let y = b.input("y", Dtype::F32, &[Dim::Fixed(3), Dim::Fixed(1)]);
+        let w = b.initializer(
+            "w",
+            array![[1.0f32, 2.0], [3.0, 4.0], [5.0, 6.0], [7.0, 8.0]].into_dyn(),
+        );
+        let [sum] = b.node("Add", "", &[&x, &y], []);
+        let [prod] = b.node("MatMul", "", &[&sum, &w], []);
+        let [mask] = b.node("Greater", "", &[&prod, &prod], []);
+        let [unknown] = b.node("Foo", "custom", &[&prod], []);
+        let [after] = b.node("Relu", "", &[&unknown], []);
+        let [declared] = b.node("Foo", "custom", &[&prod], []);
+        let [relu] = b.node("Relu", "", &[&declared], []);
+        let [mixed] = b.node("Add", "", &[&unknown, &prod], []);
+        let [cast] = b.node("Cast", "", &[&unknown], [("to", Attribute::I64(7))]);
+        let mut model = b.into_model().unwrap();
+        let declared_info = tensor(Dtype::F32, vec![dynamic("N"), Dim::Unknown]);
+        (model.graph.edge_infos).insert(declared.name().to_string(), declared_info.clone());
+
+        infer_shapes(&mut model).unwrap();
+        let infos = &model.graph.edge_infos;
+        assert_eq!(
+            infos[sum.name()],
+            tensor(Dtype::F32, vec![dynamic("N"), Dim::Fixed(3), Dim::Fixed(4)])
+        );
+        assert_eq!(
+            infos[prod.name()],
+            tensor(Dtype::F32, vec![dynamic("N"), Dim::Fixed(3), Dim::Fixed(2)])
+        );
+        assert_eq!(
+            infos[mask.name()],
+            tensor(
+                Dtype::Bool,
+                vec![dynamic("N"), Dim::Fixed(3), Dim::Fixed(2)]
+            )
+        );
+        // Declared outputs of unknown operators are propagated
+        assert_eq!(infos[declared.name()], declared_info);
+        assert_eq!(infos[relu.name()], declared_info);
+        // Otherwise only the types which do not depend on them are
+        assert!(!infos.contains_key(unknown.name()));
+        assert!(!infos.contains_key(after.name()));
+        let unranked = |dtype| Tensor(TensorInfo { shape: None, dtype });
+        assert_eq!(infos[mixed.name()], unranked(Dtype::F32));
+        assert_eq!(infos[cast.name()], unranked(Dtype::I64));
+    }
+
+    #[test]
+    fn test_reshape_from_shape() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input(
+            "x",
+            Dtype::F32,
+            &[dynamic("N"), Dim::Fixed(3), Dim::Fixed(4)],
+        );
+        let [shape] = b.node("Shape", "", &[&x], [("end", Attribute::I64(1))]);
+        let minus_one = b.initializer("minus_one", array![-1i64].into_dyn());
+        let [target] = b.node(
+            "Concat",
+            "",
+            &[&shape, &minus_one],
+            [("axis", Attribute::I64(0))],
+        );
+        let [flat] = b.node("Reshape", "", &[&x, &target], []);
+        let perm = Attribute::I64s(vec![1, 0]);
+        let [t] = b.node("Transpose", "", &[&flat], [("perm", perm)]);
+        let axes = b.initializer("axes", array![1i64].into_dyn());
+        let [sum] = b.node(
+            "ReduceSum",
+            "",
+            &[&t, &axes],
+            [("keepdims", Attribute::I64(0))],
+        );
+        b.output(&sum, tensor(Dtype::F32, vec![Dim::Unknown]));
+        let mut model = b.into_model().unwrap();
+
+        infer_shapes(&mut model).unwrap();
+        let infos = &model.graph.edge_infos;
+        assert_eq!(infos[shape.name()], tensor(Dtype::I64, vec![Dim::Fixed(1)]));
+        assert_eq!(
+            infos[flat.name()],
+            tensor(Dtype::F32, vec![dynamic("N"), Dim::Unknown])
+        );
+        assert_eq!(
+            infos[t.name()],
+            tensor(Dtype::F32, vec![Dim::Unknown, dynamic("N")])
+        );
+        assert!(!infos.contains_key(sum.name()));
+        assert_eq!(
+            model.graph.outputs[0].info,
+            tensor(Dtype::F32, vec![Dim::Unknown])
+        );
+    }
+
+    #[test]
+    fn test_conv_and_pool() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input(
+            "x",
+            Dtype::F32,
+            &[dynamic("N"), Dim::Fixed(3), Dim::Fixed(32), Dim::Fixed(32)],
+        );
+        let w = b.input(
+            "w",
+            Dtype::F32,
+            &[Dim::Fixed(8), Dim::Fixed(3), Dim::Fixed(3), Dim::Fixed(3)],
+        );
+        let [conv] = b.node(
+            "Conv",
+            "",
+            &[&x, &w],
+            [
+                ("pads", Attribute::I64s(vec![1, 1, 1, 1])),
+                ("strides", Attribute::I64s(vec![2, 2])),
+            ],
+        );
+        let [pool] = b.node(
+            "MaxPool",
+            "",
+            &[&conv],
+            [("kernel_shape", Attribute::I64s(vec![2, 2]))],
+        );
+        let [flat] = b.node("Flatten", "", &[&pool], []);
+        let mut model = b.into_model().unwrap();
+
+        infer_shapes(&mut model).unwrap();
+        let infos = &model.graph.edge_infos;
+        let shape = |edge: &str| match &infos[edge] {
+            Tensor(info) => info.shape.clone().unwrap(),
+            _ => unreachable!(),
+        };
+        let fixed = |dims: &[usize]| dims.iter().copied().map(Dim::Fixed).collect::<Vec<_>>();
+        assert_eq!(shape(conv.name())[1..], fixed(&[8, 16, 16]));
+        assert_eq!(shape(pool.name())[1..], fixed(&[8, 15, 15]));
+        assert_eq!(shape(flat.name()), [dynamic("N"), Dim::Fixed(8 * 15 * 15)]);
+    }
+
+    #[test]
+    fn test_overflowing_values() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2), Dim::Fixed(3)]);
+        let [shape] = b.node("Shape", "", &[&x], []);
+        let zero = b.initializer("zero", array![0i64].into_dyn());
+        let max = b.initializer("max", array![i64::MAX].into_dyn());
+        let min = b.initializer("min", array![i64::MIN].into_dyn());
+        let one = b.initializer("one", array![1i64].into_dyn());
+        let [sliced] = b.node("Slice", "", &[&shape, &zero, &max, &zero, &max], []);
+        let [sliced_x] = b.node("Slice", "", &[&x, &zero, &max, &zero, &max], []);
+        let repeats = b.initializer("repeats", array![1i64, i64::MAX].into_dyn());
+        let [tiled] = b.node("Tile", "", &[&x, &repeats], []);
+        let [range] = b.node("Range", "", &[&min, &max, &one], []);
+        let mut model = b.into_model().unwrap();
+
+        infer_shapes(&mut model).unwrap();
+        let infos = &model.graph.edge_infos;
+        assert_eq!(
+            infos[sliced.name()],
+            tensor(Dtype::I64, vec![Dim::Fixed(1)])
+        );
+        assert_eq!(
+            infos[sliced_x.name()],
+            tensor(Dtype::F32, vec![Dim::Fixed(1), Dim::Fixed(3)])
+        );
+        assert_eq!(
+            infos[tiled.name()],
+            tensor(Dtype::F32, vec![Dim::Fixed(2), Dim::Unknown])
+        );
+        assert_eq!(infos[range.name()], tensor(Dtype::I64, vec![Dim::Unknown]));
+
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input(
+            "x",
+            Dtype::F32,
+            &[Dim::Fixed(1), Dim::Fixed(3), Dim::Fixed(8)],
+        );
+        let w = b.input(
+            "w",
+            Dtype::F32,
+            &[Dim::Fixed(4), Dim::Fixed(3), Dim::Fixed(3)],
+        );
+        b.node::<1>("Conv", "", &[&x, &w], [("group", Attribute::I64(i64::MAX))]);
+        let err = infer_shapes(&mut b.into_model().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("Input has 3 channels"));
+    }
+
+    #[test]
+    fn test_empty_values() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[]);
+        let [shape] = b.node("Shape", "", &[&x], []);
+        let [one] = b.node(
+            "Constant",
+            "",
+            &[],
+            [("value_ints", Attribute::I64s(vec![1]))],
+        );
+        let [sum] = b.node("Add", "", &[&shape, &one], []);
+        let mut model = b.into_model().unwrap();
+
+        infer_shapes(&mut model).unwrap();
+        assert_eq!(
+            model.graph.edge_infos[sum.name()],
+            tensor(Dtype::I64, vec![Dim::Fixed(0)])
+        );
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2), Dim::Fixed(3)]);
+        let y = b.input("y", Dtype::F32, &[Dim::Fixed(4)]);
+        b.node::<1>("Add", "", &[&x, &y], []);
+        let err = infer_shapes(&mut b.into_model().unwrap()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Cannot broadcast dimensions 3 and 4"));
+
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2), Dim::Fixed(3)]);
+        let [y] = b.node("Relu", "", &[&x], []);
+        b.output(&y, tensor(Dtype::I64, vec![Dim::Unknown, Dim::Unknown]));
+        let err = infer_shapes(&mut b.into_model().unwrap()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Declared as `I64` but inferred as `F32`"));
+    }
+}
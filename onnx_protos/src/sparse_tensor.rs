@@ -0,0 +1,268 @@
+use ndarray::{ArrayD, IxDyn};
+
+use crate::protos::SparseTensorProto;
+
+use crate::utils::{dims_to_shape, num_elements};
+use crate::{Error, Tensor, TensorValue};
+
+/// Tensor of which only the non-default (i.e. non-zero or non-empty
+/// string) values are stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseTensor {
+    /// The non-default values as a tensor of shape `[NNZ]`.
+    pub values: Tensor,
+    /// `I64` tensor of either shape `[NNZ, rank]` holding the
+    /// coordinates or of shape `[NNZ]` holding the linearized indices
+    /// of the `values`. Indices must be in ascending order.
+    pub indices: Tensor,
+    /// Shape of the dense tensor.
+    pub dims: Vec<usize>,
+}
+
+/// Apply the generic function `$f` to the array of any decoded
+/// variant of `$value` and the further arguments, wrapping the result
+/// in the same variant.
+macro_rules! map_array {
+    ($value:expr, $f:ident $(, $arg:expr)*) => {
+        match $value {
+            TensorValue::F32(arr) => TensorValue::F32($f(arr $(, $arg)*)?),
+            TensorValue::F64(arr) => TensorValue::F64($f(arr $(, $arg)*)?),
+            TensorValue::I8(arr) => TensorValue::I8($f(arr $(, $arg)*)?),
+            TensorValue::I16(arr) => TensorValue::I16($f(arr $(, $arg)*)?),
+            TensorValue::I64(arr) => TensorValue::I64($f(arr $(, $arg)*)?),
+            TensorValue::I32(arr) => TensorValue::I32($f(arr $(, $arg)*)?),
+            TensorValue::U8(arr) => TensorValue::U8($f(arr $(, $arg)*)?),
+            TensorValue::U16(arr) => TensorValue::U16($f(arr $(, $arg)*)?),
+            TensorValue::U32(arr) => TensorValue::U32($f(arr $(, $arg)*)?),
+            TensorValue::U64(arr) => TensorValue::U64($f(arr $(, $arg)*)?),
+            TensorValue::Bool(arr) => TensorValue::Bool($f(arr $(, $arg)*)?),
+            TensorValue::String(arr) => TensorValue::String($f(arr $(, $arg)*)?),
+            TensorValue::F16(arr) => TensorValue::F16($f(arr $(, $arg)*)?),
+            TensorValue::BF16(arr) => TensorValue::BF16($f(arr $(, $arg)*)?),
+            TensorValue::C64(arr) => TensorValue::C64($f(arr $(, $arg)*)?),
+            TensorValue::C128(arr) => TensorValue::C128($f(arr $(, $arg)*)?),
+            TensorValue::F8E4M3FN(arr) => TensorValue::F8E4M3FN($f(arr $(, $arg)*)?),
+            TensorValue::F8E4M3FNUZ(arr) => TensorValue::F8E4M3FNUZ($f(arr $(, $arg)*)?),
+            TensorValue::F8E5M2(arr) => TensorValue::F8E5M2($f(arr $(, $arg)*)?),
+            TensorValue::F8E5M2FNUZ(arr) => TensorValue::F8E5M2FNUZ($f(arr $(, $arg)*)?),
+            TensorValue::U4(arr) => TensorValue::U4($f(arr $(, $arg)*)?),
+            TensorValue::I4(arr) => TensorValue::I4($f(arr $(, $arg)*)?),
+            TensorValue::Unloaded { .. } | TensorValue::Raw(_) => {
+                unreachable!("Tensor has not been decoded")
+            }
+        }
+    };
+}
+
+impl SparseTensor {
+    /// The `name` is stored on the `values` tensor.
+    pub fn sparse_tensor_proto(&self, name: String) -> SparseTensorProto {
+        SparseTensorProto {
+            values: Some(self.values.tensor_proto(name)).into(),
+            indices: Some(self.indices.tensor_proto(String::new())).into(),
+            dims: self.dims.iter().map(|el| *el as i64).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Store the non-default values of `tensor` together with their
+    /// linearized indices.
+    pub fn sparsify(tensor: &Tensor) -> Result<Self, Error> {
+        let dense = decoded(tensor)?;
+        let dims = dense.shape();
+        let mut indices = vec![];
+        let values = map_array!(&dense, gather_non_default, &mut indices);
+        Ok(Self {
+            values: Tensor {
+                tensor: values,
+                path: None,
+            },
+            indices: Tensor {
+                tensor: ArrayD::from_shape_vec(IxDyn(&[indices.len()]), indices)
+                    .unwrap()
+                    .into(),
+                path: None,
+            },
+            dims,
+        })
+    }
+
+    /// The dense tensor where all positions not listed in `indices`
+    /// hold the default value of the element type.
+    pub fn densify(&self) -> Result<Tensor, Error> {
+        let indices = self.linear_indices()?;
+        let values = decoded(&self.values)?;
+        if values.shape() != [indices.len()] {
+            return Err(Error::new_validation(format!(
+                "Sparse tensor has {} indices but values of shape {:?}.",
+                indices.len(),
+                values.shape()
+            )));
+        }
+        let tensor = map_array!(&values, scatter, &indices, &self.dims);
+        Ok(Tensor { tensor, path: None })
+    }
+
+    /// Indices into the flattened dense tensor, checked to be in
+    /// bounds.
+    fn linear_indices(&self) -> Result<Vec<usize>, Error> {
+        let TensorValue::I64(indices) = decoded(&self.indices)? else {
+            return Err(Error::new_validation(
+                "Indices of a sparse tensor must be of type `I64`.".into(),
+            ));
+        };
+        let len = num_elements(&self.dims)?;
+        let out_of_bounds = || Error::new_validation("Sparse tensor index out of bounds.".into());
+        let linear: Vec<usize> = match indices.shape() {
+            [_] => indices.iter().map(|el| *el as usize).collect(),
+            [_, rank] if *rank == self.dims.len() => indices
+                .outer_iter()
+                .map(|coords| {
+                    coords
+                        .iter()
+                        .zip(self.dims.iter())
+                        .try_fold(0, |acc, (coord, dim)| {
+                            let coord = *coord as usize;
+                            (coord < *dim).then_some(acc * dim + coord)
+                        })
+                        .ok_or_else(out_of_bounds)
+                })
+                .collect::<Result<_, _>>()?,
+            shape => {
+                return Err(Error::new_validation(format!(
+                    "Invalid shape {:?} of sparse tensor indices for rank {}.",
+                    shape,
+                    self.dims.len()
+                )))
+            }
+        };
+        if linear.iter().any(|idx| *idx >= len) {
+            return Err(out_of_bounds());
+        }
+        Ok(linear)
+    }
+}
+
+/// The non-default elements of `arr` in logical order. Their
+/// linearized indices are appended to `indices`.
+fn gather_non_default<T: Clone + Default + PartialEq>(
+    arr: &ArrayD<T>,
+    indices: &mut Vec<i64>,
+) -> Result<ArrayD<T>, Error> {
+    let default = T::default();
+    let mut values = vec![];
+    for (idx, el) in arr.iter().enumerate() {
+        if *el != default {
+            indices.push(idx as i64);
+            values.push(el.clone());
+        }
+    }
+    Ok(ArrayD::from_shape_vec(IxDyn(&[values.len()]), values).unwrap())
+}
+
+/// Array of shape `dims` holding the `values` at the linearized
+/// `indices` and the default value elsewhere.
+fn scatter<T: Clone + Default>(
+    values: &ArrayD<T>,
+    indices: &[usize],
+    dims: &[usize],
+) -> Result<ArrayD<T>, Error> {
+    let mut dense = vec![T::default(); num_elements(dims)?];
+    for (idx, el) in indices.iter().zip(values.iter()) {
+        dense[*idx] = el.clone();
+    }
+    ArrayD::from_shape_vec(IxDyn(dims), dense).map_err(|e| Error::new_validation(e.to_string()))
+}
+
+/// The value of `tensor` with lazily decoded data decoded.
+fn decoded(tensor: &Tensor) -> Result<TensorValue, Error> {
+    match &tensor.tensor {
+        TensorValue::Raw(raw) => raw.decode(),
+        TensorValue::Unloaded { .. } => Err(Error::new_validation(
+            "External data of sparse tensor has not been loaded.".into(),
+        )),
+        value => Ok(value.clone()),
+    }
+}
+
+impl TryFrom<SparseTensorProto> for SparseTensor {
+    type Error = Error;
+
+    fn try_from(proto: SparseTensorProto) -> Result<Self, Self::Error> {
+        Ok(Self {
+            values: proto
+                .values
+                .into_option()
+                .ok_or_else(|| Error::new_validation("Sparse tensor has no values.".into()))?
+                .try_into()?,
+            indices: proto
+                .indices
+                .into_option()
+                .ok_or_else(|| Error::new_validation("Sparse tensor has no indices.".into()))?
+                .try_into()?,
+            dims: dims_to_shape(&proto.dims)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sparsify_roundtrip() {
+        let dense = Tensor {
+            tensor: ndarray::array![[0.0f32, 1.5, 0.0], [0.0, 0.0, -2.0]]
+                .into_dyn()
+                .into(),
+            path: None,
+        };
+        let sparse = SparseTensor::sparsify(&dense).unwrap();
+        assert_eq!(
+            sparse.values.tensor,
+            ndarray::arr1(&[1.5f32, -2.0]).into_dyn().into()
+        );
+        assert_eq!(
+            sparse.indices.tensor,
+            ndarray::arr1(&[1i64, 5]).into_dyn().into()
+        );
+        assert_eq!(sparse.dims, [2, 3]);
+        assert_eq!(sparse.densify().unwrap(), dense);
+    }
+
+    #[test]
+    fn test_coordinate_indices() {
+        let sparse = SparseTensor {
+            values: Tensor {
+                tensor: ndarray::arr1(&["a".to_string(), "b".to_string()])
+                    .into_dyn()
+                    .into(),
+                path: None,
+            },
+            indices: Tensor {
+                tensor: ndarray::array![[0i64, 1], [1, 0]].into_dyn().into(),
+                path: None,
+            },
+            dims: vec![2, 2],
+        };
+        let expected: TensorValue = ndarray::array![
+            ["".to_string(), "a".to_string()],
+            ["b".to_string(), "".to_string()]
+        ]
+        .into_dyn()
+        .into();
+        assert_eq!(sparse.densify().unwrap().tensor, expected);
+
+        let out_of_bounds = SparseTensor {
+            dims: vec![2, 1],
+            ..sparse.clone()
+        };
+        assert!(out_of_bounds.densify().is_err());
+
+        let overflowing = SparseTensor {
+            dims: vec![1 << 62, 8],
+            ..sparse
+        };
+        assert!(overflowing.densify().is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Summary statistics of a model for reports.
+use std::collections::BTreeSet;
+use std::fmt;
+
+use indexmap::IndexMap;
+
+use crate::graph_view::captures;
+use crate::passes::{is_default_domain, node_label};
+use crate::text::dtype_name;
+use crate::utils::{byte_size, human_bytes, num_elements};
+use crate::{Attribute, Dim, Dtype, Graph, GraphView, Model, TensorValue, ValueInfo};
+
+/// Number of initializers listed in [`Summary::largest_initializers`].
+const LARGEST_INITIALIZERS: usize = 10;
+
+/// Statistics of a [`Model`] as returned by [`Model::summary`].
+///
+/// Everything but the `depth` includes nested subgraphs, but not the
+/// bodies of model local functions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// Number of elements and their size in bytes of all initializers
+    /// per element type.
+    pub parameters: IndexMap<Dtype, Parameters>,
+    /// Number of nodes per domain and operator type, most frequent
+    /// first. The default domain is listed as `""`.
+    pub operators: IndexMap<String, IndexMap<String, usize>>,
+    /// The largest initializers by size, largest first.
+    pub largest_initializers: Vec<Initializer>,
+    /// Number of nodes on the longest path through the main graph.
+    pub depth: usize,
+    /// Names of symbolic dimensions used anywhere in the model.
+    pub dynamic_dims: BTreeSet<String>,
+    /// Imported version and number of nodes of every opset, including
+    /// domains used by nodes without being imported.
+    pub opsets: IndexMap<String, OpsetUsage>,
+}
+
+/// Both counts saturate at `usize::MAX`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parameters {
+    pub count: usize,
+    /// Strings count with their length in bytes.
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Initializer {
+    /// Name prefixed by the path of the subgraph holding it, if any.
+    pub name: String,
+    pub dtype: Dtype,
+    pub shape: Vec<usize>,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpsetUsage {
+    /// `None` if the domain is not imported.
+    pub version: Option<i64>,
+    pub nodes: usize,
+}
+
+impl Model {
+    /// Collect statistics about the parameters, operators and shapes
+    /// of the model.
+    ///
+    /// ```
+    /// use onnx_protos::Model;
+    ///
+    /// let model: Model = r#"
+    ///     <opset_import: ["" : 19]>
+    ///     agraph (float[N] a) => (float[N] b) <float[2] w = {1.0, 2.0}> {
+    ///         t = Mul (a, w)
+    ///         b = Abs (t)
+    ///     }
+    /// "#
+    /// .parse()
+    /// .unwrap();
+    /// let summary = model.summary();
+    /// assert_eq!(summary.depth, 2);
+    /// assert_eq!(summary.largest_initializers[0].bytes, 8);
+    /// println!("{}", summary);
+    /// ```
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            parameters: IndexMap::new(),
+            operators: IndexMap::new(),
+            largest_initializers: vec![],
+            depth: depth(&self.graph),
+            dynamic_dims: BTreeSet::new(),
+            opsets: IndexMap::new(),
+        };
+        for (domain, version) in self.opsets.iter() {
+            let domain = if is_default_domain(domain) {
+                ""
+            } else {
+                domain
+            };
+            summary.opsets.insert(
+                domain.to_string(),
+                OpsetUsage {
+                    version: Some(*version),
+                    nodes: 0,
+                },
+            );
+        }
+        summary.visit(&self.graph, "");
+
+        summary
+            .parameters
+            .sort_by(|_, a, _, b| b.bytes.cmp(&a.bytes));
+        summary.operators.sort_keys();
+        for ops in summary.operators.values_mut() {
+            ops.sort_by(|op_a, a, op_b, b| b.cmp(a).then(op_a.cmp(op_b)));
+        }
+        summary
+            .largest_initializers
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.name.cmp(&b.name)));
+        summary.largest_initializers.truncate(LARGEST_INITIALIZERS);
+        summary
+    }
+}
+
+impl Summary {
+    fn visit(&mut self, graph: &Graph, prefix: &str) {
+        let infos = (graph.inputs.iter().map(|i| &i.info))
+            .chain(graph.outputs.iter().map(|o| &o.info))
+            .chain(graph.edge_infos.values());
+        for info in infos {
+            collect_dims(info, &mut self.dynamic_dims);
+        }
+
+        let tensors = graph.initializers.iter().chain(
+            graph
+                .sparse_initializers
+                .iter()
+                .map(|(name, sparse)| (name, &sparse.values)),
+        );
+        for (name, tensor) in tensors {
+            let (dtype, shape) = (tensor.tensor.dtype(), tensor.tensor.shape());
+            let count = num_elements(&shape).unwrap_or(usize::MAX);
+            let bytes = bytes(&tensor.tensor, count);
+            let parameters = self.parameters.entry(dtype).or_default();
+            parameters.count = parameters.count.saturating_add(count);
+            parameters.bytes = parameters.bytes.saturating_add(bytes);
+            self.largest_initializers.push(Initializer {
+                name: format!("{}{}", prefix, name),
+                dtype,
+                shape,
+                bytes,
+            });
+        }
+
+        for node in graph.nodes.iter() {
+            let domain = &node.operation.domain;
+            let domain = if is_default_domain(domain) {
+                ""
+            } else {
+                domain
+            };
+            *self
+                .operators
+                .entry(domain.to_string())
+                .or_default()
+                .entry(node.operation.name.clone())
+                .or_default() += 1;
+            self.opsets
+                .entry(domain.to_string())
+                .or_insert(OpsetUsage {
+                    version: None,
+                    nodes: 0,
+                })
+                .nodes += 1;
+
+            for (name, attr) in node.attributes.iter() {
+                let graphs = match attr {
+                    Attribute::Graph(g) => std::slice::from_ref(g),
+                    Attribute::Graphs(gs) => gs.as_slice(),
+                    _ => continue,
+                };
+                for subgraph in graphs {
+                    let label = format!("{}/{}/", node_label(prefix, node), name);
+                    self.visit(subgraph, &label);
+                }
+            }
+        }
+    }
+}
+
+/// Size of the data of `tensor` holding `count` elements.
+fn bytes(tensor: &TensorValue, count: usize) -> usize {
+    match tensor {
+        TensorValue::String(arr) => arr.iter().map(String::len).sum(),
+        TensorValue::Raw(raw) if raw.dtype() == Dtype::String => raw.bytes().len(),
+        value => byte_size(value.dtype(), count).unwrap_or(0),
+    }
+}
+
+fn collect_dims(info: &ValueInfo, dims: &mut BTreeSet<String>) {
+    match info {
+        ValueInfo::Tensor(t) | ValueInfo::SparseTensor(t) => {
+            for dim in t.shape.iter().flatten() {
+                if let Dim::Dynamic(name) = dim {
+                    dims.insert(name.clone());
+                }
+            }
+        }
+        ValueInfo::Sequence(info) | ValueInfo::Optional(info) | ValueInfo::Map(_, info) => {
+            collect_dims(info, dims)
+        }
+    }
+}
+
+/// Number of nodes on the longest path through `graph`, where the
+/// nodes of a cyclic graph are taken in their given order.
+fn depth(graph: &Graph) -> usize {
+    let view = GraphView::new(graph);
+    let order = view
+        .topological_order()
+        .unwrap_or_else(|_| (0..graph.nodes.len()).collect());
+    let mut depths = vec![0; graph.nodes.len()];
+    for idx in order {
+        let node = &graph.nodes[idx];
+        let inputs = node.inputs.iter().map(String::as_str).chain(captures(node));
+        let deepest = inputs
+            .filter_map(|edge| view.producer(edge))
+            .map(|producer| depths[producer])
+            .max()
+            .unwrap_or(0);
+        depths[idx] = deepest + 1;
+    }
+    depths.into_iter().max().unwrap_or(0)
+}
+
+fn domain_name(domain: &str) -> &str {
+    if domain.is_empty() {
+        "ai.onnx"
+    } else {
+        domain
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (count, bytes) = self
+            .parameters
+            .values()
+            .fold((0usize, 0usize), |(count, bytes), p| {
+                (count.saturating_add(p.count), bytes.saturating_add(p.bytes))
+            });
+        writeln!(f, "Parameters: {} ({})", count, human_bytes(bytes))?;
+        for (dtype, parameters) in self.parameters.iter() {
+            writeln!(
+                f,
+                "  {}: {} ({})",
+                dtype_name(*dtype),
+                parameters.count,
+                human_bytes(parameters.bytes)
+            )?;
+        }
+
+        let nodes: usize = self.opsets.values().map(|o| o.nodes).sum();
+        writeln!(f, "Operators: {} nodes", nodes)?;
+        for (domain, ops) in self.operators.iter() {
+            writeln!(f, "  {}:", domain_name(domain))?;
+            for (op, count) in ops.iter() {
+                writeln!(f, "    {}: {}", op, count)?;
+            }
+        }
+
+        writeln!(f, "Opsets:")?;
+        for (domain, usage) in self.opsets.iter() {
+            let version = match usage.version {
+                Some(version) => version.to_string(),
+                None => "not imported".to_string(),
+            };
+            writeln!(
+                f,
+                "  {} {}: {} nodes",
+                domain_name(domain),
+                version,
+                usage.nodes
+            )?;
+        }
+
+        if !self.largest_initializers.is_empty() {
+            writeln!(f, "Largest initializers:")?;
+            for init in self.largest_initializers.iter() {
+                let dims: Vec<_> = init.shape.iter().map(|d| d.to_string()).collect();
+                writeln!(
+                    f,
+                    "  {}: {}[{}] ({})",
+                    init.name,
+                    dtype_name(init.dtype),
+                    dims.join(","),
+                    human_bytes(init.bytes)
+                )?;
+            }
+        }
+
+        writeln!(f, "Depth: {}", self.depth)?;
+        let dims: Vec<_> = self.dynamic_dims.iter().map(String::as_str).collect();
+        write!(f, "Dynamic dimensions: {}", dims.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let model: Model = r#"
+            <opset_import: ["ai.onnx" : 19, "unused" : 1]>
+            main (bool c, float[N, 2] x) => (float[N, 2] y)
+               <float[2] w = {1.0, 2.0}, int64[3] big = {1, 2, 3}, float[N, seq] t>
+            {
+               [mul] a = Mul (x, w)
+               b = Relu (a)
+               [cond] y = If <then_branch = then () => (float[?] z) <float[4] v = {1.0, 2.0, 3.0, 4.0}> {
+                  z = Add (b, v)
+               }, else_branch = else () => (float[?] z2) {
+                  z2 = custom.Neg (b)
+               }> (c)
+            }
+        "#
+        .parse()
+        .unwrap();
+        let summary = model.summary();
+
+        assert_eq!(
+            summary.parameters[&Dtype::F32],
+            Parameters {
+                count: 6,
+                bytes: 24
+            }
+        );
+        assert_eq!(summary.parameters[&Dtype::I64].bytes, 24);
+        assert_eq!(summary.operators[""]["Mul"], 1);
+        assert_eq!(summary.operators[""].len(), 4);
+        assert_eq!(summary.operators["custom"]["Neg"], 1);
+        let largest: Vec<_> = summary
+            .largest_initializers
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(largest, ["big", "cond/then_branch/v", "w"]);
+        // Mul -> Relu -> If, which captures `b`
+        assert_eq!(summary.depth, 3);
+        assert_eq!(
+            summary.dynamic_dims.iter().collect::<Vec<_>>(),
+            ["N", "seq"]
+        );
+        assert_eq!(summary.opsets[""].nodes, 4);
+        assert_eq!(summary.opsets["unused"].nodes, 0);
+        assert_eq!(
+            summary.opsets["custom"],
+            OpsetUsage {
+                version: None,
+                nodes: 1
+            }
+        );
+        let text = summary.to_string();
+        assert!(text.contains("Parameters: 9 (48 B)"), "{}", text);
+        assert!(text.contains("  custom not imported: 1 nodes"), "{}", text);
+    }
+
+    #[test]
+    fn test_overflowing_parameters() {
+        let mut model = Model::default();
+        for (name, shape) in [("a", vec![usize::MAX, 2]), ("b", vec![usize::MAX])] {
+            model.graph.initializers.insert(
+                name.into(),
+                crate::Tensor {
+                    tensor: TensorValue::Unloaded {
+                        dtype: Dtype::F32,
+                        shape,
+                    },
+                    path: None,
+                },
+            );
+        }
+        let summary = model.summary();
+        assert_eq!(
+            summary.parameters[&Dtype::F32],
+            Parameters {
+                count: usize::MAX,
+                bytes: usize::MAX
+            }
+        );
+        let text = summary.to_string();
+        let expected = format!("Parameters: {} (", usize::MAX);
+        assert!(text.contains(&expected), "{}", text);
+    }
+}
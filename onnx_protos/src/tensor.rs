@@ -1,16 +1,17 @@
-use std::mem::size_of;
 use std::path::PathBuf;
 
 use crate::protos::{
-    tensor_proto::{DataLocation, DataType},
-    type_proto::Value,
-    StringStringEntryProto, TensorProto, TypeProto, ValueInfoProto,
+    tensor_proto::DataLocation, type_proto::Value, StringStringEntryProto, TensorProto, TypeProto,
+    ValueInfoProto,
 };
-use ndarray::{Array, ArrayD};
+use half::{bf16, f16};
+use ndarray::{ArrayD, IxDyn};
+use num_complex::{Complex32, Complex64};
 
-use protobuf::Enum;
+use protobuf::Message;
 
-use crate::{utils::shape_to_tensor_shape_proto, Error};
+use crate::utils::{dims_to_shape, num_elements, shape_to_tensor_shape_proto};
+use crate::{Dtype, Error, RawTensor};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Tensor {
@@ -24,14 +25,28 @@ pub struct Tensor {
 pub struct ExternalData {
     /// POSIX filesystem path relative to the directory where the ONNX
     /// protobuf model was stored.
-    location: PathBuf,
+    pub location: PathBuf,
     /// Position of byte at which stored data begins.  Offset values
     /// SHOULD be multiples 4096 (page size) to enable mmap support.
-    offset: Option<usize>,
+    pub offset: Option<usize>,
     /// Number of bytes containing data.
-    length: Option<usize>,
-    /// SHA1 digest of file specified in under 'location' key.}
-    checksum: Option<Vec<u8>>,
+    pub length: Option<usize>,
+    /// Hex encoded SHA1 digest of the whole file specified under
+    /// `location`. Verified when the data is loaded.
+    pub checksum: Option<Vec<u8>>,
+}
+
+impl ExternalData {
+    /// External data stored at `location` relative to the model
+    /// directory. Offset and length are determined when saving.
+    pub fn new(location: impl Into<PathBuf>) -> Self {
+        Self {
+            location: location.into(),
+            offset: None,
+            length: None,
+            checksum: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -48,12 +63,62 @@ pub enum TensorValue {
     U64(ArrayD<u64>),
     Bool(ArrayD<bool>),
     String(ArrayD<String>),
+    F16(ArrayD<f16>),
+    BF16(ArrayD<bf16>),
+    C64(ArrayD<Complex32>),
+    C128(ArrayD<Complex64>),
+    /// 8 bit floats given by their bit patterns.
+    F8E4M3FN(ArrayD<u8>),
+    F8E4M3FNUZ(ArrayD<u8>),
+    F8E5M2(ArrayD<u8>),
+    F8E5M2FNUZ(ArrayD<u8>),
+    /// 4 bit integers, one per element. Only the lower 4 bits are
+    /// stored.
+    U4(ArrayD<u8>),
+    /// 4 bit integers in the range `-8..=7`, one per element.
+    I4(ArrayD<i8>),
+    /// Externally stored data which has not been read yet. See
+    /// [`crate::Model::load_external_data`].
+    Unloaded {
+        dtype: Dtype,
+        shape: Vec<usize>,
+    },
+    /// Data which is only decoded on demand. See
+    /// [`crate::Model::from_path_lazy`].
+    Raw(RawTensor),
     // `Unknown` does not have a defined serialization format inside a TensorProto
     // Unknown { shape: Vec<usize>, data: Vec<u8> },
 }
 
+macro_rules! impl_from_array {
+    ($var:ident, $ty:ty) => {
+        impl From<ArrayD<$ty>> for TensorValue {
+            fn from(arr: ArrayD<$ty>) -> Self {
+                TensorValue::$var(arr)
+            }
+        }
+    };
+}
+
+impl_from_array!(F32, f32);
+impl_from_array!(F64, f64);
+impl_from_array!(I8, i8);
+impl_from_array!(I16, i16);
+impl_from_array!(I64, i64);
+impl_from_array!(I32, i32);
+impl_from_array!(U8, u8);
+impl_from_array!(U16, u16);
+impl_from_array!(U32, u32);
+impl_from_array!(U64, u64);
+impl_from_array!(Bool, bool);
+impl_from_array!(String, String);
+impl_from_array!(F16, f16);
+impl_from_array!(BF16, bf16);
+impl_from_array!(C64, Complex32);
+impl_from_array!(C128, Complex64);
+
 impl TensorValue {
-    fn shape(&self) -> Vec<usize> {
+    pub fn shape(&self) -> Vec<usize> {
         use TensorValue::*;
 
         match self {
@@ -69,31 +134,63 @@ impl TensorValue {
             U64(t) => t.shape().to_vec(),
             Bool(t) => t.shape().to_vec(),
             String(t) => t.shape().to_vec(),
+            F16(t) => t.shape().to_vec(),
+            BF16(t) => t.shape().to_vec(),
+            C64(t) => t.shape().to_vec(),
+            C128(t) => t.shape().to_vec(),
+            F8E4M3FN(t) | F8E4M3FNUZ(t) | F8E5M2(t) | F8E5M2FNUZ(t) | U4(t) => t.shape().to_vec(),
+            I4(t) => t.shape().to_vec(),
+            Unloaded { shape, .. } => shape.clone(),
+            Raw(raw) => raw.shape().to_vec(),
         }
     }
 
-    fn dtype(&self) -> DataType {
+    pub fn dtype(&self) -> Dtype {
         use TensorValue::*;
 
         match self {
-            F32(_) => DataType::FLOAT,
-            F64(_) => DataType::DOUBLE,
-            I8(_) => DataType::INT8,
-            I16(_) => DataType::INT16,
-            I64(_) => DataType::INT64,
-            I32(_) => DataType::INT32,
-            U8(_) => DataType::UINT8,
-            U16(_) => DataType::UINT16,
-            U32(_) => DataType::UINT32,
-            U64(_) => DataType::UINT64,
-            Bool(_) => DataType::BOOL,
-            String(_) => DataType::STRING,
+            F32(_) => Dtype::F32,
+            F64(_) => Dtype::F64,
+            I8(_) => Dtype::I8,
+            I16(_) => Dtype::I16,
+            I64(_) => Dtype::I64,
+            I32(_) => Dtype::I32,
+            U8(_) => Dtype::U8,
+            U16(_) => Dtype::U16,
+            U32(_) => Dtype::U32,
+            U64(_) => Dtype::U64,
+            Bool(_) => Dtype::Bool,
+            String(_) => Dtype::String,
+            F16(_) => Dtype::F16,
+            BF16(_) => Dtype::BF16,
+            C64(_) => Dtype::C64,
+            C128(_) => Dtype::C128,
+            F8E4M3FN(_) => Dtype::F8E4M3FN,
+            F8E4M3FNUZ(_) => Dtype::F8E4M3FNUZ,
+            F8E5M2(_) => Dtype::F8E5M2,
+            F8E5M2FNUZ(_) => Dtype::F8E5M2FNUZ,
+            U4(_) => Dtype::U4,
+            I4(_) => Dtype::I4,
+            Unloaded { dtype, .. } => *dtype,
+            Raw(raw) => raw.dtype(),
         }
     }
 }
 
 impl Tensor {
-    pub fn tensor_proto(self, name: String) -> TensorProto {
+    /// Parse a serialized `TensorProto`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        TensorProto::parse_from_bytes(bytes)?.try_into()
+    }
+
+    /// Serialize as a `TensorProto` with the given `name`.
+    pub fn to_bytes(&self, name: String) -> Result<Vec<u8>, Error> {
+        Ok(self.tensor_proto(name).write_to_bytes()?)
+    }
+
+    /// All data except for strings is stored little-endian in
+    /// `raw_data`.
+    pub fn tensor_proto(&self, name: String) -> TensorProto {
         let mut tp = TensorProto {
             dims: self
                 .tensor
@@ -101,43 +198,64 @@ impl Tensor {
                 .into_iter()
                 .map(|el| el as i64)
                 .collect(),
-            data_type: self.tensor.dtype() as _,
+            data_type: self.tensor.dtype().into(),
             name,
             ..Default::default()
         };
 
         macro_rules! to_le_bytes {
             ($arr:expr) => {
-                $arr.mapv(|el| el.to_le_bytes())
-                    .into_iter()
-                    .flatten()
-                    .collect()
+                $arr.iter().flat_map(|el| el.to_le_bytes()).collect()
             };
         }
 
-        match self.tensor {
-            TensorValue::F32(arr) => tp.float_data = arr.into_raw_vec(),
-            TensorValue::F64(arr) => tp.double_data = arr.into_raw_vec(),
-            TensorValue::I64(arr) => tp.int64_data = arr.into_raw_vec(),
-            TensorValue::I32(arr) => tp.int32_data = arr.into_raw_vec(),
-            TensorValue::U64(arr) => tp.uint64_data = arr.into_raw_vec(),
-            TensorValue::String(arr) => {
-                tp.string_data = arr.mapv(|el| el.into_bytes()).into_raw_vec()
-            }
+        // Externally stored data is kept inline in `raw_data` until
+        // `Model::save` moves it into the referenced file. Strings
+        // cannot be stored externally. Unloaded tensors merely keep
+        // their reference.
+        let is_string = matches!(self.tensor, TensorValue::String(_));
+        if let Some(external_data) = self.path.clone().filter(|_| !is_string) {
+            tp.data_location = DataLocation::EXTERNAL.into();
+            tp.external_data = external_data.into();
+        }
 
-            TensorValue::I8(arr) => tp.raw_data = to_le_bytes!(arr),
-            TensorValue::I16(arr) => tp.raw_data = to_le_bytes!(arr),
-            TensorValue::U8(arr) => tp.raw_data = to_le_bytes!(arr),
-            TensorValue::U16(arr) => tp.raw_data = to_le_bytes!(arr),
-            TensorValue::U32(arr) => tp.raw_data = to_le_bytes!(arr),
-            TensorValue::Bool(arr) => {
-                tp.raw_data = arr
-                    .mapv(|el| (el as u8).to_le_bytes())
-                    .into_iter()
-                    .flatten()
-                    .collect()
+        tp.raw_data = match &self.tensor {
+            TensorValue::String(arr) => {
+                tp.string_data = arr.iter().map(|el| el.as_bytes().to_vec()).collect();
+                vec![]
             }
-        }
+            TensorValue::F32(arr) => to_le_bytes!(arr),
+            TensorValue::F64(arr) => to_le_bytes!(arr),
+            TensorValue::I8(arr) => to_le_bytes!(arr),
+            TensorValue::I16(arr) => to_le_bytes!(arr),
+            TensorValue::I32(arr) => to_le_bytes!(arr),
+            TensorValue::I64(arr) => to_le_bytes!(arr),
+            TensorValue::U8(arr) => to_le_bytes!(arr),
+            TensorValue::U16(arr) => to_le_bytes!(arr),
+            TensorValue::U32(arr) => to_le_bytes!(arr),
+            TensorValue::U64(arr) => to_le_bytes!(arr),
+            TensorValue::Bool(arr) => arr.iter().map(|el| *el as u8).collect(),
+            TensorValue::F16(arr) => to_le_bytes!(arr),
+            TensorValue::BF16(arr) => to_le_bytes!(arr),
+            TensorValue::C64(arr) => arr
+                .iter()
+                .flat_map(|el| [el.re.to_le_bytes(), el.im.to_le_bytes()])
+                .flatten()
+                .collect(),
+            TensorValue::C128(arr) => arr
+                .iter()
+                .flat_map(|el| [el.re.to_le_bytes(), el.im.to_le_bytes()])
+                .flatten()
+                .collect(),
+            TensorValue::F8E4M3FN(arr)
+            | TensorValue::F8E4M3FNUZ(arr)
+            | TensorValue::F8E5M2(arr)
+            | TensorValue::F8E5M2FNUZ(arr) => to_le_bytes!(arr),
+            TensorValue::U4(arr) => pack_4bit(arr.iter().copied()),
+            TensorValue::I4(arr) => pack_4bit(arr.iter().map(|el| *el as u8)),
+            TensorValue::Unloaded { .. } => vec![],
+            TensorValue::Raw(raw) => raw.bytes().to_vec(),
+        };
         tp
     }
 
@@ -152,7 +270,7 @@ impl Tensor {
             type_: Some(TypeProto {
                 denotation: type_denotation,
                 value: Some(Value::TensorType(crate::protos::type_proto::Tensor {
-                    elem_type: self.tensor.dtype() as _,
+                    elem_type: self.tensor.dtype().into(),
                     shape: Some(shape_to_tensor_shape_proto(shape.as_slice())).into(),
                     ..Default::default()
                 })),
@@ -165,87 +283,180 @@ impl Tensor {
     }
 }
 
+/// Two values per byte, the first one in the lower half.
+fn pack_4bit(values: impl Iterator<Item = u8>) -> Vec<u8> {
+    let values: Vec<u8> = values.map(|el| el & 0x0F).collect();
+    values
+        .chunks(2)
+        .map(|pair| pair[0] | pair.get(1).map_or(0, |hi| hi << 4))
+        .collect()
+}
+
+fn unpack_4bit(bytes: impl Iterator<Item = u8>, len: usize) -> Vec<u8> {
+    bytes
+        .flat_map(|el| [el & 0x0F, el >> 4])
+        .take(len)
+        .collect()
+}
+
+/// Sign extend the lower 4 bits.
+fn to_i4(value: u8) -> i8 {
+    ((value << 4) as i8) >> 4
+}
+
+/// Split `raw` into little-endian values of `N` bytes each.
+fn from_le<const N: usize, T>(raw: &[u8], f: impl Fn([u8; N]) -> T) -> Result<Vec<T>, Error> {
+    if !raw.len().is_multiple_of(N) {
+        return Err(Error::new_validation(format!(
+            "Length of `raw_data` ({}) is not a multiple of the element size ({}).",
+            raw.len(),
+            N
+        )));
+    }
+    Ok(raw
+        .chunks_exact(N)
+        .map(|chunk| f(chunk.try_into().unwrap()))
+        .collect())
+}
+
+fn to_complex<T: Copy>(parts: &[T]) -> Result<Vec<num_complex::Complex<T>>, Error> {
+    if !parts.len().is_multiple_of(2) {
+        return Err(Error::new_validation(
+            "Complex tensor has an odd number of components.".into(),
+        ));
+    }
+    Ok(parts
+        .chunks_exact(2)
+        .map(|c| num_complex::Complex::new(c[0], c[1]))
+        .collect())
+}
+
 impl TryFrom<TensorProto> for Tensor {
     type Error = Error;
 
     fn try_from(proto: TensorProto) -> Result<Self, Self::Error> {
-        macro_rules! read_raw {
-            ($ty:ty) => {{
-                assert_eq!(proto.raw_data.len() % size_of::<$ty>(), 0);
-                proto
-                    .raw_data
-                    .chunks_exact(size_of::<$ty>())
-                    .map(|slice| slice.try_into().unwrap())
-                    .map(<$ty>::from_le_bytes)
-            }};
+        let shape = dims_to_shape(&proto.dims)?;
+        let len = num_elements(&shape)?;
+
+        if proto.data_location.enum_value() == Ok(DataLocation::EXTERNAL)
+            && proto.raw_data.is_empty()
+            && len > 0
+        {
+            return Ok(Tensor {
+                tensor: TensorValue::Unloaded {
+                    dtype: proto.data_type.try_into()?,
+                    shape,
+                },
+                path: Some(proto.external_data.try_into()?),
+            });
         }
 
-        let shape: Vec<_> = proto.dims.iter().map(|v| *v as usize).collect();
+        let dtype: Dtype = proto.data_type.try_into()?;
+        let raw = proto.raw_data.as_slice();
+        let use_raw = !raw.is_empty();
+        let int32 = proto.int32_data.iter();
 
-        macro_rules! match_arm {
-            ($var:tt, $expl_field:expr, $rust_ty:ty) => {{
-                let explicit_field = &$expl_field;
-                let vec: Vec<_> = if !explicit_field.is_empty() {
-                    explicit_field.clone()
-                } else {
-                    read_raw!($rust_ty).collect::<Vec<_>>()
-                };
-                TensorValue::$var(
-                    Array::from_vec(vec)
-                        .into_dyn()
-                        .into_shape(shape)
-                        .unwrap()
-                        .into(),
+        macro_rules! array {
+            ($var:ident, $vec:expr) => {
+                TensorValue::$var(ArrayD::from_shape_vec(IxDyn(&shape), $vec).map_err(|e| {
+                    Error::new_validation(format!(
+                        "Data of tensor `{}` does not match its shape {:?}: {}",
+                        proto.name, shape, e
+                    ))
+                })?)
+            };
+        }
+        // Element types which are either stored in `raw_data` or in
+        // the given typed field
+        macro_rules! numeric {
+            ($var:ident, $ty:ty, $typed:expr) => {
+                array!(
+                    $var,
+                    if use_raw {
+                        from_le(raw, <$ty>::from_le_bytes)?
+                    } else {
+                        $typed
+                    }
                 )
-            }};
+            };
         }
 
-        let data_type = DataType::from_i32(proto.data_type).ok_or_else(|| {
-            Error::new_validation(format!(
-                "Failed to parse tensor data type: `{}`",
-                proto.data_type
-            ))
-        })?;
-        let tval = match dbg!(data_type) {
-            DataType::FLOAT => match_arm!(F32, proto.float_data, f32),
-            DataType::DOUBLE => match_arm!(F64, proto.double_data, f64),
-
-            DataType::INT8 => match_arm!(I8, vec![], i8),
-            DataType::INT16 => match_arm!(I16, vec![], i16),
-            DataType::INT32 => match_arm!(I32, proto.int32_data, i32),
-            DataType::INT64 => match_arm!(I64, proto.int64_data, i64),
-
-            DataType::UINT8 => match_arm!(U8, vec![], u8),
-            DataType::UINT16 => match_arm!(U16, vec![], u16),
-            DataType::UINT32 => match_arm!(U32, vec![], u32),
-            DataType::UINT64 => match_arm!(U64, proto.uint64_data, u64),
-
-            DataType::BOOL => {
-                let explicit_field = &proto.int32_data;
-                let vec: Vec<_> = if !explicit_field.is_empty() {
-                    explicit_field.iter().map(|&el| el != 0).collect()
+        let tval = match dtype {
+            Dtype::F32 => numeric!(F32, f32, proto.float_data.clone()),
+            Dtype::F64 => numeric!(F64, f64, proto.double_data.clone()),
+            Dtype::I8 => numeric!(I8, i8, int32.map(|el| *el as _).collect()),
+            Dtype::I16 => numeric!(I16, i16, int32.map(|el| *el as _).collect()),
+            Dtype::I32 => numeric!(I32, i32, proto.int32_data.clone()),
+            Dtype::I64 => numeric!(I64, i64, proto.int64_data.clone()),
+            Dtype::U8 => numeric!(U8, u8, int32.map(|el| *el as _).collect()),
+            Dtype::U16 => numeric!(U16, u16, int32.map(|el| *el as _).collect()),
+            Dtype::U32 => numeric!(
+                U32,
+                u32,
+                proto.uint64_data.iter().map(|el| *el as _).collect()
+            ),
+            Dtype::U64 => numeric!(U64, u64, proto.uint64_data.clone()),
+            Dtype::Bool => array!(
+                Bool,
+                if use_raw {
+                    raw.iter().map(|el| *el != 0).collect()
+                } else {
+                    int32.map(|el| *el != 0).collect()
+                }
+            ),
+            Dtype::F16 => numeric!(F16, f16, int32.map(|el| f16::from_bits(*el as _)).collect()),
+            Dtype::BF16 => numeric!(
+                BF16,
+                bf16,
+                int32.map(|el| bf16::from_bits(*el as _)).collect()
+            ),
+            Dtype::C64 => array!(
+                C64,
+                if use_raw {
+                    to_complex(&from_le(raw, f32::from_le_bytes)?)?
+                } else {
+                    to_complex(&proto.float_data)?
+                }
+            ),
+            Dtype::C128 => array!(
+                C128,
+                if use_raw {
+                    to_complex(&from_le(raw, f64::from_le_bytes)?)?
                 } else {
-                    read_raw!(u8).map(|v| v > 0).collect()
+                    to_complex(&proto.double_data)?
+                }
+            ),
+            Dtype::F8E4M3FN => numeric!(F8E4M3FN, u8, int32.map(|el| *el as _).collect()),
+            Dtype::F8E4M3FNUZ => numeric!(F8E4M3FNUZ, u8, int32.map(|el| *el as _).collect()),
+            Dtype::F8E5M2 => numeric!(F8E5M2, u8, int32.map(|el| *el as _).collect()),
+            Dtype::F8E5M2FNUZ => numeric!(F8E5M2FNUZ, u8, int32.map(|el| *el as _).collect()),
+            // Each element of `int32_data` holds one packed byte
+            Dtype::U4 | Dtype::I4 => {
+                let unpacked = if use_raw {
+                    unpack_4bit(raw.iter().copied(), len)
+                } else {
+                    unpack_4bit(int32.map(|el| *el as u8), len)
                 };
-                TensorValue::Bool(Array::from_vec(vec).into_dyn().into_shape(shape).unwrap())
-            }
-            DataType::STRING => {
-                let explicit_field = &proto.string_data;
-                // Strings are always in the explicit field (from proto3 source)
-                let vec: Vec<_> = explicit_field
-                    .clone()
-                    .into_iter()
-                    .map(|u8_vec| String::from_utf8(u8_vec).unwrap())
-                    .collect();
-                TensorValue::String(Array::from_vec(vec).into_dyn().into_shape(shape).unwrap())
-            }
-            v => {
-                return Err(Error::new_validation(format!(
-                    "Unsupported DataType: `{:?}`",
-                    v
-                )))
+                if dtype == Dtype::U4 {
+                    array!(U4, unpacked)
+                } else {
+                    array!(I4, unpacked.into_iter().map(to_i4).collect())
+                }
             }
+            Dtype::String => array!(
+                String,
+                proto
+                    .string_data
+                    .iter()
+                    .map(|el| String::from_utf8(el.clone()))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| Error::new_validation(format!(
+                        "Tensor `{}` holds invalid UTF-8: {}",
+                        proto.name, e
+                    )))?
+            ),
         };
+
         let path = match proto
             .data_location
             .enum_value()
@@ -258,6 +469,33 @@ impl TryFrom<TensorProto> for Tensor {
     }
 }
 
+impl From<ExternalData> for Vec<StringStringEntryProto> {
+    fn from(data: ExternalData) -> Self {
+        let entry = |key: &str, value: String| StringStringEntryProto {
+            key: key.to_string(),
+            value,
+            ..Default::default()
+        };
+        let mut out = vec![entry(
+            "location",
+            data.location.to_string_lossy().into_owned(),
+        )];
+        if let Some(offset) = data.offset {
+            out.push(entry("offset", offset.to_string()));
+        }
+        if let Some(length) = data.length {
+            out.push(entry("length", length.to_string()));
+        }
+        if let Some(checksum) = data.checksum {
+            out.push(entry(
+                "checksum",
+                String::from_utf8_lossy(&checksum).into_owned(),
+            ));
+        }
+        out
+    }
+}
+
 impl TryFrom<Vec<StringStringEntryProto>> for ExternalData {
     type Error = Error;
 
@@ -297,3 +535,107 @@ impl TryFrom<Vec<StringStringEntryProto>> for ExternalData {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let expected = Tensor {
+            tensor: TensorValue::I16(ndarray::array![[1, -2], [3, -4]].into_dyn()),
+            path: None,
+        };
+        let bytes = expected.to_bytes("foo".into()).unwrap();
+        let candidate = Tensor::from_bytes(&bytes).unwrap();
+
+        assert_eq!(expected, candidate);
+    }
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let values = [
+            TensorValue::F16(ndarray::arr1(&[f16::from_f32(1.5), f16::NEG_INFINITY]).into_dyn()),
+            TensorValue::C64(ndarray::arr1(&[Complex32::new(1.0, -2.0)]).into_dyn()),
+            TensorValue::I4(ndarray::array![[-8, 7, 0], [1, -1, 3]].into_dyn()),
+            TensorValue::U4(ndarray::arr1(&[15, 0, 9]).into_dyn()),
+            TensorValue::Bool(ndarray::arr1(&[true, false]).into_dyn()),
+        ];
+        for value in values {
+            let expected = Tensor {
+                tensor: value,
+                path: None,
+            };
+            let tp = expected.tensor_proto("foo".into());
+            assert!(tp.int32_data.is_empty());
+            assert!(!tp.raw_data.is_empty());
+            assert_eq!(expected, tp.try_into().unwrap());
+        }
+
+        let tp = Tensor {
+            tensor: TensorValue::U4(ndarray::arr1(&[1, 2, 3]).into_dyn()),
+            path: None,
+        }
+        .tensor_proto("foo".into());
+        assert_eq!(tp.raw_data, [0x21, 0x03]);
+    }
+
+    #[test]
+    fn test_typed_fields() {
+        let tp = |data_type: Dtype, dims: &[i64]| TensorProto {
+            data_type: data_type.into(),
+            dims: dims.to_vec(),
+            ..Default::default()
+        };
+
+        let mut u32_tp = tp(Dtype::U32, &[2]);
+        u32_tp.uint64_data = vec![1, u32::MAX as u64];
+        assert_eq!(
+            Tensor::try_from(u32_tp).unwrap().tensor,
+            TensorValue::U32(ndarray::arr1(&[1, u32::MAX]).into_dyn())
+        );
+
+        let mut f16_tp = tp(Dtype::F16, &[1]);
+        f16_tp.int32_data = vec![f16::ONE.to_bits() as i32];
+        assert_eq!(
+            Tensor::try_from(f16_tp).unwrap().tensor,
+            TensorValue::F16(ndarray::arr1(&[f16::ONE]).into_dyn())
+        );
+
+        let mut c128_tp = tp(Dtype::C128, &[1]);
+        c128_tp.double_data = vec![3.0, 4.0];
+        assert_eq!(
+            Tensor::try_from(c128_tp).unwrap().tensor,
+            TensorValue::C128(ndarray::arr1(&[Complex64::new(3.0, 4.0)]).into_dyn())
+        );
+
+        let mut i4_tp = tp(Dtype::I4, &[3]);
+        i4_tp.int32_data = vec![0xF1, 0x08];
+        assert_eq!(
+            Tensor::try_from(i4_tp).unwrap().tensor,
+            TensorValue::I4(ndarray::arr1(&[1, -1, -8]).into_dyn())
+        );
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let mut tp = TensorProto {
+            data_type: Dtype::F32.into(),
+            dims: vec![2, 2],
+            float_data: vec![1.0, 2.0, 3.0],
+            ..Default::default()
+        };
+        assert!(Tensor::try_from(tp.clone()).is_err());
+
+        tp.float_data.clear();
+        tp.raw_data = vec![0; 15];
+        assert!(Tensor::try_from(tp.clone()).is_err());
+
+        // Negative or overflowing dimensions
+        for dims in [vec![-1, -1], vec![1 << 62, 8]] {
+            tp.dims = dims;
+            let bytes = tp.write_to_bytes().unwrap();
+            assert!(Tensor::from_bytes(&bytes).is_err());
+        }
+    }
+}
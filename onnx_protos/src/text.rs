@@ -0,0 +1,1647 @@
+//! The ONNX textual syntax.
+//!
+//! [`Model`] and [`Graph`] implement [`fmt::Display`] printing them in
+//! the syntax understood by `onnx.parser`, and [`FromStr`] parsing it
+//! back:
+//!
+//! ```text
+//! <ir_version: 9, opset_import: ["" : 19]>
+//! agraph (float[N] a) => (float[N] b) {
+//!    b = Abs (a)
+//! }
+//! ```
+//!
+//! Sparse tensors are written in dense form and converted back when
+//! parsing. Tensors whose data is not available are written as their
+//! external data reference, which is empty if they have none. Doc
+//! strings other than the ones of models and functions, denotations,
+//! node metadata, training info and unknown fields are not part of
+//! the syntax and are dropped when printing.
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+use ndarray::{ArrayD, IxDyn};
+
+use crate::tensor::ExternalData;
+use crate::utils::num_elements;
+use crate::{
+    bf16, f16, Attribute, AttributeKind, Complex32, Complex64, Dim, Dtype, Error, Function,
+    FunctionAttribute, Graph, Input, Model, Node, Operation, Output, SparseTensor, Tensor,
+    TensorInfo, TensorValue, UnknownFields, ValueInfo,
+};
+
+const INDENT: &str = "   ";
+
+const DTYPES: [(Dtype, &str); 22] = [
+    (Dtype::U8, "uint8"),
+    (Dtype::U16, "uint16"),
+    (Dtype::U32, "uint32"),
+    (Dtype::U64, "uint64"),
+    (Dtype::I8, "int8"),
+    (Dtype::I16, "int16"),
+    (Dtype::I32, "int32"),
+    (Dtype::I64, "int64"),
+    (Dtype::F32, "float"),
+    (Dtype::F64, "double"),
+    (Dtype::String, "string"),
+    (Dtype::Bool, "bool"),
+    (Dtype::F16, "float16"),
+    (Dtype::BF16, "bfloat16"),
+    (Dtype::C64, "complex64"),
+    (Dtype::C128, "complex128"),
+    (Dtype::F8E4M3FN, "float8e4m3fn"),
+    (Dtype::F8E4M3FNUZ, "float8e4m3fnuz"),
+    (Dtype::F8E5M2, "float8e5m2"),
+    (Dtype::F8E5M2FNUZ, "float8e5m2fnuz"),
+    (Dtype::U4, "uint4"),
+    (Dtype::I4, "int4"),
+];
+
+const KINDS: [(AttributeKind, &str); 13] = [
+    (AttributeKind::Tensor, "tensor"),
+    (AttributeKind::SparseTensor, "sparse_tensor"),
+    (AttributeKind::F32, "float"),
+    (AttributeKind::I64, "int"),
+    (AttributeKind::String, "string"),
+    (AttributeKind::Strings, "strings"),
+    (AttributeKind::F32s, "floats"),
+    (AttributeKind::I64s, "ints"),
+    (AttributeKind::Tensors, "tensors"),
+    (AttributeKind::SparseTensors, "sparse_tensors"),
+    (AttributeKind::Graph, "graph"),
+    (AttributeKind::Graphs, "graphs"),
+    (AttributeKind::TypeProto, "type_proto"),
+];
+
+pub(crate) fn dtype_name(dtype: Dtype) -> &'static str {
+    DTYPES.iter().find(|(d, _)| *d == dtype).unwrap().1
+}
+
+fn kind_name(kind: AttributeKind) -> &'static str {
+    KINDS.iter().find(|(k, _)| *k == kind).unwrap().1
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A name which is quoted unless it is a plain identifier.
+struct Id<'a>(&'a str);
+
+impl fmt::Display for Id<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_identifier(self.0) {
+            f.write_str(self.0)
+        } else {
+            Quoted(self.0).fmt(f)
+        }
+    }
+}
+
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\t' => f.write_str("\\t")?,
+                '\r' => f.write_str("\\r")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Floats are always written with a decimal point or exponent to
+/// tell them apart from integers.
+fn float(value: f64, formatted: String) -> String {
+    if value.is_nan() {
+        "nan".into()
+    } else if value.is_infinite() || formatted.contains(['.', 'e', 'E']) {
+        formatted
+    } else {
+        formatted + ".0"
+    }
+}
+
+fn f32_literal(value: f32) -> String {
+    float(value as f64, format!("{:?}", value))
+}
+
+fn f64_literal(value: f64) -> String {
+    float(value, format!("{:?}", value))
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut header = vec![
+            format!("ir_version: {}", self.ir_version),
+            format!("opset_import: {}", Opsets(&self.opsets)),
+        ];
+        let strings = [
+            ("producer_name", &self.producer_name),
+            ("producer_version", &self.producer_version),
+            ("domain", &self.domain),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                header.push(format!("{}: {}", key, Quoted(value)));
+            }
+        }
+        if self.model_version != 0 {
+            header.push(format!("model_version: {}", self.model_version));
+        }
+        if let Some(doc_string) = &self.doc_string {
+            header.push(format!("doc_string: {}", Quoted(doc_string)));
+        }
+        if !self.metadata.is_empty() {
+            let props: Vec<_> = self
+                .metadata
+                .iter()
+                .map(|(k, v)| format!("{}: {}", Quoted(k), Quoted(v)))
+                .collect();
+            header.push(format!("metadata_props: [{}]", props.join(", ")));
+        }
+        write_header(f, &header)?;
+        write_graph(f, &self.graph, 0)?;
+        writeln!(f)?;
+        for function in self.functions.iter() {
+            writeln!(f)?;
+            write_function(f, function)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_graph(f, self, 0)
+    }
+}
+
+struct Opsets<'a>(&'a IndexMap<String, i64>);
+
+impl fmt::Display for Opsets<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opsets: Vec<_> = self
+            .0
+            .iter()
+            .map(|(domain, version)| format!("{} : {}", Quoted(domain), version))
+            .collect();
+        write!(f, "[{}]", opsets.join(", "))
+    }
+}
+
+fn write_header(f: &mut fmt::Formatter<'_>, entries: &[String]) -> fmt::Result {
+    writeln!(f, "<")?;
+    for (i, entry) in entries.iter().enumerate() {
+        let separator = if i + 1 < entries.len() { "," } else { "" };
+        writeln!(f, "{}{}{}", INDENT, entry, separator)?;
+    }
+    writeln!(f, ">")
+}
+
+/// Write `graph` starting at the current position, with its nodes
+/// indented by one more level than `indent`.
+fn write_graph(f: &mut fmt::Formatter<'_>, graph: &Graph, indent: usize) -> fmt::Result {
+    write!(f, "{} (", Id(&graph.name))?;
+    for (i, input) in graph.inputs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{} {}", Type(&input.info), Id(&input.name))?;
+        if let Some(tensor) = graph.initializers.get(&input.name) {
+            write!(f, " = {}", Data(tensor))?;
+        }
+    }
+    write!(f, ") => (")?;
+    for (i, output) in graph.outputs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{} {}", Type(&output.info), Id(&output.name))?;
+    }
+    write!(f, ")")?;
+
+    let mut listed = vec![];
+    for (name, tensor) in graph.initializers.iter() {
+        if !graph.inputs.iter().any(|i| i.name == *name) {
+            listed.push(format!(
+                "{} {} = {}",
+                TensorType(&tensor.tensor),
+                Id(name),
+                Data(tensor)
+            ));
+        }
+    }
+    for (name, sparse) in graph.sparse_initializers.iter() {
+        let dense = dense(sparse);
+        listed.push(format!(
+            "sparse_tensor({}) {} = {}",
+            TensorType(&dense.tensor),
+            Id(name),
+            Data(&dense)
+        ));
+    }
+    for (name, info) in graph.edge_infos.iter() {
+        listed.push(format!("{} {}", Type(info), Id(name)));
+    }
+    let pad = INDENT.repeat(indent);
+    if listed.is_empty() {
+        writeln!(f, " {{")?;
+    } else {
+        writeln!(f, "\n{}{}<{}>\n{}{{", pad, INDENT, listed.join(", "), pad)?;
+    }
+    for node in graph.nodes.iter() {
+        write_node(f, node, indent + 1)?;
+    }
+    write!(f, "{}}}", pad)
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, node: &Node, indent: usize) -> fmt::Result {
+    write!(f, "{}", INDENT.repeat(indent))?;
+    if !node.name.is_empty() {
+        write!(f, "[{}] ", Id(&node.name))?;
+    }
+    let outputs: Vec<_> = node.outputs.iter().map(|o| Id(o).to_string()).collect();
+    write!(f, "{}", outputs.join(", "))?;
+    if !outputs.is_empty() {
+        write!(f, " ")?;
+    }
+    write!(f, "= ")?;
+    let Operation { name, domain } = &node.operation;
+    if !domain.is_empty() {
+        write!(f, "{}.", domain)?;
+    }
+    write!(f, "{}", name)?;
+    if let Some(overload) = &node.overload {
+        write!(f, ":{}", Id(overload))?;
+    }
+    if !node.attributes.is_empty() {
+        write!(f, " <")?;
+        for (i, (name, attr)) in node.attributes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write_attribute(f, name, attr, indent)?;
+        }
+        write!(f, ">")?;
+    }
+    let inputs: Vec<_> = node.inputs.iter().map(|i| Id(i).to_string()).collect();
+    writeln!(f, " ({})", inputs.join(", "))
+}
+
+fn write_attribute(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    attr: &Attribute,
+    indent: usize,
+) -> fmt::Result {
+    let annotated = |kind| format!("{}: {} = ", name, kind_name(kind));
+    let list = |f: &mut fmt::Formatter<'_>, kind, items: Vec<String>| {
+        if items.is_empty() {
+            write!(f, "{}[]", annotated(kind))
+        } else {
+            write!(f, "{} = [{}]", name, items.join(", "))
+        }
+    };
+    match attr {
+        Attribute::F32(v) => write!(f, "{} = {}", name, f32_literal(*v)),
+        Attribute::I64(v) => write!(f, "{} = {}", name, v),
+        Attribute::String(s) => write!(f, "{} = {}", name, Quoted(s)),
+        Attribute::F32s(vs) => list(
+            f,
+            AttributeKind::F32s,
+            vs.iter().map(|v| f32_literal(*v)).collect(),
+        ),
+        Attribute::I64s(vs) => list(
+            f,
+            AttributeKind::I64s,
+            vs.iter().map(|v| v.to_string()).collect(),
+        ),
+        Attribute::Strings(vs) => list(
+            f,
+            AttributeKind::Strings,
+            vs.iter().map(|v| Quoted(v).to_string()).collect(),
+        ),
+        Attribute::Tensor(t) => write!(f, "{} = {}", name, Constant(t)),
+        Attribute::Tensors(ts) => list(
+            f,
+            AttributeKind::Tensors,
+            ts.iter().map(|t| Constant(t).to_string()).collect(),
+        ),
+        Attribute::SparseTensor(s) => {
+            let dense = dense(s);
+            write!(
+                f,
+                "{}{}",
+                annotated(AttributeKind::SparseTensor),
+                Constant(&dense)
+            )
+        }
+        Attribute::SparseTensors(ss) => {
+            let dense: Vec<_> = ss.iter().map(|s| Constant(&dense(s)).to_string()).collect();
+            write!(
+                f,
+                "{}[{}]",
+                annotated(AttributeKind::SparseTensors),
+                dense.join(", ")
+            )
+        }
+        Attribute::Graph(g) => {
+            write!(f, "{} = ", name)?;
+            write_graph(f, g, indent)
+        }
+        Attribute::Graphs(gs) if gs.is_empty() => {
+            write!(f, "{}[]", annotated(AttributeKind::Graphs))
+        }
+        Attribute::Graphs(gs) => {
+            write!(f, "{} = [", name)?;
+            for (i, g) in gs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_graph(f, g, indent)?;
+            }
+            write!(f, "]")
+        }
+        Attribute::TypeProto(info) => {
+            write!(f, "{}{}", annotated(AttributeKind::TypeProto), Type(info))
+        }
+        Attribute::RefAttrName(reference, kind) => {
+            write!(f, "{}@{}", annotated(*kind), Id(reference))
+        }
+    }
+}
+
+fn write_function(f: &mut fmt::Formatter<'_>, function: &Function) -> fmt::Result {
+    let mut header = vec![
+        format!("domain: {}", Quoted(&function.operation.domain)),
+        format!("opset_import: {}", Opsets(&function.opsets)),
+    ];
+    if let Some(doc_string) = &function.doc_string {
+        header.push(format!("doc_string: {}", Quoted(doc_string)));
+    }
+    write_header(f, &header)?;
+    write!(f, "{}", Id(&function.operation.name))?;
+    if !function.attributes.is_empty() {
+        write!(f, " <")?;
+        for (i, (name, attr)) in function.attributes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match attr {
+                FunctionAttribute::Mandatory => write!(f, "{}", Id(name))?,
+                FunctionAttribute::Optional(attr) => write_attribute(f, name, attr, 0)?,
+            }
+        }
+        write!(f, ">")?;
+    }
+    let ids = |names: &[String]| {
+        names
+            .iter()
+            .map(|n| Id(n).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    writeln!(
+        f,
+        " ({}) => ({}) {{",
+        ids(&function.inputs),
+        ids(&function.outputs)
+    )?;
+    for node in function.nodes.iter() {
+        write_node(f, node, 1)?;
+    }
+    write!(f, "}}")
+}
+
+pub(crate) struct Type<'a>(pub(crate) &'a ValueInfo);
+
+impl fmt::Display for Type<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tensor = |f: &mut fmt::Formatter<'_>, info: &TensorInfo| {
+            write!(f, "{}", dtype_name(info.dtype))?;
+            if let Some(shape) = &info.shape {
+                let dims: Vec<_> = shape
+                    .iter()
+                    .map(|dim| match dim {
+                        Dim::Unknown => "?".to_string(),
+                        Dim::Fixed(n) => n.to_string(),
+                        Dim::Dynamic(name) => Id(name).to_string(),
+                    })
+                    .collect();
+                write!(f, "[{}]", dims.join(","))?;
+            }
+            Ok(())
+        };
+        match self.0 {
+            ValueInfo::Tensor(info) => tensor(f, info),
+            ValueInfo::SparseTensor(info) => {
+                write!(f, "sparse_tensor(")?;
+                tensor(f, info)?;
+                write!(f, ")")
+            }
+            ValueInfo::Sequence(info) => write!(f, "seq({})", Type(info)),
+            ValueInfo::Map(key, value) => {
+                write!(f, "map({}, {})", dtype_name(*key), Type(value))
+            }
+            ValueInfo::Optional(info) => write!(f, "optional({})", Type(info)),
+        }
+    }
+}
+
+/// Element type and shape of a tensor value.
+struct TensorType<'a>(&'a TensorValue);
+
+impl fmt::Display for TensorType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dims: Vec<_> = self.0.shape().iter().map(|d| d.to_string()).collect();
+        write!(f, "{}[{}]", dtype_name(self.0.dtype()), dims.join(","))
+    }
+}
+
+/// A tensor attribute, i.e. the type followed by the data.
+struct Constant<'a>(&'a Tensor);
+
+impl fmt::Display for Constant<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", TensorType(&self.0.tensor), Data(self.0))
+    }
+}
+
+/// The values of a tensor, or its external data reference if they are
+/// not available.
+struct Data<'a>(&'a Tensor);
+
+impl fmt::Display for Data<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = match &self.0.tensor {
+            TensorValue::Unloaded { .. } => return write_external(f, self.0.path.as_ref()),
+            TensorValue::Raw(raw) => match raw.decode() {
+                Ok(tensor) => return Data(&Tensor { tensor, path: None }).fmt(f),
+                Err(_) => return write_external(f, self.0.path.as_ref()),
+            },
+            TensorValue::F32(arr) => arr.iter().map(|v| f32_literal(*v)).collect(),
+            TensorValue::F64(arr) => arr.iter().map(|v| f64_literal(*v)).collect(),
+            TensorValue::F16(arr) => arr.iter().map(|v| f32_literal(v.to_f32())).collect(),
+            TensorValue::BF16(arr) => arr.iter().map(|v| f32_literal(v.to_f32())).collect(),
+            TensorValue::C64(arr) => arr
+                .iter()
+                .flat_map(|v| [f32_literal(v.re), f32_literal(v.im)])
+                .collect(),
+            TensorValue::C128(arr) => arr
+                .iter()
+                .flat_map(|v| [f64_literal(v.re), f64_literal(v.im)])
+                .collect(),
+            TensorValue::String(arr) => arr.iter().map(|v| Quoted(v).to_string()).collect(),
+            TensorValue::Bool(arr) => arr.iter().map(|v| (*v as u8).to_string()).collect(),
+            TensorValue::I8(arr) | TensorValue::I4(arr) => {
+                arr.iter().map(|v| v.to_string()).collect()
+            }
+            TensorValue::I16(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::I32(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::I64(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::U8(arr)
+            | TensorValue::U4(arr)
+            | TensorValue::F8E4M3FN(arr)
+            | TensorValue::F8E4M3FNUZ(arr)
+            | TensorValue::F8E5M2(arr)
+            | TensorValue::F8E5M2FNUZ(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::U16(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::U32(arr) => arr.iter().map(|v| v.to_string()).collect(),
+            TensorValue::U64(arr) => arr.iter().map(|v| v.to_string()).collect(),
+        };
+        write!(f, "{{{}}}", values.join(", "))
+    }
+}
+
+/// Write an external data reference, which is empty for tensors
+/// whose data is neither loaded nor stored externally.
+fn write_external(f: &mut fmt::Formatter<'_>, external: Option<&ExternalData>) -> fmt::Result {
+    let Some(external) = external else {
+        return write!(f, "[]");
+    };
+    let mut entries = vec![("location", external.location.to_string_lossy().into_owned())];
+    if let Some(offset) = external.offset {
+        entries.push(("offset", offset.to_string()));
+    }
+    if let Some(length) = external.length {
+        entries.push(("length", length.to_string()));
+    }
+    if let Some(checksum) = &external.checksum {
+        entries.push(("checksum", String::from_utf8_lossy(checksum).into()));
+    }
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|(k, v)| format!("{}: {}", Quoted(k), Quoted(v)))
+        .collect();
+    write!(f, "[{}]", entries.join(", "))
+}
+
+/// `sparse` in dense form. If it cannot be densified, e.g. as its
+/// data has not been loaded, the result is an unloaded tensor which is
+/// printed with an empty external data reference.
+fn dense(sparse: &SparseTensor) -> Tensor {
+    sparse.densify().unwrap_or_else(|_| Tensor {
+        tensor: TensorValue::Unloaded {
+            dtype: sparse.values.tensor.dtype(),
+            shape: sparse.dims.clone(),
+        },
+        path: None,
+    })
+}
+
+/// Parse a model in the textual syntax.
+///
+/// ```
+/// use onnx_protos::Model;
+///
+/// let model: Model = r#"
+///     <ir_version: 9, opset_import: ["" : 19]>
+///     agraph (float[N] a) => (float[N] b) {
+///         b = Abs (a)
+///     }
+/// "#
+/// .parse()
+/// .unwrap();
+/// assert_eq!(model.graph.nodes[0].operation.name, "Abs");
+/// assert_eq!(model.to_string().parse::<Model>().unwrap(), model);
+/// ```
+impl FromStr for Model {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(text)?;
+        let model = parser.model()?;
+        parser.finish()?;
+        Ok(model)
+    }
+}
+
+impl FromStr for Graph {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(text)?;
+        let graph = parser.graph()?;
+        parser.finish()?;
+        Ok(graph)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Identifier, possibly qualified by a domain such as `ai.onnx.Abs`.
+    Id(String),
+    Str(String),
+    /// Numeric literal, including `-inf`.
+    Num(String),
+    Punct(char),
+    /// `=>`
+    Arrow,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        let rest = &text[start..];
+        let take_while = |chars: &mut std::iter::Peekable<std::str::CharIndices>,
+                          f: &dyn Fn(char) -> bool| {
+            let mut end = text.len();
+            while let Some(&(i, c)) = chars.peek() {
+                if !f(c) {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            text[start..end].to_string()
+        };
+        let token = if c.is_whitespace() {
+            chars.next();
+            continue;
+        } else if c == '#' {
+            take_while(&mut chars, &|c| c != '\n');
+            continue;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            Token::Id(take_while(&mut chars, &|c| {
+                c.is_ascii_alphanumeric() || c == '_' || c == '.'
+            }))
+        } else if c.is_ascii_digit()
+            || (matches!(c, '-' | '+' | '.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            chars.next();
+            let mut prev = c;
+            let mut end = text.len();
+            while let Some(&(i, c)) = chars.peek() {
+                let sign = matches!(c, '+' | '-') && matches!(prev, 'e' | 'E');
+                if !(c.is_ascii_alphanumeric() || c == '.' || sign) {
+                    end = i;
+                    break;
+                }
+                prev = c;
+                chars.next();
+            }
+            Token::Num(text[start..end].to_string())
+        } else if c == '-' && rest[1..].starts_with("inf") {
+            chars.nth(3);
+            Token::Num("-inf".into())
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => s.push('\n'),
+                        Some((_, 't')) => s.push('\t'),
+                        Some((_, 'r')) => s.push('\r'),
+                        Some((_, c)) => s.push(c),
+                        None => break,
+                    },
+                    Some((_, c)) => s.push(c),
+                    None => {
+                        return Err(Error::new_validation(format!(
+                            "{}: Unterminated string.",
+                            position(text, start)
+                        )))
+                    }
+                }
+            }
+            Token::Str(s)
+        } else if c == '=' && rest[1..].starts_with('>') {
+            chars.nth(1);
+            Token::Arrow
+        } else if "()[]{}<>,:=@?".contains(c) {
+            chars.next();
+            Token::Punct(c)
+        } else {
+            return Err(Error::new_validation(format!(
+                "{}: Unexpected character `{}`.",
+                position(text, start),
+                c
+            )));
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+fn position(text: &str, offset: usize) -> String {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    format!("Line {}, column {}", line, column)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Result<Self, Error> {
+        Ok(Self {
+            text,
+            tokens: tokenize(text)?,
+            pos: 0,
+        })
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let offset = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.text.len(), |(_, offset)| *offset);
+        Error::new_validation(format!("{}: {}", position(self.text, offset), msg))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.error("Unexpected end of input."))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let is = self.is(c);
+        if is {
+            self.pos += 1;
+        }
+        is
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if !self.eat(c) {
+            return Err(self.error(&format!("Expected `{}`.", c)));
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("Expected end of input.")),
+        }
+    }
+
+    fn is_name(&self) -> bool {
+        matches!(self.peek(), Some(Token::Id(_) | Token::Str(_)))
+    }
+
+    /// An identifier or quoted string.
+    fn name(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Id(s) | Token::Str(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.error("Expected a name.")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Str(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.error("Expected a string.")),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        match self.peek() {
+            Some(Token::Num(s)) => {
+                let value = s.parse().map_err(|_| self.error("Expected an integer."))?;
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.error("Expected an integer.")),
+        }
+    }
+
+    /// Comma separated `item`s enclosed by `open` and `close`.
+    fn list<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        self.expect(open)?;
+        let mut items = vec![];
+        if !self.eat(close) {
+            loop {
+                items.push(item(self)?);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(close)?;
+        }
+        Ok(items)
+    }
+
+    /// Names of node inputs or outputs, where missing optional ones
+    /// are left empty.
+    fn optional_names(&mut self, end: char) -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        if self.is(end) {
+            return Ok(names);
+        }
+        loop {
+            names.push(if self.is_name() {
+                self.name()?
+            } else {
+                String::new()
+            });
+            if !self.eat(',') {
+                return Ok(names);
+            }
+        }
+    }
+
+    fn opsets(&mut self) -> Result<IndexMap<String, i64>, Error> {
+        let opsets = self.list('[', ']', |p| {
+            let domain = p.string()?;
+            p.expect(':')?;
+            Ok((domain, p.int()?))
+        })?;
+        Ok(opsets.into_iter().collect())
+    }
+
+    /// Key-value pairs of the header of a model or function.
+    fn header(
+        &mut self,
+        mut entry: impl FnMut(&mut Self, &str) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.is('<') {
+            self.list('<', '>', |p| {
+                let key = p.name()?;
+                p.expect(':')?;
+                entry(p, &key)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn model(&mut self) -> Result<Model, Error> {
+        let mut model = Model::default();
+        self.header(|p, key| {
+            match key {
+                "ir_version" => model.ir_version = p.int()?,
+                "opset_import" => model.opsets = p.opsets()?,
+                "producer_name" => model.producer_name = Some(p.string()?),
+                "producer_version" => model.producer_version = Some(p.string()?),
+                "domain" => model.domain = Some(p.string()?),
+                "model_version" => model.model_version = p.int()?,
+                "doc_string" => model.doc_string = Some(p.string()?),
+                "metadata_props" => {
+                    let props = p.list('[', ']', |p| {
+                        let key = p.string()?;
+                        p.expect(':')?;
+                        Ok((key, p.string()?))
+                    })?;
+                    model.metadata = props.into_iter().collect();
+                }
+                _ => return Err(p.error(&format!("Unknown model field `{}`.", key))),
+            }
+            Ok(())
+        })?;
+        model.graph = self.graph()?;
+        while self.peek().is_some() {
+            model.functions.push(self.function()?);
+        }
+        Ok(model)
+    }
+
+    fn graph(&mut self) -> Result<Graph, Error> {
+        let mut graph = Graph {
+            name: self.name()?,
+            ..Default::default()
+        };
+        let inputs = self.list('(', ')', Self::value_info_or_initializer)?;
+        for (info, name, data) in inputs {
+            if let Some(data) = data {
+                graph.initializers.insert(name.clone(), data);
+            }
+            graph.inputs.push(Input {
+                name,
+                info,
+                doc_string: None,
+                denotation: None,
+            });
+        }
+        if self.next()? != Token::Arrow {
+            self.pos -= 1;
+            return Err(self.error("Expected `=>`."));
+        }
+        graph.outputs = self.list('(', ')', |p| {
+            Ok(Output {
+                info: p.type_()?,
+                name: p.name()?,
+                doc_string: None,
+                denotation: None,
+            })
+        })?;
+        if self.is('<') {
+            for (info, name, data) in self.list('<', '>', Self::value_info_or_initializer)? {
+                match (info, data) {
+                    (ValueInfo::SparseTensor(_), Some(data)) => {
+                        let sparse = SparseTensor::sparsify(&data)?;
+                        graph.sparse_initializers.insert(name, sparse);
+                    }
+                    (_, Some(data)) => {
+                        graph.initializers.insert(name, data);
+                    }
+                    (info, None) => {
+                        graph.edge_infos.insert(name, info);
+                    }
+                }
+            }
+        }
+        self.expect('{')?;
+        while !self.eat('}') {
+            graph.nodes.push(self.node()?);
+        }
+        Ok(graph)
+    }
+
+    fn value_info_or_initializer(&mut self) -> Result<(ValueInfo, String, Option<Tensor>), Error> {
+        let info = self.type_()?;
+        let name = self.name()?;
+        if !self.eat('=') {
+            return Ok((info, name, None));
+        }
+        let (ValueInfo::Tensor(tensor) | ValueInfo::SparseTensor(tensor)) = &info else {
+            return Err(self.error("Only tensors can be initialized."));
+        };
+        let shape = tensor
+            .shape
+            .as_ref()
+            .and_then(|dims| {
+                dims.iter()
+                    .map(|dim| match dim {
+                        Dim::Fixed(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| self.error("Initializers must have a fixed shape."))?;
+        let data = self.data(tensor.dtype, shape)?;
+        Ok((info, name, Some(data)))
+    }
+
+    fn dtype(&mut self) -> Result<Dtype, Error> {
+        let name = self.name()?;
+        DTYPES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(dtype, _)| *dtype)
+            .ok_or_else(|| {
+                self.pos -= 1;
+                self.error(&format!("Unknown type `{}`.", name))
+            })
+    }
+
+    fn tensor_type(&mut self) -> Result<TensorInfo, Error> {
+        let dtype = self.dtype()?;
+        let shape = if self.is('[') {
+            Some(self.list('[', ']', |p| match p.next()? {
+                Token::Punct('?') => Ok(Dim::Unknown),
+                Token::Id(name) | Token::Str(name) => Ok(Dim::Dynamic(name)),
+                Token::Num(n) => n.parse().map(Dim::Fixed).map_err(|_| {
+                    p.pos -= 1;
+                    p.error("Expected a dimension.")
+                }),
+                _ => {
+                    p.pos -= 1;
+                    Err(p.error("Expected a dimension."))
+                }
+            })?)
+        } else {
+            None
+        };
+        Ok(TensorInfo { shape, dtype })
+    }
+
+    fn type_(&mut self) -> Result<ValueInfo, Error> {
+        let nested = |p: &mut Self| -> Result<ValueInfo, Error> {
+            p.expect('(')?;
+            let info = p.type_()?;
+            p.expect(')')?;
+            Ok(info)
+        };
+        Ok(match self.peek() {
+            Some(Token::Id(id)) if id == "seq" => {
+                self.pos += 1;
+                ValueInfo::Sequence(Box::new(nested(self)?))
+            }
+            Some(Token::Id(id)) if id == "optional" => {
+                self.pos += 1;
+                ValueInfo::Optional(Box::new(nested(self)?))
+            }
+            Some(Token::Id(id)) if id == "sparse_tensor" => {
+                self.pos += 1;
+                self.expect('(')?;
+                let info = self.tensor_type()?;
+                self.expect(')')?;
+                ValueInfo::SparseTensor(info)
+            }
+            Some(Token::Id(id)) if id == "map" => {
+                self.pos += 1;
+                self.expect('(')?;
+                let key = self.dtype()?;
+                self.expect(',')?;
+                let value = self.type_()?;
+                self.expect(')')?;
+                ValueInfo::Map(key, Box::new(value))
+            }
+            _ => ValueInfo::Tensor(self.tensor_type()?),
+        })
+    }
+
+    /// Values in braces or an external data reference.
+    fn data(&mut self, dtype: Dtype, shape: Vec<usize>) -> Result<Tensor, Error> {
+        if self.is('[') {
+            let mut external = ExternalData::new(PathBuf::new());
+            self.list('[', ']', |p| {
+                let key = p.string()?;
+                p.expect(':')?;
+                let value = p.string()?;
+                let number = |p: &Self| {
+                    value
+                        .parse()
+                        .map_err(|_| p.error(&format!("Invalid `{}` of external data.", key)))
+                };
+                match key.as_str() {
+                    "location" => external.location = PathBuf::from(&value),
+                    "offset" => external.offset = Some(number(p)?),
+                    "length" => external.length = Some(number(p)?),
+                    "checksum" => external.checksum = Some(value.into_bytes()),
+                    _ => return Err(p.error(&format!("Unknown external data field `{}`.", key))),
+                }
+                Ok(())
+            })?;
+            return Ok(Tensor {
+                tensor: TensorValue::Unloaded { dtype, shape },
+                path: Some(external),
+            });
+        }
+
+        let start = self.pos;
+        let literals = self.list('{', '}', |p| match p.next()? {
+            Token::Num(s) | Token::Id(s) | Token::Str(s) => Ok(s),
+            _ => {
+                p.pos -= 1;
+                Err(p.error("Expected a value."))
+            }
+        })?;
+        let error = |p: &mut Self, msg: &str| {
+            p.pos = start;
+            Err(p.error(msg))
+        };
+        let components = if matches!(dtype, Dtype::C64 | Dtype::C128) {
+            2
+        } else {
+            1
+        };
+        let Some(len) = num_elements(&shape)
+            .ok()
+            .and_then(|len| len.checked_mul(components))
+        else {
+            return error(self, &format!("Too many elements for shape {:?}.", shape));
+        };
+        if literals.len() != len {
+            return error(
+                self,
+                &format!(
+                    "Expected {} values for shape {:?} but found {}.",
+                    len,
+                    shape,
+                    literals.len()
+                ),
+            );
+        }
+        let Some(tensor) = tensor_value(dtype, &shape, &literals) else {
+            return error(
+                self,
+                &format!("Invalid values for `{}`.", dtype_name(dtype)),
+            );
+        };
+        Ok(Tensor { tensor, path: None })
+    }
+
+    /// A tensor type followed by its data, optionally separated by a
+    /// name and `=`.
+    fn constant(&mut self) -> Result<Tensor, Error> {
+        let info = self.tensor_type()?;
+        let shape = info
+            .shape
+            .unwrap_or_default()
+            .into_iter()
+            .map(|dim| match dim {
+                Dim::Fixed(n) => Ok(n),
+                _ => Err(self.error("Constants must have a fixed shape.")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.is_name() {
+            self.name()?;
+        }
+        self.eat('=');
+        self.data(info.dtype, shape)
+    }
+
+    fn node(&mut self) -> Result<Node, Error> {
+        let mut node = Node::default();
+        if self.eat('[') {
+            node.name = self.name()?;
+            self.expect(']')?;
+        }
+        node.outputs = self.optional_names('=')?;
+        self.expect('=')?;
+        let Token::Id(op) = self.next()? else {
+            self.pos -= 1;
+            return Err(self.error("Expected an operator."));
+        };
+        let (domain, name) = op.rsplit_once('.').unwrap_or(("", &op));
+        node.operation = Operation {
+            name: name.to_string(),
+            domain: domain.to_string(),
+        };
+        if self.eat(':') {
+            node.overload = Some(self.name()?);
+        }
+        if self.is('<') {
+            node.attributes = self.attributes()?;
+        }
+        self.expect('(')?;
+        node.inputs = self.optional_names(')')?;
+        self.expect(')')?;
+        if node.attributes.is_empty() && self.is('<') {
+            node.attributes = self.attributes()?;
+        }
+        Ok(node)
+    }
+
+    fn attributes(&mut self) -> Result<IndexMap<String, Attribute>, Error> {
+        let attributes = self.list('<', '>', |p| {
+            let name = p.name()?;
+            let kind = p.attribute_kind()?;
+            p.expect('=')?;
+            Ok((name, p.attribute(kind)?))
+        })?;
+        Ok(attributes.into_iter().collect())
+    }
+
+    /// Optional `: kind` annotation.
+    fn attribute_kind(&mut self) -> Result<Option<AttributeKind>, Error> {
+        if !self.eat(':') {
+            return Ok(None);
+        }
+        let name = self.name()?;
+        KINDS
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(kind, _)| Some(*kind))
+            .ok_or_else(|| {
+                self.pos -= 1;
+                self.error(&format!("Unknown attribute type `{}`.", name))
+            })
+    }
+
+    fn attribute(&mut self, kind: Option<AttributeKind>) -> Result<Attribute, Error> {
+        if self.eat('@') {
+            let reference = self.name()?;
+            let kind = kind.ok_or_else(|| self.error("Attribute references need a type."))?;
+            return Ok(Attribute::RefAttrName(reference, kind));
+        }
+        let sparse = |p: &mut Self| SparseTensor::sparsify(&p.constant()?);
+        match kind {
+            Some(AttributeKind::TypeProto) => return Ok(Attribute::TypeProto(self.type_()?)),
+            Some(AttributeKind::SparseTensor) => return Ok(Attribute::SparseTensor(sparse(self)?)),
+            Some(AttributeKind::SparseTensors) => {
+                return Ok(Attribute::SparseTensors(self.list('[', ']', sparse)?))
+            }
+            _ => {}
+        }
+
+        let start = self.pos;
+        let attr = if self.is('[') {
+            let items = self.list('[', ']', Self::single_attribute)?;
+            let all = |f: fn(&Attribute) -> bool| items.iter().all(f);
+            if items.is_empty() {
+                match kind {
+                    Some(AttributeKind::I64s) => Attribute::I64s(vec![]),
+                    Some(AttributeKind::F32s) => Attribute::F32s(vec![]),
+                    Some(AttributeKind::Strings) => Attribute::Strings(vec![]),
+                    Some(AttributeKind::Tensors) => Attribute::Tensors(vec![]),
+                    Some(AttributeKind::Graphs) => Attribute::Graphs(vec![]),
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("Empty lists need a type."));
+                    }
+                }
+            } else if all(|a| matches!(a, Attribute::I64(_))) && kind != Some(AttributeKind::F32s) {
+                Attribute::I64s(items.into_iter().filter_map(as_i64).collect())
+            } else if all(|a| matches!(a, Attribute::I64(_) | Attribute::F32(_))) {
+                Attribute::F32s(items.into_iter().filter_map(as_f32).collect())
+            } else if all(|a| matches!(a, Attribute::String(_))) {
+                Attribute::Strings(
+                    items
+                        .into_iter()
+                        .filter_map(|a| match a {
+                            Attribute::String(s) => Some(s),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            } else if all(|a| matches!(a, Attribute::Tensor(_))) {
+                Attribute::Tensors(
+                    items
+                        .into_iter()
+                        .filter_map(|a| match a {
+                            Attribute::Tensor(t) => Some(t),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            } else if all(|a| matches!(a, Attribute::Graph(_))) {
+                Attribute::Graphs(
+                    items
+                        .into_iter()
+                        .filter_map(|a| match a {
+                            Attribute::Graph(g) => Some(g),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            } else {
+                self.pos = start;
+                return Err(self.error("Lists must not mix values of different types."));
+            }
+        } else {
+            match (self.single_attribute()?, kind) {
+                (Attribute::I64(v), Some(AttributeKind::F32)) => Attribute::F32(v as f32),
+                (attr, _) => attr,
+            }
+        };
+        Ok(attr)
+    }
+
+    fn single_attribute(&mut self) -> Result<Attribute, Error> {
+        let is_graph = self.peek_at(1) == Some(&Token::Punct('('));
+        Ok(match self.peek().cloned() {
+            Some(Token::Num(s)) => {
+                let is_float = s.contains(['.', 'e', 'E']) || s.ends_with("inf");
+                let attr = if is_float {
+                    s.parse().ok().map(Attribute::F32)
+                } else {
+                    s.parse().ok().map(Attribute::I64)
+                };
+                let attr = attr.ok_or_else(|| self.error("Invalid number."))?;
+                self.pos += 1;
+                attr
+            }
+            Some(Token::Id(s)) if s == "inf" || s == "nan" => {
+                self.pos += 1;
+                Attribute::F32(s.parse().unwrap())
+            }
+            Some(Token::Str(_) | Token::Id(_)) if is_graph => Attribute::Graph(self.graph()?),
+            Some(Token::Str(s)) => {
+                self.pos += 1;
+                Attribute::String(s)
+            }
+            Some(Token::Id(_)) => Attribute::Tensor(self.constant()?),
+            _ => return Err(self.error("Expected an attribute value.")),
+        })
+    }
+
+    fn function(&mut self) -> Result<Function, Error> {
+        let mut function = Function {
+            operation: Operation::default(),
+            doc_string: None,
+            attributes: IndexMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+            nodes: vec![],
+            opsets: IndexMap::new(),
+            unknown_fields: UnknownFields::default(),
+        };
+        self.header(|p, key| {
+            match key {
+                "domain" => function.operation.domain = p.string()?,
+                "opset_import" => function.opsets = p.opsets()?,
+                "doc_string" => function.doc_string = Some(p.string()?),
+                _ => return Err(p.error(&format!("Unknown function field `{}`.", key))),
+            }
+            Ok(())
+        })?;
+        function.operation.name = self.name()?;
+        if self.is('<') {
+            let attributes = self.list('<', '>', |p| {
+                let name = p.name()?;
+                let kind = p.attribute_kind()?;
+                if p.eat('=') {
+                    Ok((name, FunctionAttribute::Optional(p.attribute(kind)?)))
+                } else {
+                    Ok((name, FunctionAttribute::Mandatory))
+                }
+            })?;
+            function.attributes = attributes.into_iter().collect();
+        }
+        function.inputs = self.list('(', ')', Self::name)?;
+        if self.next()? != Token::Arrow {
+            self.pos -= 1;
+            return Err(self.error("Expected `=>`."));
+        }
+        function.outputs = self.list('(', ')', Self::name)?;
+        self.expect('{')?;
+        while !self.eat('}') {
+            function.nodes.push(self.node()?);
+        }
+        Ok(function)
+    }
+}
+
+fn as_i64(attr: Attribute) -> Option<i64> {
+    match attr {
+        Attribute::I64(v) => Some(v),
+        _ => None,
+    }
+}
+
+fn as_f32(attr: Attribute) -> Option<f32> {
+    match attr {
+        Attribute::I64(v) => Some(v as f32),
+        Attribute::F32(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Parse `literals` as values of type `dtype`, returning `None` if
+/// any of them is invalid.
+fn tensor_value(dtype: Dtype, shape: &[usize], literals: &[String]) -> Option<TensorValue> {
+    fn parse<T: FromStr>(literals: &[String]) -> Option<Vec<T>> {
+        literals.iter().map(|l| l.parse().ok()).collect()
+    }
+    fn array<T>(shape: &[usize], values: Vec<T>) -> ArrayD<T> {
+        ArrayD::from_shape_vec(IxDyn(shape), values).unwrap()
+    }
+    fn complex<T: Copy>(parts: Vec<T>) -> Vec<num_complex::Complex<T>> {
+        parts
+            .chunks_exact(2)
+            .map(|c| num_complex::Complex::new(c[0], c[1]))
+            .collect()
+    }
+    let bits = |literals: &[String]| parse::<u8>(literals).map(|v| array(shape, v));
+    Some(match dtype {
+        Dtype::U8 => TensorValue::U8(bits(literals)?),
+        Dtype::U16 => TensorValue::U16(array(shape, parse(literals)?)),
+        Dtype::U32 => TensorValue::U32(array(shape, parse(literals)?)),
+        Dtype::U64 => TensorValue::U64(array(shape, parse(literals)?)),
+        Dtype::I8 => TensorValue::I8(array(shape, parse(literals)?)),
+        Dtype::I16 => TensorValue::I16(array(shape, parse(literals)?)),
+        Dtype::I32 => TensorValue::I32(array(shape, parse(literals)?)),
+        Dtype::I64 => TensorValue::I64(array(shape, parse(literals)?)),
+        Dtype::F32 => TensorValue::F32(array(shape, parse(literals)?)),
+        Dtype::F64 => TensorValue::F64(array(shape, parse(literals)?)),
+        Dtype::String => TensorValue::String(array(shape, literals.to_vec())),
+        Dtype::Bool => TensorValue::Bool(array(
+            shape,
+            literals
+                .iter()
+                .map(|l| match l.as_str() {
+                    "0" | "false" => Some(false),
+                    "1" | "true" => Some(true),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        )),
+        Dtype::F16 => TensorValue::F16(array(
+            shape,
+            parse::<f32>(literals)?
+                .into_iter()
+                .map(f16::from_f32)
+                .collect(),
+        )),
+        Dtype::BF16 => TensorValue::BF16(array(
+            shape,
+            parse::<f32>(literals)?
+                .into_iter()
+                .map(bf16::from_f32)
+                .collect(),
+        )),
+        Dtype::C64 => TensorValue::C64(array::<Complex32>(shape, complex(parse(literals)?))),
+        Dtype::C128 => TensorValue::C128(array::<Complex64>(shape, complex(parse(literals)?))),
+        Dtype::F8E4M3FN => TensorValue::F8E4M3FN(bits(literals)?),
+        Dtype::F8E4M3FNUZ => TensorValue::F8E4M3FNUZ(bits(literals)?),
+        Dtype::F8E5M2 => TensorValue::F8E5M2(bits(literals)?),
+        Dtype::F8E5M2FNUZ => TensorValue::F8E5M2FNUZ(bits(literals)?),
+        Dtype::U4 => TensorValue::U4(bits(literals)?),
+        Dtype::I4 => TensorValue::I4(array(shape, parse(literals)?)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::GraphBuilder;
+    use Dtype::F32;
+
+    fn info(dtype: Dtype, shape: &[Dim]) -> ValueInfo {
+        ValueInfo::Tensor(TensorInfo {
+            shape: Some(shape.to_vec()),
+            dtype,
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let model: Model = r#"
+            <
+               ir_version: 7,
+               opset_import: ["" : 10, "custom" : 1],
+               producer_name: "test"
+            >
+            agraph (float[N, 128] X, float[128, 10] W, float[10] B) => (float[N, 10] C) {
+               T = MatMul(X, W)  # comment
+               S = Add(T, B)
+               [last] C = Softmax <axis = -1> ("S")
+               C2, = custom.Op:v2 (, S) <pads = [1, 2], alpha = 1e-3, mode = "x">
+            }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(model.ir_version, 7);
+        assert_eq!(model.opsets["custom"], 1);
+        assert_eq!(model.producer_name.as_deref(), Some("test"));
+        let graph = &model.graph;
+        assert_eq!(graph.name, "agraph");
+        assert_eq!(
+            graph.inputs[0].info,
+            info(F32, &[Dim::Dynamic("N".into()), Dim::Fixed(128)])
+        );
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.nodes[2].name, "last");
+        assert_eq!(graph.nodes[2].attributes["axis"], Attribute::I64(-1));
+        let op = &graph.nodes[3];
+        assert_eq!(op.operation.domain, "custom");
+        assert_eq!(op.overload.as_deref(), Some("v2"));
+        assert_eq!(op.inputs, ["", "S"]);
+        assert_eq!(op.outputs, ["C2", ""]);
+        assert_eq!(op.attributes["pads"], Attribute::I64s(vec![1, 2]));
+        assert_eq!(op.attributes["alpha"], Attribute::F32(1e-3));
+
+        let err = "g (float x) => (float y) {\n   y = Abs x\n}"
+            .parse::<Graph>()
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 2, column 12"), "{}", err);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut branch = GraphBuilder::new("then");
+        let c = branch.initializer("c", ndarray::arr1(&[f16::from_f32(0.5)]).into_dyn());
+        let [n] = branch.node("Cast", "", &[&c], [("to", Attribute::I64(1))]);
+        branch.output(&n, info(F32, &[Dim::Fixed(1)]));
+
+        let mut b = GraphBuilder::new("main graph")
+            .opset("", 19)
+            .opset("custom", 1);
+        let x = b.input("x", F32, &[Dim::Dynamic("batch size".into()), Dim::Unknown]);
+        let flag = b.input("flag", Dtype::Bool, &[]);
+        let w = b.initializer(
+            "w",
+            ndarray::arr2(&[[1f32, -2.5], [f32::INFINITY, 1e-8]]).into_dyn(),
+        );
+        let s = b.initializer(
+            "s",
+            ndarray::arr1(&["a \"quoted\"\n".to_string()]).into_dyn(),
+        );
+        let [m] = b.node("MatMul", "", &[&x, &w], []);
+        let [cond] = b.node(
+            "If",
+            "",
+            &[&flag],
+            [
+                ("then_branch", Attribute::Graph(branch.build())),
+                (
+                    "else_branch",
+                    Attribute::Graph(
+                        "e () => (float[1] z) {\n z = Constant <value_float = 1.0> ()\n}"
+                            .parse()
+                            .unwrap(),
+                    ),
+                ),
+            ],
+        );
+        let attrs = [
+            ("f", Attribute::F32(2.0)),
+            ("fs", Attribute::F32s(vec![])),
+            ("is", Attribute::I64s(vec![1, 2])),
+            ("ss", Attribute::Strings(vec!["x".into()])),
+            (
+                "t",
+                Attribute::Tensor(Tensor {
+                    tensor: ndarray::arr0(3i64).into_dyn().into(),
+                    path: None,
+                }),
+            ),
+            (
+                "type",
+                Attribute::TypeProto(ValueInfo::Sequence(Box::new(ValueInfo::Map(
+                    Dtype::String,
+                    Box::new(info(F32, &[])),
+                )))),
+            ),
+            (
+                "sparse",
+                Attribute::SparseTensor(
+                    SparseTensor::sparsify(&Tensor {
+                        tensor: ndarray::arr1(&[0f64, 1.0, 0.0]).into_dyn().into(),
+                        path: None,
+                    })
+                    .unwrap(),
+                ),
+            ),
+        ];
+        let [y] = b.node("Op", "custom", &[&m, &cond, &s], attrs);
+        b.output(&y, ValueInfo::Optional(Box::new(info(F32, &[]))));
+        let mut model = b.into_model().unwrap();
+        model.metadata.insert("key".into(), "value".into());
+        model.doc_string = Some("A model.".into());
+        model
+            .graph
+            .edge_infos
+            .insert(m.name().into(), info(F32, &[Dim::Unknown]));
+        model.graph.initializers.insert(
+            "external".into(),
+            Tensor {
+                tensor: TensorValue::Unloaded {
+                    dtype: Dtype::I32,
+                    shape: vec![2],
+                },
+                path: Some(ExternalData {
+                    location: "data.bin".into(),
+                    offset: Some(0),
+                    length: Some(8),
+                    checksum: None,
+                }),
+            },
+        );
+        model.functions.push(function());
+
+        let text = model.to_string();
+        let parsed: Model = text.parse().unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(parsed, model, "{}", text);
+        assert_eq!(parsed.to_string(), text);
+    }
+
+    #[test]
+    fn test_unavailable_data() {
+        let err = "g () => () <float[4294967296,4294967296] w = {1.0}> {}"
+            .parse::<Graph>()
+            .unwrap_err();
+        assert!(err.to_string().contains("Too many elements"), "{}", err);
+
+        // Unloaded data without a location and sparse tensors which
+        // cannot be densified are printed as placeholders
+        let unloaded = Tensor {
+            tensor: TensorValue::Unloaded {
+                dtype: F32,
+                shape: vec![2],
+            },
+            path: None,
+        };
+        let sparse = SparseTensor {
+            values: unloaded.clone(),
+            indices: unloaded.clone(),
+            dims: vec![4],
+        };
+        let mut graph: Graph = "g () => () {\n y = Constant <value = float[2] {1.0, 2.0}> ()\n}"
+            .parse()
+            .unwrap();
+        graph.initializers.insert("w".into(), unloaded);
+        graph.sparse_initializers.insert("s".into(), sparse.clone());
+        (graph.nodes[0].attributes).insert("sparse".into(), Attribute::SparseTensor(sparse));
+        let text = graph.to_string();
+        assert!(text.contains("float[2] w = []"), "{}", text);
+        assert!(text.contains("sparse_tensor(float[4]) s = []"), "{}", text);
+        assert!(
+            text.contains("sparse: sparse_tensor = float[4] []"),
+            "{}",
+            text
+        );
+    }
+
+    fn function() -> Function {
+        let model: Model = r#"
+            <opset_import: ["" : 19]>
+            g () => () {}
+            <domain: "custom", opset_import: ["" : 19]>
+            Op <alpha, beta: float = 1> (a, b) => (c) {
+               c = Add <unused: float = @alpha> (a, b)
+            }
+        "#
+        .parse()
+        .unwrap();
+        let function = model.functions[0].clone();
+        assert_eq!(
+            function.attributes["beta"],
+            FunctionAttribute::Optional(Attribute::F32(1.0))
+        );
+        function
+    }
+}
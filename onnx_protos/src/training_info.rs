@@ -0,0 +1,84 @@
+use indexmap::IndexMap;
+
+use crate::protos::{StringStringEntryProto, TrainingInfoProto};
+
+use crate::{Error, Graph, UnknownFields};
+
+/// Initialization and training step of a trainable model.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TrainingInfo {
+    /// Graph without inputs computing the initial values of tensors.
+    pub initialization: Option<Graph>,
+    /// Graph computing a single training step.
+    pub algorithm: Graph,
+    /// Initializer name -> output of `initialization` assigned to it.
+    pub initialization_binding: IndexMap<String, String>,
+    /// Initializer name -> output of `algorithm` assigned to it after
+    /// every step.
+    pub update_binding: IndexMap<String, String>,
+    pub unknown_fields: UnknownFields,
+}
+
+impl TryFrom<TrainingInfoProto> for TrainingInfo {
+    type Error = Error;
+
+    fn try_from(proto: TrainingInfoProto) -> Result<Self, Self::Error> {
+        let unknown_fields = UnknownFields::new(proto.special_fields.unknown_fields(), &[]);
+        Ok(Self {
+            initialization: proto
+                .initialization
+                .into_option()
+                .map(TryInto::try_into)
+                .transpose()?,
+            algorithm: proto
+                .algorithm
+                .into_option()
+                .ok_or_else(|| {
+                    Error::new_validation("Training info must define an algorithm.".into())
+                })?
+                .try_into()?,
+            initialization_binding: bindings(proto.initialization_binding),
+            update_binding: bindings(proto.update_binding),
+            unknown_fields,
+        })
+    }
+}
+
+impl From<TrainingInfo> for TrainingInfoProto {
+    fn from(info: TrainingInfo) -> Self {
+        (&info).into()
+    }
+}
+
+impl From<&TrainingInfo> for TrainingInfoProto {
+    fn from(info: &TrainingInfo) -> Self {
+        let mut proto = Self {
+            initialization: info.initialization.as_ref().map(Into::into).into(),
+            algorithm: Some((&info.algorithm).into()).into(),
+            initialization_binding: binding_protos(&info.initialization_binding),
+            update_binding: binding_protos(&info.update_binding),
+            ..Default::default()
+        };
+        info.unknown_fields
+            .write_to(proto.special_fields.mut_unknown_fields());
+        proto
+    }
+}
+
+fn bindings(entries: Vec<StringStringEntryProto>) -> IndexMap<String, String> {
+    entries
+        .into_iter()
+        .map(|entry| (entry.key, entry.value))
+        .collect()
+}
+
+fn binding_protos(bindings: &IndexMap<String, String>) -> Vec<StringStringEntryProto> {
+    bindings
+        .iter()
+        .map(|(key, value)| StringStringEntryProto {
+            key: key.clone(),
+            value: value.clone(),
+            ..Default::default()
+        })
+        .collect()
+}
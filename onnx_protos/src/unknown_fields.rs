@@ -0,0 +1,56 @@
+use protobuf::UnknownValueRef;
+
+/// Protobuf fields which are not part of the vendored `onnx.proto3`,
+/// e.g. because they were introduced by a later IR version. They are
+/// kept as they are and written back unchanged.
+///
+/// Only a [`Model`](crate::Model), [`Graph`](crate::Graph),
+/// [`Node`](crate::Node), [`Function`](crate::Function) and
+/// [`TrainingInfo`](crate::TrainingInfo) keep their unknown fields.
+/// Those of tensors, value infos, types and attributes are dropped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnknownFields(protobuf::UnknownFields);
+
+impl UnknownFields {
+    /// The fields of `fields` except for those with the given numbers.
+    pub(crate) fn new(fields: &protobuf::UnknownFields, except: &[u32]) -> Self {
+        let mut kept = protobuf::UnknownFields::new();
+        for (number, value) in fields.iter() {
+            if !except.contains(&number) {
+                add(&mut kept, number, value);
+            }
+        }
+        Self(kept)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().next().is_none()
+    }
+
+    /// Add all fields to those of a message.
+    pub(crate) fn write_to(&self, fields: &mut protobuf::UnknownFields) {
+        for (number, value) in self.0.iter() {
+            add(fields, number, value);
+        }
+    }
+}
+
+fn add(fields: &mut protobuf::UnknownFields, number: u32, value: UnknownValueRef) {
+    match value {
+        UnknownValueRef::Fixed32(v) => fields.add_fixed32(number, v),
+        UnknownValueRef::Fixed64(v) => fields.add_fixed64(number, v),
+        UnknownValueRef::Varint(v) => fields.add_varint(number, v),
+        UnknownValueRef::LengthDelimited(v) => fields.add_length_delimited(number, v.to_vec()),
+    }
+}
+
+/// All length-delimited values of the field `number`.
+pub(crate) fn length_delimited(fields: &protobuf::UnknownFields, number: u32) -> Vec<&[u8]> {
+    fields
+        .iter()
+        .filter_map(|(n, value)| match value {
+            UnknownValueRef::LengthDelimited(v) if n == number => Some(v),
+            _ => None,
+        })
+        .collect()
+}
@@ -2,6 +2,7 @@ use crate::protos::{
     tensor_shape_proto::{dimension, Dimension},
     TensorShapeProto,
 };
+use crate::{Dtype, Error};
 
 pub fn shape_to_dimensions(shape: &[usize]) -> Vec<Dimension> {
     shape
@@ -20,6 +21,60 @@ pub fn shape_to_tensor_shape_proto(shape: &[usize]) -> TensorShapeProto {
     }
 }
 
+/// The shape given by the `dims` of a protobuf message. Fails for
+/// negative dimensions and if the number of elements overflows.
+pub fn dims_to_shape(dims: &[i64]) -> Result<Vec<usize>, Error> {
+    let shape = dims
+        .iter()
+        .map(|dim| usize::try_from(*dim))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::new_validation(format!("Invalid dimensions {:?}.", dims)))?;
+    num_elements(&shape)?;
+    Ok(shape)
+}
+
+/// Number of elements of a tensor of `shape`, or an error if it
+/// overflows.
+pub fn num_elements(shape: &[usize]) -> Result<usize, Error> {
+    if shape.contains(&0) {
+        return Ok(0);
+    }
+    shape
+        .iter()
+        .try_fold(1usize, |acc, dim| acc.checked_mul(*dim))
+        .ok_or_else(|| {
+            Error::new_validation(format!(
+                "Number of elements of shape {:?} overflows.",
+                shape
+            ))
+        })
+}
+
 pub fn if_not_empty(s: String) -> Option<String> {
     (!s.is_empty()).then_some(s)
 }
+
+/// Number of bytes taken by `len` elements of type `dtype`, saturating
+/// at `usize::MAX`, or `None` for strings.
+pub fn byte_size(dtype: Dtype, len: usize) -> Option<usize> {
+    dtype.bits().map(|bits| {
+        (len / 8)
+            .saturating_mul(bits)
+            .saturating_add((len % 8 * bits).div_ceil(8))
+    })
+}
+
+/// Format `bytes` with a binary unit such as `1.5 KiB`.
+pub fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
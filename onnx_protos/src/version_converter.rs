@@ -0,0 +1,1068 @@
+//! Conversion of models between versions of the default opset.
+//!
+//! ```
+//! use onnx_protos::builder::GraphBuilder;
+//! use onnx_protos::{convert_opset, Attribute, Dim, Dtype};
+//!
+//! let mut b = GraphBuilder::new("graph").opset("", 11);
+//! let x = b.input("x", Dtype::F32, &[Dim::Fixed(1), Dim::Fixed(3)]);
+//! b.node::<1>("Squeeze", "", &[&x], [("axes", Attribute::I64s(vec![0]))]);
+//! let mut model = b.into_model().unwrap();
+//!
+//! convert_opset(&mut model, 13).unwrap();
+//! assert_eq!(model.opsets[""], 13);
+//! let squeeze = model.graph.nodes.last().unwrap();
+//! assert!(squeeze.attributes.is_empty());
+//! assert_eq!(squeeze.inputs.len(), 2);
+//! ```
+use std::collections::{HashMap, HashSet};
+
+use ndarray::{arr0, Array1};
+
+use crate::passes::{is_default_domain, node_label};
+use crate::shape_inference::infer_shapes;
+use crate::{
+    f16, Attribute, Dtype, Error, Graph, Model, Node, Operation, Tensor, TensorInfo, TensorValue,
+    ValueInfo,
+};
+
+/// Oldest opset version models can be converted from or to.
+pub const MIN_OPSET_VERSION: i64 = 10;
+/// Newest opset version models can be converted from or to.
+pub const MAX_OPSET_VERSION: i64 = 20;
+
+/// Operators added after [`MIN_OPSET_VERSION`] and the version which
+/// introduced them.
+const INTRODUCED: [(&str, i64); 55] = [
+    ("BitShift", 11),
+    ("ConcatFromSequence", 11),
+    ("CumSum", 11),
+    ("Det", 11),
+    ("DynamicQuantizeLinear", 11),
+    ("GatherElements", 11),
+    ("GatherND", 11),
+    ("Range", 11),
+    ("Round", 11),
+    ("ScatterND", 11),
+    ("SequenceAt", 11),
+    ("SequenceConstruct", 11),
+    ("SequenceEmpty", 11),
+    ("SequenceErase", 11),
+    ("SequenceInsert", 11),
+    ("SequenceLength", 11),
+    ("SplitToSequence", 11),
+    ("Unique", 11),
+    ("Celu", 12),
+    ("Einsum", 12),
+    ("GreaterOrEqual", 12),
+    ("LessOrEqual", 12),
+    ("NegativeLogLikelihoodLoss", 12),
+    ("SoftmaxCrossEntropyLoss", 12),
+    ("HardSwish", 14),
+    ("Trilu", 14),
+    ("Bernoulli", 15),
+    ("CastLike", 15),
+    ("Optional", 15),
+    ("OptionalGetElement", 15),
+    ("OptionalHasElement", 15),
+    ("GridSample", 16),
+    ("BlackmanWindow", 17),
+    ("DFT", 17),
+    ("HammingWindow", 17),
+    ("HannWindow", 17),
+    ("LayerNormalization", 17),
+    ("MelWeightMatrix", 17),
+    ("STFT", 17),
+    ("SequenceMap", 17),
+    ("BitwiseAnd", 18),
+    ("BitwiseNot", 18),
+    ("BitwiseOr", 18),
+    ("BitwiseXor", 18),
+    ("CenterCropPad", 18),
+    ("Col2Im", 18),
+    ("GroupNormalization", 18),
+    ("Mish", 18),
+    ("DeformConv", 19),
+    ("AffineGrid", 20),
+    ("Gelu", 20),
+    ("ImageDecoder", 20),
+    ("RegexFullMatch", 20),
+    ("StringConcat", 20),
+    ("StringSplit", 20),
+];
+
+/// Integer attributes added after [`MIN_OPSET_VERSION`] as `(op,
+/// attribute, version, default)`. They are dropped when converting
+/// to an older version if they hold their default.
+const NEW_ATTRIBUTES: [(&str, &str, i64, i64); 9] = [
+    ("TopK", "largest", 11, 1),
+    ("TopK", "sorted", 11, 1),
+    ("ArgMax", "select_last_index", 12, 0),
+    ("ArgMin", "select_last_index", 12, 0),
+    ("GatherND", "batch_dims", 12, 0),
+    ("Reshape", "allowzero", 14, 0),
+    ("Shape", "start", 15, 0),
+    ("Resize", "antialias", 18, 0),
+    ("Cast", "saturate", 19, 1),
+];
+
+/// Reductions whose `axes` became an input in opset 18.
+const REDUCTIONS_18: [&str; 9] = [
+    "ReduceL1",
+    "ReduceL2",
+    "ReduceLogSum",
+    "ReduceLogSumExp",
+    "ReduceMax",
+    "ReduceMean",
+    "ReduceMin",
+    "ReduceProd",
+    "ReduceSumSquare",
+];
+
+/// Convert the default opset of `model`, its subgraphs and local
+/// functions to version `target`.
+///
+/// Nodes whose signature changed between the versions are rewritten,
+/// e.g. attributes which became inputs are turned into `Constant`
+/// nodes feeding the new input. Converting to an older version
+/// requires such inputs to be constant. Conversions outside of
+/// [`MIN_OPSET_VERSION`] to [`MAX_OPSET_VERSION`], of operators which
+/// do not exist in the target version and of nodes using features the
+/// target version lacks fail without modifying the model. The IR
+/// version is raised if the target opset requires it.
+pub fn convert_opset(model: &mut Model, target: i64) -> Result<(), Error> {
+    let key = default_opset_key(&model.opsets)
+        .ok_or_else(|| Error::new_validation("Model does not import the default opset.".into()))?;
+    let source = model.opsets[key];
+    check_versions(source, target)?;
+
+    let mut converted = model.clone();
+    let mut converter = Converter::new(model);
+    converter.graph(&mut converted.graph, source, target, "", &HashMap::new())?;
+    converted.opsets.insert(key.to_string(), target);
+
+    for function in converted.functions.iter_mut() {
+        let Some(key) = default_opset_key(&function.opsets) else {
+            continue;
+        };
+        let source = function.opsets[key];
+        check_versions(source, target)?;
+        // Type information of the main graph does not apply to the body
+        let mut converter = Converter {
+            names: converter.names.clone(),
+            types: HashMap::new(),
+        };
+        let mut body = Graph {
+            nodes: std::mem::take(&mut function.nodes),
+            ..Default::default()
+        };
+        let prefix = format!("{}/", function.operation.name);
+        converter.graph(&mut body, source, target, &prefix, &HashMap::new())?;
+        function.nodes = body.nodes;
+        function.opsets.insert(key.to_string(), target);
+    }
+
+    converted.ir_version = converted.ir_version.max(ir_version(target));
+    *model = converted;
+    Ok(())
+}
+
+fn default_opset_key(opsets: &crate::IndexMap<String, i64>) -> Option<&'static str> {
+    ["", "ai.onnx"]
+        .into_iter()
+        .find(|key| opsets.contains_key(*key))
+}
+
+fn check_versions(source: i64, target: i64) -> Result<(), Error> {
+    let supported = MIN_OPSET_VERSION..=MAX_OPSET_VERSION;
+    if !supported.contains(&source) || !supported.contains(&target) {
+        return Err(Error::new_validation(format!(
+            "Conversion from opset {} to {} is not supported, only versions {} to {} are.",
+            source, target, MIN_OPSET_VERSION, MAX_OPSET_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Minimal IR version supporting the default opset `version`.
+fn ir_version(version: i64) -> i64 {
+    match version {
+        ..=10 => 5,
+        11 => 6,
+        12..=14 => 7,
+        15..=18 => 8,
+        _ => 9,
+    }
+}
+
+struct Converter {
+    /// Names of all edges and nodes, to keep inserted ones unique.
+    names: HashSet<String>,
+    /// Inferred type information of the edges of the model.
+    types: HashMap<String, TensorInfo>,
+}
+
+impl Converter {
+    fn new(model: &Model) -> Self {
+        let mut inferred = model.clone();
+        // Inference is best effort, so its errors are no concern here
+        let _ = infer_shapes(&mut inferred);
+        let mut converter = Converter {
+            names: HashSet::new(),
+            types: HashMap::new(),
+        };
+        converter.collect(&inferred.graph);
+        for function in model.functions.iter() {
+            for node in function.nodes.iter() {
+                converter.collect_node(node);
+            }
+        }
+        converter
+    }
+
+    fn collect(&mut self, graph: &Graph) {
+        let infos = graph
+            .inputs
+            .iter()
+            .map(|i| (&i.name, &i.info))
+            .chain(graph.outputs.iter().map(|o| (&o.name, &o.info)))
+            .chain(graph.edge_infos.iter());
+        for (name, info) in infos {
+            if let ValueInfo::Tensor(info) = info {
+                self.types.insert(name.clone(), info.clone());
+            }
+            self.names.insert(name.clone());
+        }
+        for (name, tensor) in graph.initializers.iter() {
+            let info = TensorInfo {
+                shape: Some(
+                    tensor
+                        .tensor
+                        .shape()
+                        .into_iter()
+                        .map(crate::Dim::Fixed)
+                        .collect(),
+                ),
+                dtype: tensor.tensor.dtype(),
+            };
+            self.types.insert(name.clone(), info);
+            self.names.insert(name.clone());
+        }
+        self.names.extend(graph.sparse_initializers.keys().cloned());
+        for node in graph.nodes.iter() {
+            self.collect_node(node);
+        }
+    }
+
+    fn collect_node(&mut self, node: &Node) {
+        self.names.insert(node.name.clone());
+        self.names.extend(node.outputs.iter().cloned());
+        for attr in node.attributes.values() {
+            match attr {
+                Attribute::Graph(g) => self.collect(g),
+                Attribute::Graphs(gs) => gs.iter().for_each(|g| self.collect(g)),
+                _ => {}
+            }
+        }
+    }
+
+    /// A name starting with `base` which is not used yet.
+    fn fresh(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut counter = 0;
+        while self.names.contains(&name) {
+            counter += 1;
+            name = format!("{}_{}", base, counter);
+        }
+        self.names.insert(name.clone());
+        name
+    }
+
+    fn graph(
+        &mut self,
+        graph: &mut Graph,
+        source: i64,
+        target: i64,
+        prefix: &str,
+        outer: &HashMap<String, TensorValue>,
+    ) -> Result<(), Error> {
+        let mut constants = outer.clone();
+        for (name, tensor) in graph.initializers.iter() {
+            if !graph.inputs.iter().any(|i| i.name == *name) {
+                constants.insert(name.clone(), tensor.tensor.clone());
+            }
+        }
+        for node in graph.nodes.iter() {
+            if let (true, Some(output), Some(value)) = (
+                is_default_domain(&node.operation.domain) && node.operation.name == "Constant",
+                node.outputs.first(),
+                node.attributes.values().next().and_then(constant_value),
+            ) {
+                constants.insert(output.clone(), value);
+            }
+        }
+
+        for node in graph.nodes.iter_mut() {
+            let label = node_label(prefix, node);
+            for (attr_name, attr) in node.attributes.iter_mut() {
+                let graphs = match attr {
+                    Attribute::Graph(g) => std::slice::from_mut(g),
+                    Attribute::Graphs(gs) => gs.as_mut_slice(),
+                    _ => continue,
+                };
+                for g in graphs {
+                    let prefix = format!("{}/{}/", label, attr_name);
+                    self.graph(g, source, target, &prefix, &constants)?;
+                }
+            }
+        }
+
+        let mut nodes = std::mem::take(&mut graph.nodes);
+        let upgrade = source < target;
+        let versions: Vec<i64> = match upgrade {
+            true => (source + 1..=target).collect(),
+            false => (target + 1..=source).rev().collect(),
+        };
+        for version in versions {
+            let mut converted = Vec::with_capacity(nodes.len());
+            for node in nodes {
+                if !is_default_domain(&node.operation.domain) {
+                    converted.push(node);
+                    continue;
+                }
+                let label = node_label(prefix, &node);
+                let result = match upgrade {
+                    true => self.upgrade(node, version, &mut converted),
+                    false => self.downgrade(node, version, &constants, &mut converted),
+                };
+                result.map_err(|msg| {
+                    let (from, to) = match upgrade {
+                        true => (version - 1, version),
+                        false => (version, version - 1),
+                    };
+                    Error::new_validation(format!(
+                        "Cannot convert node `{}` from opset {} to {}: {}",
+                        label, from, to, msg
+                    ))
+                })?;
+            }
+            nodes = converted;
+        }
+        graph.nodes = nodes;
+        Ok(())
+    }
+
+    /// Rewrite `node` from opset `version - 1` to `version` and append
+    /// it, along with any nodes it needs, to `nodes`.
+    fn upgrade(
+        &mut self,
+        mut node: Node,
+        version: i64,
+        nodes: &mut Vec<Node>,
+    ) -> Result<(), String> {
+        let op = node.operation.name.as_str();
+        match (version, op) {
+            (11, "Clip") => {
+                let dtype = self.dtype(first_input(&node));
+                for (attr, idx) in [("min", 1), ("max", 2)] {
+                    let Some(value) = node.attributes.shift_remove(attr) else {
+                        continue;
+                    };
+                    let Attribute::F32(value) = value else {
+                        return Err(format!("Attribute `{}` must be a float", attr));
+                    };
+                    let value = float_scalar(dtype.ok_or("Unknown type of the input")?, value)?;
+                    self.add_input(&mut node, idx, attr, value, nodes);
+                }
+            }
+            (11, "Pad") => {
+                let dtype = self.dtype(first_input(&node));
+                self.attribute_to_input(&mut node, "pads", 1, nodes)?;
+                if let Some(value) = node.attributes.shift_remove("value") {
+                    let Attribute::F32(value) = value else {
+                        return Err("Attribute `value` must be a float".into());
+                    };
+                    let value = float_scalar(dtype.ok_or("Unknown type of the input")?, value)?;
+                    self.add_input(&mut node, 2, "value", value, nodes);
+                }
+            }
+            (11, "Resize") => {
+                let scales = node.inputs.get(1).cloned().unwrap_or_default();
+                let roi = TensorValue::F32(Array1::<f32>::zeros(0).into_dyn());
+                self.add_input(&mut node, 1, "roi", roi, nodes);
+                node.inputs.truncate(2);
+                node.inputs.push(scales);
+                set_string(&mut node, "coordinate_transformation_mode", "asymmetric");
+                if string_attribute(&node, "mode") == Some("nearest") {
+                    set_string(&mut node, "nearest_mode", "floor");
+                }
+            }
+            (11, "Scatter") => node.operation.name = "ScatterElements".into(),
+            (12, "Dropout") => {
+                if let Some(ratio) = node.attributes.shift_remove("ratio") {
+                    let Attribute::F32(ratio) = ratio else {
+                        return Err("Attribute `ratio` must be a float".into());
+                    };
+                    let ratio = TensorValue::F32(arr0(ratio).into_dyn());
+                    self.add_input(&mut node, 1, "ratio", ratio, nodes);
+                }
+            }
+            (13, "Resize")
+                if string_attribute(&node, "coordinate_transformation_mode")
+                    == Some("tf_half_pixel_for_nn") =>
+            {
+                return Err("Mode `tf_half_pixel_for_nn` was removed".into());
+            }
+            (13, "Split") => self.attribute_to_input(&mut node, "split", 1, nodes)?,
+            (13, "ReduceSum" | "Squeeze" | "Unsqueeze") => {
+                self.attribute_to_input(&mut node, "axes", 1, nodes)?
+            }
+            (13, "Softmax" | "LogSoftmax" | "Hardmax") => {
+                let axis = integer_attribute(&node, "axis").unwrap_or(1);
+                if !self.is_last_axis(first_input(&node), axis) {
+                    return self.coerce_to_2d(node, axis, nodes);
+                }
+                node.attributes.insert("axis".into(), Attribute::I64(axis));
+            }
+            (18, "Split") if !has_input(&node, 1) => {
+                let num_outputs = node.outputs.len() as i64;
+                node.attributes
+                    .insert("num_outputs".into(), Attribute::I64(num_outputs));
+            }
+            (18, op) if REDUCTIONS_18.contains(&op) => {
+                self.attribute_to_input(&mut node, "axes", 1, nodes)?
+            }
+            _ => {}
+        }
+        nodes.push(node);
+        Ok(())
+    }
+
+    /// Rewrite `node` from opset `version` to `version - 1` and append
+    /// it to `nodes`.
+    fn downgrade(
+        &mut self,
+        mut node: Node,
+        version: i64,
+        constants: &HashMap<String, TensorValue>,
+        nodes: &mut Vec<Node>,
+    ) -> Result<(), String> {
+        let op = node.operation.name.as_str();
+        if INTRODUCED.contains(&(op, version)) {
+            return Err(format!("`{}` was introduced in opset {}", op, version));
+        }
+        for (_, attr, _, default) in NEW_ATTRIBUTES
+            .iter()
+            .filter(|(o, _, v, _)| *o == op && *v == version)
+        {
+            match node.attributes.get(*attr) {
+                None => {}
+                Some(Attribute::I64(value)) if value == default => {
+                    node.attributes.shift_remove(*attr);
+                }
+                Some(_) => return Err(format!("Attribute `{}` is not supported", attr)),
+            }
+        }
+
+        match (version, op) {
+            (11, "Clip") => {
+                for (idx, attr) in [(1, "min"), (2, "max")] {
+                    if let Some(value) = input_value(&node, idx, constants)? {
+                        let value = float_value(&value).ok_or("Bound must be a float scalar")?;
+                        node.attributes.insert(attr.into(), Attribute::F32(value));
+                    }
+                }
+                node.inputs.truncate(1);
+            }
+            (11, "Pad") => {
+                input_to_attribute(&mut node, 1, "pads", constants)?;
+                if let Some(value) = input_value(&node, 2, constants)? {
+                    let value = float_value(&value).ok_or("Padding must be a float scalar")?;
+                    node.attributes
+                        .insert("value".into(), Attribute::F32(value));
+                }
+                node.inputs.truncate(1);
+            }
+            (11, "Resize") => {
+                let mode = string_attribute(&node, "coordinate_transformation_mode");
+                let nearest = string_attribute(&node, "mode") == Some("nearest");
+                if mode != Some("asymmetric")
+                    || (nearest && string_attribute(&node, "nearest_mode") != Some("floor"))
+                    || string_attribute(&node, "mode") == Some("cubic")
+                {
+                    return Err(
+                        "Only asymmetric linear or floor nearest resizing is supported".into(),
+                    );
+                }
+                if has_input(&node, 3) || !has_input(&node, 2) {
+                    return Err("`sizes` cannot be expressed, only `scales`".into());
+                }
+                for attr in [
+                    "coordinate_transformation_mode",
+                    "nearest_mode",
+                    "cubic_coeff_a",
+                    "exclude_outside",
+                    "extrapolation_value",
+                ] {
+                    node.attributes.shift_remove(attr);
+                }
+                node.inputs = vec![node.inputs[0].clone(), node.inputs[2].clone()];
+            }
+            (11, "ScatterElements") => node.operation.name = "Scatter".into(),
+            (11, "Gemm") if !has_input(&node, 2) => {
+                let dtype = self.dtype(first_input(&node));
+                let zero = float_scalar(dtype.ok_or("Unknown type of the input")?, 0.0)?;
+                self.add_input(&mut node, 2, "c", zero, nodes);
+            }
+            (11, "DepthToSpace") => {
+                if string_attribute(&node, "mode").is_some_and(|m| m != "DCR") {
+                    return Err("Only mode `DCR` is supported".into());
+                }
+                node.attributes.shift_remove("mode");
+            }
+            (12, "Dropout") => {
+                if let Some(ratio) = input_value(&node, 1, constants)? {
+                    let ratio = float_value(&ratio).ok_or("Ratio must be a float scalar")?;
+                    node.attributes
+                        .insert("ratio".into(), Attribute::F32(ratio));
+                }
+                match input_value(&node, 2, constants)? {
+                    None => {}
+                    Some(TensorValue::Bool(mode)) if mode.iter().all(|m| !m) => {}
+                    Some(_) => return Err("Training mode is not supported".into()),
+                }
+                node.inputs.truncate(1);
+            }
+            (12, "Constant") => {
+                for attr in [
+                    "value_float",
+                    "value_floats",
+                    "value_int",
+                    "value_ints",
+                    "value_string",
+                    "value_strings",
+                ] {
+                    let Some(value) = node.attributes.shift_remove(attr) else {
+                        continue;
+                    };
+                    let tensor = match value {
+                        Attribute::String(v) => TensorValue::String(arr0(v).into_dyn()),
+                        Attribute::Strings(v) => TensorValue::String(Array1::from(v).into_dyn()),
+                        value => constant_value(&value)
+                            .ok_or_else(|| format!("Attribute `{}` has the wrong type", attr))?,
+                    };
+                    let tensor = Tensor { tensor, path: None };
+                    node.attributes
+                        .insert("value".into(), Attribute::Tensor(tensor));
+                }
+            }
+            (12, "Pow") => {
+                let base = self.dtype(first_input(&node));
+                let exponent = node.inputs.get(1).and_then(|edge| self.dtype(edge));
+                if base.is_none() || base != exponent {
+                    return Err("Exponent must be known to be of the type of the base".into());
+                }
+            }
+            (13, "QuantizeLinear" | "DequantizeLinear") => {
+                let scale = node.inputs.get(1).and_then(|edge| self.types.get(edge));
+                if !scale.is_some_and(|info| info.shape.as_ref().is_some_and(Vec::is_empty)) {
+                    return Err("Scale must be known to be a scalar".into());
+                }
+                node.attributes.shift_remove("axis");
+            }
+            (13, "Resize") => {
+                // `roi` and `scales` can only be left out from opset 13
+                for (idx, name) in [(1, "roi"), (2, "scales")] {
+                    if !has_input(&node, idx) {
+                        let empty = TensorValue::F32(Array1::<f32>::zeros(0).into_dyn());
+                        self.add_input(&mut node, idx, name, empty, nodes);
+                    }
+                }
+            }
+            (13, "Split") => input_to_attribute(&mut node, 1, "split", constants)?,
+            (13, "ReduceSum") => {
+                if !has_input(&node, 1)
+                    && integer_attribute(&node, "noop_with_empty_axes") == Some(1)
+                {
+                    return Err("`noop_with_empty_axes` is not supported".into());
+                }
+                node.attributes.shift_remove("noop_with_empty_axes");
+                input_to_attribute(&mut node, 1, "axes", constants)?;
+            }
+            (13, "Squeeze" | "Unsqueeze") => input_to_attribute(&mut node, 1, "axes", constants)?,
+            (13, "Softmax" | "LogSoftmax" | "Hardmax") => {
+                let axis = integer_attribute(&node, "axis").unwrap_or(-1);
+                if !self.is_last_axis(first_input(&node), axis) {
+                    return Err(format!(
+                        "Normalizing over axis {} other than the last is not supported",
+                        axis
+                    ));
+                }
+                node.attributes.insert("axis".into(), Attribute::I64(axis));
+            }
+            (15, "Shape") if node.attributes.contains_key("end") => {
+                return Err("Attribute `end` is not supported".into());
+            }
+            (16, "ScatterElements" | "ScatterND") => {
+                if string_attribute(&node, "reduction").is_some_and(|r| r != "none") {
+                    return Err("Attribute `reduction` is not supported".into());
+                }
+                node.attributes.shift_remove("reduction");
+            }
+            (18, "Split") => {
+                node.attributes.shift_remove("num_outputs");
+            }
+            (18, "Pad") if has_input(&node, 3) => {
+                return Err("Input `axes` is not supported".into());
+            }
+            (18, "Resize") => {
+                if node.attributes.contains_key("axes")
+                    || string_attribute(&node, "keep_aspect_ratio_policy")
+                        .is_some_and(|p| p != "stretch")
+                {
+                    return Err(
+                        "Attributes `axes` and `keep_aspect_ratio_policy` are not supported".into(),
+                    );
+                }
+                node.attributes.shift_remove("keep_aspect_ratio_policy");
+            }
+            (18, op) if REDUCTIONS_18.contains(&op) => {
+                if !has_input(&node, 1)
+                    && integer_attribute(&node, "noop_with_empty_axes") == Some(1)
+                {
+                    return Err("`noop_with_empty_axes` is not supported".into());
+                }
+                node.attributes.shift_remove("noop_with_empty_axes");
+                input_to_attribute(&mut node, 1, "axes", constants)?;
+            }
+            (19, "Pad") if string_attribute(&node, "mode") == Some("wrap") => {
+                return Err("Mode `wrap` is not supported".into());
+            }
+            (19, "Resize")
+                if string_attribute(&node, "coordinate_transformation_mode")
+                    == Some("half_pixel_symmetric") =>
+            {
+                return Err("Mode `half_pixel_symmetric` is not supported".into());
+            }
+            _ => {}
+        }
+        nodes.push(node);
+        Ok(())
+    }
+
+    fn dtype(&self, edge: &str) -> Option<Dtype> {
+        self.types.get(edge).map(|info| info.dtype)
+    }
+
+    /// Whether `axis` is known to be the last axis of `edge`.
+    fn is_last_axis(&self, edge: &str, axis: i64) -> bool {
+        let rank = self
+            .types
+            .get(edge)
+            .and_then(|info| info.shape.as_ref())
+            .map(Vec::len);
+        axis == -1 || rank.is_some_and(|rank| axis == rank as i64 - 1)
+    }
+
+    /// Feed `value` to the input `idx` of `node` through a new
+    /// `Constant` node.
+    fn add_input(
+        &mut self,
+        node: &mut Node,
+        idx: usize,
+        name: &str,
+        value: TensorValue,
+        nodes: &mut Vec<Node>,
+    ) {
+        let base = node.outputs.first().map_or("constant", String::as_str);
+        let edge = self.fresh(&format!("{}_{}", base, name));
+        nodes.push(Node {
+            outputs: vec![edge.clone()],
+            operation: Operation {
+                name: "Constant".into(),
+                domain: node.operation.domain.clone(),
+            },
+            attributes: [(
+                "value".to_string(),
+                Attribute::Tensor(Tensor {
+                    tensor: value,
+                    path: None,
+                }),
+            )]
+            .into(),
+            ..Default::default()
+        });
+        if node.inputs.len() <= idx {
+            node.inputs.resize(idx + 1, String::new());
+        }
+        node.inputs[idx] = edge;
+    }
+
+    /// Move the integer list attribute `attr` to the input `idx`.
+    fn attribute_to_input(
+        &mut self,
+        node: &mut Node,
+        attr: &str,
+        idx: usize,
+        nodes: &mut Vec<Node>,
+    ) -> Result<(), String> {
+        let Some(value) = node.attributes.shift_remove(attr) else {
+            return Ok(());
+        };
+        let Attribute::I64s(values) = value else {
+            return Err(format!("Attribute `{}` must be a list of integers", attr));
+        };
+        let value = TensorValue::I64(Array1::from(values).into_dyn());
+        self.add_input(node, idx, attr, value, nodes);
+        Ok(())
+    }
+
+    /// Express `op(x, axis)` with the pre-13 semantics of coercing `x`
+    /// to 2D at `axis` using the newer per-axis semantics.
+    fn coerce_to_2d(
+        &mut self,
+        mut node: Node,
+        axis: i64,
+        nodes: &mut Vec<Node>,
+    ) -> Result<(), String> {
+        let (Some(x), Some(y)) = (node.inputs.first().cloned(), node.outputs.first().cloned())
+        else {
+            return Err("Missing input or output".into());
+        };
+        let shape = self.fresh(&format!("{}_shape", x));
+        let flat = self.fresh(&format!("{}_flat", x));
+        let normalized = self.fresh(&format!("{}_2d", y));
+        let new_node = |op: &str, inputs: Vec<String>, output: String| Node {
+            inputs,
+            outputs: vec![output],
+            operation: Operation {
+                name: op.into(),
+                domain: node.operation.domain.clone(),
+            },
+            ..Default::default()
+        };
+        let mut flatten = new_node("Flatten", vec![x.clone()], flat.clone());
+        flatten
+            .attributes
+            .insert("axis".into(), Attribute::I64(axis));
+        nodes.push(new_node("Shape", vec![x], shape.clone()));
+        nodes.push(flatten);
+        let reshape = new_node("Reshape", vec![normalized.clone(), shape], y);
+        node.inputs[0] = flat;
+        node.outputs[0] = normalized;
+        node.attributes.insert("axis".into(), Attribute::I64(1));
+        nodes.push(node);
+        nodes.push(reshape);
+        Ok(())
+    }
+}
+
+fn first_input(node: &Node) -> &str {
+    node.inputs.first().map_or("", String::as_str)
+}
+
+fn has_input(node: &Node, idx: usize) -> bool {
+    node.inputs.get(idx).is_some_and(|i| !i.is_empty())
+}
+
+fn integer_attribute(node: &Node, name: &str) -> Option<i64> {
+    match node.attributes.get(name) {
+        Some(Attribute::I64(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn string_attribute<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    match node.attributes.get(name) {
+        Some(Attribute::String(v)) => Some(v),
+        _ => None,
+    }
+}
+
+fn set_string(node: &mut Node, name: &str, value: &str) {
+    node.attributes
+        .insert(name.into(), Attribute::String(value.into()));
+}
+
+/// Value of a `Constant` node given by its only attribute.
+fn constant_value(attr: &Attribute) -> Option<TensorValue> {
+    Some(match attr {
+        Attribute::Tensor(t) => t.tensor.clone(),
+        Attribute::I64(v) => TensorValue::I64(arr0(*v).into_dyn()),
+        Attribute::I64s(v) => TensorValue::I64(Array1::from(v.clone()).into_dyn()),
+        Attribute::F32(v) => TensorValue::F32(arr0(*v).into_dyn()),
+        Attribute::F32s(v) => TensorValue::F32(Array1::from(v.clone()).into_dyn()),
+        _ => return None,
+    })
+}
+
+/// Value of the optional input `idx`, which must be constant if given.
+fn input_value(
+    node: &Node,
+    idx: usize,
+    constants: &HashMap<String, TensorValue>,
+) -> Result<Option<TensorValue>, String> {
+    if !has_input(node, idx) {
+        return Ok(None);
+    }
+    let edge = &node.inputs[idx];
+    let value = constants
+        .get(edge)
+        .ok_or_else(|| format!("Input `{}` must be constant", edge))?;
+    Ok(Some(match value {
+        TensorValue::Raw(raw) => raw.decode().map_err(|e| e.to_string())?,
+        value => value.clone(),
+    }))
+}
+
+/// Move the constant integer input `idx` to the attribute `attr`.
+fn input_to_attribute(
+    node: &mut Node,
+    idx: usize,
+    attr: &str,
+    constants: &HashMap<String, TensorValue>,
+) -> Result<(), String> {
+    let Some(value) = input_value(node, idx, constants)? else {
+        return Ok(());
+    };
+    let values = match value {
+        TensorValue::I64(arr) => arr.iter().copied().collect(),
+        TensorValue::I32(arr) => arr.iter().map(|v| *v as i64).collect(),
+        _ => {
+            return Err(format!(
+                "Input `{}` must be an integer tensor",
+                node.inputs[idx]
+            ))
+        }
+    };
+    node.attributes.insert(attr.into(), Attribute::I64s(values));
+    node.inputs.truncate(idx);
+    Ok(())
+}
+
+fn float_scalar(dtype: Dtype, value: f32) -> Result<TensorValue, String> {
+    Ok(match dtype {
+        Dtype::F32 => TensorValue::F32(arr0(value).into_dyn()),
+        Dtype::F64 => TensorValue::F64(arr0(value as f64).into_dyn()),
+        Dtype::F16 => TensorValue::F16(arr0(f16::from_f32(value)).into_dyn()),
+        dtype => return Err(format!("Unsupported type `{:?}`", dtype)),
+    })
+}
+
+fn float_value(value: &TensorValue) -> Option<f32> {
+    match value {
+        TensorValue::F32(arr) if arr.len() == 1 => arr.iter().next().copied(),
+        TensorValue::F64(arr) if arr.len() == 1 => arr.iter().next().map(|v| *v as f32),
+        TensorValue::F16(arr) if arr.len() == 1 => arr.iter().next().map(|v| v.to_f32()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use super::*;
+    use crate::builder::{GraphBuilder, Handle};
+    use crate::Dim;
+
+    fn fixed(dims: &[usize]) -> Vec<Dim> {
+        dims.iter().copied().map(Dim::Fixed).collect()
+    }
+
+    fn ops(model: &Model) -> Vec<&str> {
+        model
+            .graph
+            .nodes
+            .iter()
+            .map(|n| n.operation.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let mut b = GraphBuilder::new("graph").opset("", 10);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 1, 4]));
+        let bounds = [("min", Attribute::F32(0.0)), ("max", Attribute::F32(6.0))];
+        let [clipped] = b.node("Clip", "", &[&x], bounds);
+        let axes = [("axes", Attribute::I64s(vec![1]))];
+        let [squeezed] = b.node("Squeeze", "", &[&clipped], axes);
+        let split = [
+            ("split", Attribute::I64s(vec![1, 3])),
+            ("axis", Attribute::I64(1)),
+        ];
+        b.node::<2>("Split", "", &[&squeezed], split);
+        b.node::<1>(
+            "ReduceMean",
+            "",
+            &[&squeezed],
+            [("axes", Attribute::I64s(vec![0]))],
+        );
+        let mut model = b.into_model().unwrap();
+
+        convert_opset(&mut model, 18).unwrap();
+        assert_eq!(model.opsets[""], 18);
+        assert_eq!(
+            ops(&model),
+            [
+                "Constant",
+                "Constant",
+                "Clip",
+                "Constant",
+                "Squeeze",
+                "Constant",
+                "Split",
+                "Constant",
+                "ReduceMean"
+            ]
+        );
+        let nodes = &model.graph.nodes;
+        assert_eq!(
+            nodes[2].inputs,
+            ["x", &nodes[0].outputs[0], &nodes[1].outputs[0]]
+        );
+        assert!(nodes[2].attributes.is_empty());
+        assert_eq!(
+            nodes[1].attributes["value"],
+            Attribute::Tensor(Tensor {
+                tensor: TensorValue::F32(arr0(6.0f32).into_dyn()),
+                path: None
+            })
+        );
+        assert_eq!(nodes[6].attributes.keys().collect::<Vec<_>>(), ["axis"]);
+        assert_eq!(nodes[8].inputs[1], nodes[7].outputs[0]);
+        assert_eq!(model.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let mut b = GraphBuilder::new("graph").opset("", 13);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 1, 4]));
+        let axes = b.initializer("axes", array![1i64].into_dyn());
+        let [squeezed] = b.node("Squeeze", "", &[&x, &axes], []);
+        b.node::<1>("Softmax", "", &[&squeezed], []);
+        let mut model = b.into_model().unwrap();
+
+        convert_opset(&mut model, 11).unwrap();
+        let nodes = &model.graph.nodes;
+        assert_eq!(nodes[0].inputs, ["x"]);
+        assert_eq!(nodes[0].attributes["axes"], Attribute::I64s(vec![1]));
+        assert_eq!(nodes[1].attributes["axis"], Attribute::I64(-1));
+
+        let mut b = GraphBuilder::new("graph").opset("", 13);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 1, 4]));
+        let axes = b.input("axes", Dtype::I64, &fixed(&[1]));
+        b.node::<1>("Squeeze", "", &[&x, &axes], []);
+        let mut model = b.into_model().unwrap();
+        let original = model.clone();
+        let err = convert_opset(&mut model, 12).unwrap_err();
+        assert!(err.to_string().contains("Input `axes` must be constant"));
+        assert_eq!(model, original);
+    }
+
+    #[test]
+    fn test_dropout() {
+        let mut b = GraphBuilder::new("graph").opset("", 11);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 4]));
+        b.node::<1>("Dropout", "", &[&x], [("ratio", Attribute::F32(0.25))]);
+        let mut model = b.into_model().unwrap();
+
+        convert_opset(&mut model, 12).unwrap();
+        assert_eq!(ops(&model), ["Constant", "Dropout"]);
+        let dropout = &model.graph.nodes[1];
+        assert_eq!(dropout.inputs[1], model.graph.nodes[0].outputs[0]);
+        assert!(!dropout.attributes.contains_key("ratio"));
+        assert_eq!(model.check(), Ok(()));
+
+        convert_opset(&mut model, 11).unwrap();
+        let dropout = &model.graph.nodes[1];
+        assert_eq!(dropout.inputs, ["x"]);
+        assert_eq!(dropout.attributes["ratio"], Attribute::F32(0.25));
+
+        let mut b = GraphBuilder::new("graph").opset("", 12);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 4]));
+        let ratio = b.initializer("ratio", arr0(0.5f32).into_dyn());
+        let training = b.initializer("training", arr0(true).into_dyn());
+        b.node::<1>("Dropout", "", &[&x, &ratio, &training], []);
+        let mut model = b.into_model().unwrap();
+        let err = convert_opset(&mut model, 11).unwrap_err();
+        assert!(err.to_string().contains("Training mode is not supported"));
+    }
+
+    #[test]
+    fn test_signature_changes() {
+        let mut b = GraphBuilder::new("graph").opset("", 13);
+        let x = b.input("x", Dtype::F32, &fixed(&[1, 4, 2, 2]));
+        let sizes = b.initializer("sizes", array![1i64, 4, 4, 4].into_dyn());
+        let none = Handle::omitted();
+        b.node::<1>("Resize", "", &[&x, &none, &none, &sizes], []);
+        b.node::<1>(
+            "Constant",
+            "",
+            &[],
+            [("value_ints", Attribute::I64s(vec![1, 2]))],
+        );
+        let mut model = b.into_model().unwrap();
+
+        convert_opset(&mut model, 11).unwrap();
+        let nodes = &model.graph.nodes;
+        let resize = nodes.iter().find(|n| n.operation.name == "Resize").unwrap();
+        assert!(resize.inputs.iter().all(|i| !i.is_empty()));
+        let constant = nodes.last().unwrap();
+        assert_eq!(constant.attributes.keys().collect::<Vec<_>>(), ["value"]);
+        assert_eq!(model.check(), Ok(()));
+
+        let mut b = GraphBuilder::new("graph").opset("", 11);
+        let a = b.input("a", Dtype::F32, &fixed(&[2, 4]));
+        let w = b.input("w", Dtype::F32, &fixed(&[4, 4]));
+        b.node::<1>("Gemm", "", &[&a, &w], []);
+        let mut model = b.into_model().unwrap();
+        convert_opset(&mut model, 10).unwrap();
+        assert_eq!(ops(&model), ["Constant", "Gemm"]);
+        assert_eq!(model.graph.nodes[1].inputs.len(), 3);
+
+        let mut b = GraphBuilder::new("graph").opset("", 13);
+        let x = b.input("x", Dtype::F32, &fixed(&[1, 4, 2, 2]));
+        b.node::<1>(
+            "DepthToSpace",
+            "",
+            &[&x],
+            [
+                ("blocksize", Attribute::I64(2)),
+                ("mode", Attribute::String("CRD".into())),
+            ],
+        );
+        let mut model = b.into_model().unwrap();
+        let err = convert_opset(&mut model, 10).unwrap_err();
+        assert!(err.to_string().contains("Only mode `DCR` is supported"));
+    }
+
+    #[test]
+    fn test_softmax_semantics() {
+        let mut b = GraphBuilder::new("graph").opset("", 12);
+        let x = b.input("x", Dtype::F32, &[Dim::Unknown, Dim::Unknown, Dim::Unknown]);
+        let [y] = b.node("Softmax", "", &[&x], []);
+        let mut model = b.into_model().unwrap();
+
+        convert_opset(&mut model, 13).unwrap();
+        assert_eq!(ops(&model), ["Shape", "Flatten", "Softmax", "Reshape"]);
+        assert_eq!(model.graph.nodes[3].outputs, [y.name()]);
+        assert_eq!(model.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut b = GraphBuilder::new("graph").opset("", 17);
+        let x = b.input("x", Dtype::F32, &fixed(&[2, 4]));
+        let scale = b.initializer("scale", array![1.0f32, 1.0, 1.0, 1.0].into_dyn());
+        b.node::<1>("LayerNormalization", "", &[&x, &scale], []);
+        let mut model = b.into_model().unwrap();
+
+        let err = convert_opset(&mut model, 16).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`LayerNormalization` was introduced in opset 17"));
+        let err = convert_opset(&mut model, 9).unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+    }
+}
@@ -265,10 +265,11 @@ impl Api {
     }
 
     /// Create a tensor backed by a user provided buffer. The returned
-    /// `OrtValue` is only valid for the lifetime of `data`.
+    /// `OrtValue` is only valid for the lifetime of `data` and must not
+    /// be written to.
     pub unsafe fn create_tensor_with_borrowed_data<T>(
         &self,
-        data: &mut [T],
+        data: &[T],
         shape: &[usize],
     ) -> Result<Wrapper<OrtValue>, ErrorStatus>
     where
@@ -283,7 +284,7 @@ impl Api {
         unsafe {
             self.api.CreateTensorWithDataAsOrtValue.unwrap()(
                 mem_info.ptr,
-                data.as_mut_ptr() as *mut _,
+                data.as_ptr() as *mut _,
                 data_n_bytes,
                 shape.as_ptr() as _,
                 shape_len,
@@ -0,0 +1,130 @@
+//! Helpers for inspecting what happens inside of a model.
+
+use std::collections::HashMap;
+
+use onnx_protos::{infer_shapes, GraphView, Model, Output, ValueInfo};
+
+use crate::fold::is_supported;
+use crate::{ErrorStatus, Session, Value};
+
+/// Run a copy of `model` in which the given internal `edges` of the
+/// main graph are graph outputs and return all outputs by name.
+///
+/// If `edges` is `None`, every edge produced by a node of the main
+/// graph is exposed, except for those whose type is unknown or cannot
+/// be read back into a [`Value`]. Types are taken from the model or
+/// inferred with [`infer_shapes`]; explicitly requested edges without
+/// a usable type are an error.
+pub fn run_with_intermediates(
+    model: &Model,
+    inputs: HashMap<&str, &Value>,
+    edges: Option<&[&str]>,
+) -> Result<HashMap<String, Value>, ErrorStatus> {
+    let original = model;
+    let mut model = original.clone();
+    // Declared types suffice if inference is not possible
+    if infer_shapes(&mut model).is_err() {
+        model = original.clone();
+    }
+
+    let graph = &model.graph;
+    let view = GraphView::new(graph);
+    let chosen: Vec<String> = match edges {
+        Some(edges) => {
+            if let Some(edge) = edges.iter().find(|e| view.producer(e).is_none()) {
+                return Err(ErrorStatus::new_invalid_argument(format!(
+                    "`{}` is not produced by a node of the main graph.",
+                    edge
+                )));
+            }
+            edges.iter().map(|e| e.to_string()).collect()
+        }
+        None => graph
+            .nodes
+            .iter()
+            .flat_map(|n| n.outputs.iter())
+            .filter(|e| !e.is_empty())
+            .cloned()
+            .collect(),
+    };
+
+    let mut exposed = vec![];
+    for edge in chosen {
+        if graph.outputs.iter().any(|o| o.name == edge) || exposed.contains(&edge) {
+            continue;
+        }
+        match graph.edge_infos.get(&edge) {
+            Some(ValueInfo::Tensor(info)) if is_supported(info.dtype) => exposed.push(edge),
+            _ if edges.is_none() => {}
+            _ => {
+                return Err(ErrorStatus::new_invalid_argument(format!(
+                    "Type of edge `{}` is unknown or not supported.",
+                    edge
+                )))
+            }
+        }
+    }
+
+    let graph = &mut model.graph;
+    for edge in exposed {
+        let info = graph.edge_infos.shift_remove(&edge).unwrap();
+        graph.outputs.push(Output {
+            name: edge,
+            info,
+            doc_string: None,
+            denotation: None,
+        });
+    }
+    let bytes = model
+        .to_bytes()
+        .map_err(|e| ErrorStatus::new_invalid_argument(e.to_string()))?;
+    let session = Session::from_bytes(bytes)?;
+    let values = session.run(inputs, None)?;
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use onnx_protos::builder::GraphBuilder;
+    use onnx_protos::{Dim, Dtype, TensorInfo};
+
+    use super::*;
+    use crate::{IntoValue, Tensor};
+
+    #[test]
+    fn test_run_with_intermediates() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let [a] = b.node("Abs", "", &[&x], []);
+        let [n] = b.node("Neg", "", &[&a], []);
+        let [shape] = b.node("Shape", "", &[&n], []);
+        let [y] = b.node("Reshape", "", &[&n, &shape], []);
+        b.output(
+            &y,
+            ValueInfo::Tensor(TensorInfo {
+                shape: None,
+                dtype: Dtype::F32,
+            }),
+        );
+        let model = b.into_model().unwrap();
+
+        let input = array![-1.0f32, 2.0].into_dyn().view().into_value().unwrap();
+        let inputs: HashMap<_, _> = [("x", &input)].into();
+        let values = run_with_intermediates(&model, inputs.clone(), None).unwrap();
+        assert_eq!(values.len(), 4);
+        let Value::Tensor(Tensor::F32(data)) = &values[n.name()] else {
+            panic!("Expected `F32` output.")
+        };
+        assert_eq!(data.array_view(), array![-1.0f32, -2.0].into_dyn());
+
+        let values = run_with_intermediates(&model, inputs.clone(), Some(&[a.name()])).unwrap();
+        assert_eq!(values.len(), 2);
+        assert!(run_with_intermediates(&model, inputs, Some(&["x"])).is_err());
+        // The original model is unchanged
+        assert_eq!(model.graph.outputs.len(), 1);
+    }
+}
@@ -24,6 +24,14 @@ impl ErrorStatus {
 
         Self { msg, code }
     }
+
+    /// Error for invalid arguments detected on the Rust side.
+    pub(crate) fn new_invalid_argument(msg: String) -> Self {
+        Self {
+            msg,
+            code: ort_sys::OrtErrorCode_ORT_INVALID_ARGUMENT,
+        }
+    }
 }
 
 impl std::fmt::Display for ErrorStatus {
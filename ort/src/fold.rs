@@ -0,0 +1,355 @@
+//! Constant folding by evaluating constant nodes with onnxruntime.
+
+use std::collections::{HashMap, HashSet};
+
+use onnx_protos::{
+    Attribute, Dtype, Graph, GraphView, IndexMap, Model, Node, Output, Tensor, TensorValue,
+};
+
+use crate::{ErrorStatus, Session};
+
+/// Operators whose outputs are not determined by their inputs.
+const NONDETERMINISTIC: [&str; 6] = [
+    "Bernoulli",
+    "Multinomial",
+    "RandomNormal",
+    "RandomNormalLike",
+    "RandomUniform",
+    "RandomUniformLike",
+];
+
+/// Replace the nodes of the main graph whose inputs are all
+/// initializers by initializers holding their outputs. Returns the
+/// number of folded nodes.
+///
+/// All foldable nodes are evaluated at once in a temporary
+/// [`Session`]. If onnxruntime rejects that model, e.g. because the
+/// output type of a node without `ValueInfo` was guessed wrongly, the
+/// nodes are evaluated one at a time and those which fail are kept.
+/// Initializers which are graph inputs, bound by training info or
+/// not loaded yet are not considered constant. Initializers which are
+/// no longer used after folding are removed.
+pub fn fold_constants(model: &mut Model) -> Result<usize, ErrorStatus> {
+    let candidates = foldable_nodes(model)?;
+    if candidates.is_empty() {
+        return Ok(0);
+    }
+
+    let graph = &model.graph;
+    let view = GraphView::new(graph);
+    let needed = |edge: &str, folded: &HashSet<usize>| {
+        view.is_output(edge) || view.consumers(edge).iter().any(|c| !folded.contains(c))
+    };
+
+    let batch: HashSet<usize> = candidates.iter().map(|(idx, _)| *idx).collect();
+    let nodes: Vec<&Node> = candidates
+        .iter()
+        .map(|(idx, _)| &graph.nodes[*idx])
+        .collect();
+    let outputs: Vec<(&str, Dtype)> = candidates
+        .iter()
+        .flat_map(|(_, outputs)| outputs.iter().copied())
+        .filter(|(edge, _)| needed(edge, &batch))
+        .collect();
+    let (folded, results) = match evaluate(model, &nodes, &outputs, &IndexMap::new()) {
+        Ok(tensors) => {
+            let results: IndexMap<String, Tensor> = outputs
+                .iter()
+                .map(|(edge, _)| edge.to_string())
+                .zip(tensors)
+                .collect();
+            (batch, results)
+        }
+        Err(_) => {
+            let mut folded = HashSet::new();
+            let mut results = IndexMap::new();
+            for (idx, outputs) in candidates.iter() {
+                let node = &graph.nodes[*idx];
+                if node.inputs.iter().any(|i| {
+                    !i.is_empty() && view.producer(i).is_some() && !results.contains_key(i)
+                }) {
+                    continue;
+                }
+                if let Ok(tensors) = evaluate(model, &[node], outputs, &results) {
+                    results.extend(
+                        outputs
+                            .iter()
+                            .map(|(edge, _)| edge.to_string())
+                            .zip(tensors),
+                    );
+                    folded.insert(*idx);
+                }
+            }
+            (folded, results)
+        }
+    };
+
+    let inputs: HashSet<String> = folded
+        .iter()
+        .flat_map(|idx| graph.nodes[*idx].inputs.iter().cloned())
+        .filter(|i| !i.is_empty())
+        .collect();
+    let initializers: Vec<(String, Tensor)> = results
+        .into_iter()
+        .filter(|(edge, _)| needed(edge, &folded))
+        .collect();
+
+    let graph = &mut model.graph;
+    let mut idx = 0;
+    graph.nodes.retain(|_| {
+        idx += 1;
+        !folded.contains(&(idx - 1))
+    });
+    graph.initializers.extend(initializers);
+
+    let view = GraphView::new(graph);
+    let unused: Vec<&String> = inputs
+        .iter()
+        .filter(|i| {
+            view.consumers(i).is_empty()
+                && !view.is_output(i)
+                && !graph.inputs.iter().any(|input| input.name == **i)
+        })
+        .collect();
+    let graph = &mut model.graph;
+    for name in unused {
+        graph.initializers.shift_remove(name);
+        graph.sparse_initializers.shift_remove(name);
+    }
+    Ok(folded.len())
+}
+
+/// Index of a node along with its non-empty outputs and their data
+/// types.
+type Foldable<'a> = (usize, Vec<(&'a str, Dtype)>);
+
+/// The nodes of the main graph which only depend on constants, in
+/// topological order.
+fn foldable_nodes(model: &Model) -> Result<Vec<Foldable<'_>>, ErrorStatus> {
+    let graph = &model.graph;
+    let order = GraphView::new(graph)
+        .topological_order()
+        .map_err(|e| ErrorStatus::new_invalid_argument(e.to_string()))?;
+
+    let trained: HashSet<&str> = model
+        .training_info
+        .iter()
+        .flat_map(|t| {
+            t.initialization_binding
+                .keys()
+                .chain(t.update_binding.keys())
+        })
+        .map(String::as_str)
+        .collect();
+    let dense = graph
+        .initializers
+        .iter()
+        .filter(|(_, t)| !matches!(t.tensor, TensorValue::Unloaded { .. }))
+        .map(|(name, t)| (name.as_str(), t.tensor.dtype()));
+    let sparse = graph
+        .sparse_initializers
+        .iter()
+        .map(|(name, s)| (name.as_str(), s.values.tensor.dtype()));
+    let mut constants: HashMap<&str, Dtype> = dense
+        .chain(sparse)
+        .filter(|(name, _)| {
+            !trained.contains(name) && !graph.inputs.iter().any(|i| i.name == *name)
+        })
+        .collect();
+
+    let mut foldable = vec![];
+    for idx in order {
+        let node = &graph.nodes[idx];
+        let op = node.operation.name.as_str();
+        let default_domain = matches!(node.operation.domain.as_str(), "" | "ai.onnx");
+        if (default_domain && NONDETERMINISTIC.contains(&op))
+            || node
+                .attributes
+                .values()
+                .any(|a| matches!(a, Attribute::Graph(_) | Attribute::Graphs(_)))
+            || node.outputs.iter().all(String::is_empty)
+            || !node
+                .inputs
+                .iter()
+                .all(|i| i.is_empty() || constants.contains_key(i.as_str()))
+        {
+            continue;
+        }
+        let Some(outputs) = output_dtypes(node, graph, &constants) else {
+            continue;
+        };
+        if !outputs.iter().all(|(_, dtype)| is_supported(*dtype)) {
+            continue;
+        }
+        constants.extend(outputs.iter().copied());
+        foldable.push((idx, outputs));
+    }
+    Ok(foldable)
+}
+
+/// Data types of the non-empty outputs of `node`. Taken from the
+/// `ValueInfo` of the edge if there is one and guessed from the
+/// operator otherwise.
+fn output_dtypes<'a>(
+    node: &'a Node,
+    graph: &Graph,
+    constants: &HashMap<&str, Dtype>,
+) -> Option<Vec<(&'a str, Dtype)>> {
+    let input = |idx: usize| {
+        node.inputs
+            .get(idx)
+            .and_then(|i| constants.get(i.as_str()))
+            .copied()
+    };
+    let attribute_dtype = |name: &str| match node.attributes.get(name) {
+        Some(Attribute::I64(dtype)) => Dtype::try_from(*dtype as i32).ok(),
+        _ => None,
+    };
+    let default_domain = matches!(node.operation.domain.as_str(), "" | "ai.onnx");
+    let op = node.operation.name.as_str();
+    let guess = if !default_domain || (node.outputs.len() > 1 && op != "Split") {
+        None
+    } else {
+        match op {
+            "Constant" => node.attributes.values().next().and_then(|a| match a {
+                Attribute::Tensor(t) => Some(t.tensor.dtype()),
+                Attribute::F32(_) | Attribute::F32s(_) => Some(Dtype::F32),
+                Attribute::I64(_) | Attribute::I64s(_) => Some(Dtype::I64),
+                Attribute::String(_) | Attribute::Strings(_) => Some(Dtype::String),
+                _ => None,
+            }),
+            "ConstantOfShape" => match node.attributes.get("value") {
+                Some(Attribute::Tensor(t)) => Some(t.tensor.dtype()),
+                Some(_) => None,
+                None => Some(Dtype::F32),
+            },
+            "Cast" => attribute_dtype("to"),
+            "EyeLike" => attribute_dtype("dtype").or_else(|| input(0)),
+            "CastLike" | "Where" | "DequantizeLinear" => input(1),
+            "OneHot" => input(2),
+            "QuantizeLinear" => input(2).or(Some(Dtype::U8)),
+            "Shape" | "Size" | "NonZero" | "ArgMax" | "ArgMin" => Some(Dtype::I64),
+            "Equal" | "Less" | "LessOrEqual" | "Greater" | "GreaterOrEqual" | "And" | "Or"
+            | "Xor" | "Not" | "IsNaN" | "IsInf" => Some(Dtype::Bool),
+            _ => input(0),
+        }
+    };
+    node.outputs
+        .iter()
+        .filter(|o| !o.is_empty())
+        .map(|o| match graph.edge_infos.get(o) {
+            Some(onnx_protos::ValueInfo::Tensor(info)) => Some((o.as_str(), info.dtype)),
+            Some(_) => None,
+            None => guess.map(|dtype| (o.as_str(), dtype)),
+        })
+        .collect()
+}
+
+/// Whether outputs of type `dtype` can be read back from a [`Value`](crate::Value).
+pub(crate) fn is_supported(dtype: Dtype) -> bool {
+    use Dtype::*;
+    matches!(
+        dtype,
+        U8 | U16 | U32 | U64 | I8 | I16 | I32 | I64 | F32 | F64 | Bool | String
+    )
+}
+
+/// Evaluate `nodes` of the main graph of `model` and return the
+/// requested `outputs`. Inputs are looked up in the initializers of
+/// the main graph and in `constants`.
+fn evaluate(
+    model: &Model,
+    nodes: &[&Node],
+    outputs: &[(&str, Dtype)],
+    constants: &IndexMap<String, Tensor>,
+) -> Result<Vec<Tensor>, ErrorStatus> {
+    let source = &model.graph;
+    let mut graph = Graph {
+        name: "fold_constants".to_string(),
+        nodes: nodes.iter().map(|node| (*node).clone()).collect(),
+        outputs: outputs
+            .iter()
+            .map(|(edge, dtype)| Output {
+                name: edge.to_string(),
+                info: onnx_protos::ValueInfo::Tensor(onnx_protos::TensorInfo {
+                    shape: None,
+                    dtype: *dtype,
+                }),
+                doc_string: None,
+                denotation: None,
+            })
+            .collect(),
+        ..Default::default()
+    };
+    for input in nodes.iter().flat_map(|node| node.inputs.iter()) {
+        if let Some(tensor) = constants.get(input).or(source.initializers.get(input)) {
+            graph.initializers.insert(input.clone(), tensor.clone());
+        } else if let Some(sparse) = source.sparse_initializers.get(input) {
+            graph
+                .sparse_initializers
+                .insert(input.clone(), sparse.clone());
+        }
+    }
+    let bytes = Model {
+        ir_version: model.ir_version,
+        opsets: model.opsets.clone(),
+        functions: model.functions.clone(),
+        graph,
+        ..Default::default()
+    }
+    .to_bytes()
+    .map_err(|e| ErrorStatus::new_invalid_argument(e.to_string()))?;
+
+    let session = Session::from_bytes(bytes)?;
+    let values = session.run(HashMap::new(), None)?;
+    outputs
+        .iter()
+        .map(|(edge, _)| Tensor::try_from(&values[edge]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use onnx_protos::builder::GraphBuilder;
+    use onnx_protos::{Dim, TensorInfo, ValueInfo};
+
+    use super::*;
+
+    #[test]
+    fn test_fold_constants() {
+        let mut b = GraphBuilder::new("graph").opset("", 19);
+        let x = b.input("x", Dtype::F32, &[Dim::Fixed(2)]);
+        let c1 = b.initializer("c1", array![1.0f32, 2.0].into_dyn());
+        let c2 = b.initializer("c2", array![3.0f32, 4.0].into_dyn());
+        let [s] = b.node("Add", "", &[&c1, &c2], []);
+        let [shape] = b.node("Shape", "", &[&s], []);
+        let [y] = b.node("Add", "", &[&x, &s], []);
+        let [z] = b.node("Reshape", "", &[&y, &shape], []);
+        b.output(
+            &z,
+            ValueInfo::Tensor(TensorInfo {
+                shape: None,
+                dtype: Dtype::F32,
+            }),
+        );
+        let mut model = b.into_model().unwrap();
+
+        assert_eq!(fold_constants(&mut model).unwrap(), 2);
+        let graph = &model.graph;
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(
+            graph.initializers.keys().collect::<Vec<_>>(),
+            [s.name(), shape.name()]
+        );
+        assert_eq!(
+            graph.initializers[s.name()].tensor,
+            TensorValue::F32(array![4.0f32, 6.0].into_dyn())
+        );
+        assert_eq!(
+            graph.initializers[shape.name()].tensor,
+            TensorValue::I64(array![2].into_dyn())
+        );
+        assert_eq!(fold_constants(&mut model).unwrap(), 0);
+    }
+}
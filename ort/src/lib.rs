@@ -1,14 +1,18 @@
 mod api;
+pub mod debug;
 mod error;
+mod fold;
 mod session;
 mod tensor_dtype;
+mod tensor_proto;
 mod type_info;
 mod value;
 
+pub use crate::fold::fold_constants;
 pub use crate::session::Session;
 pub use crate::tensor_dtype::TensorDataType;
 pub use crate::type_info::{TensorInfo, TypeInfo};
-pub use crate::value::{IntoValue, Tensor, Value};
+pub use crate::value::{IntoValue, IntoValueView, Tensor, Value, ValueView};
 
 pub const API_VERSION: u32 = 16;
 
@@ -31,93 +35,27 @@ unsafe impl<T> Sync for Wrapper<T> {}
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
     use std::fs::write;
 
     use super::*;
     use crate::type_info;
     use ndarray::array;
-    use onnx_protos::{
-        Dim, Dtype, Graph, Input, Model, Node, Operation, Output, TensorInfo, ValueInfo,
-    };
+    use onnx_protos::Model;
     use tempfile::NamedTempFile;
 
-    fn make_info(rank: usize, dtype: Dtype) -> ValueInfo {
-        ValueInfo::Tensor(TensorInfo {
-            shape: vec![Dim::Unknown; rank],
-            dtype,
-        })
-    }
-
     fn make_abs_model() -> Model {
-        Model {
-            opsets: [("ai.onnx".to_string(), 19)].into(),
-            graph: Graph {
-                name: "graph".to_string(),
-                inputs: vec![Input {
-                    name: "a".into(),
-                    info: make_info(1, Dtype::F32),
-                }],
-                outputs: vec![Output {
-                    name: "b".into(),
-                    info: make_info(1, Dtype::F32),
-                }],
-                nodes: vec![Node {
-                    name: "abs".into(),
-                    inputs: vec!["a".into()],
-                    outputs: vec!["b".into()],
-                    operation: Operation {
-                        name: "Abs".into(),
-                        domain: "ai.onnx".into(),
-                    },
-                    attributes: HashMap::new(),
-                    doc_string: None,
-                }],
-                ..Default::default()
-            },
-            ..Default::default()
-        }
+        r#"<opset_import: ["ai.onnx" : 19]> graph (float[?] a) => (float[?] b) { [abs] b = ai.onnx.Abs (a) }"#.parse().unwrap()
     }
 
     /// A model with a single identity node.
     fn identity_model() -> Model {
-        let info = ValueInfo::Tensor(TensorInfo {
-            shape: vec![Dim::Unknown, Dim::Fixed(2), Dim::Dynamic("N".to_string())],
-            dtype: Dtype::F32,
-        });
-        Model {
-            opsets: [("ai.onnx".to_string(), 19)].into(),
-            graph: Graph {
-                name: "graph".to_string(),
-                inputs: vec![Input {
-                    name: "a".into(),
-                    info: info.clone(),
-                }],
-                outputs: vec![Output {
-                    name: "b".into(),
-                    info,
-                }],
-                nodes: vec![Node {
-                    name: "abs".into(),
-                    inputs: vec!["a".into()],
-                    outputs: vec!["b".into()],
-                    operation: Operation {
-                        name: "Identity".into(),
-                        domain: "ai.onnx".into(),
-                    },
-                    attributes: HashMap::new(),
-                    doc_string: None,
-                }],
-                ..Default::default()
-            },
-            ..Default::default()
-        }
+        r#"<opset_import: ["ai.onnx" : 19]> graph (float[?,2,N] a) => (float[?,2,N] b) { [abs] b = ai.onnx.Identity (a) }"#.parse().unwrap()
     }
 
     #[test]
     fn session_from_path() {
         let f = NamedTempFile::new().unwrap();
-        let model = make_abs_model().into_bytes();
+        let model = make_abs_model().into_bytes().unwrap();
 
         write(f.path(), model).unwrap();
 
@@ -127,7 +65,7 @@ mod tests {
     #[test]
     fn abs_low_level() {
         let api = Api::new();
-        let model = make_abs_model().into_bytes();
+        let model = make_abs_model().into_bytes().unwrap();
 
         let sess = Session::from_bytes(model).unwrap();
         let rt_opts = api.create_run_options().unwrap();
@@ -154,7 +92,7 @@ mod tests {
 
     #[test]
     fn abs_higher_level() {
-        let model = make_abs_model().into_bytes();
+        let model = make_abs_model().into_bytes().unwrap();
         let sess = Session::from_bytes(model).unwrap();
 
         let arr = array![-1.0f32, -2.0].into_dyn();
@@ -171,10 +109,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn abs_tensor_proto() {
+        let model = make_abs_model().into_bytes().unwrap();
+        let sess = Session::from_bytes(model).unwrap();
+
+        let input = onnx_protos::Tensor {
+            tensor: onnx_protos::TensorValue::F32(array![-1.0f32, -2.0].into_dyn()),
+            path: None,
+        };
+        let input = Value::try_from(&input).unwrap();
+        let out = sess.run([("a", &input)].into(), None).unwrap();
+
+        let candidate = onnx_protos::Tensor::try_from(&out["b"]).unwrap();
+        let expected = onnx_protos::Tensor {
+            tensor: onnx_protos::TensorValue::F32(array![1.0f32, 2.0].into_dyn()),
+            path: None,
+        };
+        assert_eq!(candidate, expected);
+    }
+
     /// Test if unknown, fixed, and symbolic (TODO) shapes are correctly retrieved.
     #[test]
     fn unknown_fixed_dynamic_shapes() {
-        let model = identity_model().into_bytes();
+        let model = identity_model().into_bytes().unwrap();
         let sess = Session::from_bytes(model).unwrap();
 
         use type_info::*;
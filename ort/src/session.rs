@@ -130,24 +130,21 @@ impl Session {
             in_values.push(inputs.get(k).unwrap().ptr);
         }
 
-        let out_values = self
-            .api
-            .run(
-                self.ort_sess.ptr,
-                run_options.ptr,
-                self.input_names.as_slice(),
-                in_values
-                    .iter()
-                    .map(|item| item.cast_const())
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                self.output_names.as_slice(),
-            )
-            .unwrap();
+        let out_values = self.api.run(
+            self.ort_sess.ptr,
+            run_options.ptr,
+            self.input_names.as_slice(),
+            in_values
+                .iter()
+                .map(|item| item.cast_const())
+                .collect::<Vec<_>>()
+                .as_slice(),
+            self.output_names.as_slice(),
+        )?;
 
         let mut out = HashMap::new();
 
-        for (k, v) in self.output_names_iter().zip(out_values.into_iter()) {
+        for (k, v) in self.output_names_iter().zip(out_values) {
             out.insert(k, v);
         }
 
@@ -0,0 +1,205 @@
+//! Conversions between `onnx_protos::Tensor` and `Value`.
+//!
+//! A `ValueView` borrows the data of numeric tensors in standard
+//! layout. All other conversions copy the data exactly once, straight
+//! into the destination buffer if the array is in standard layout.
+//! Tensors with loaded external data convert like inline ones, while
+//! the external data reference itself is not part of a `Value`.
+
+use onnx_protos::TensorValue;
+
+use crate::{ErrorStatus, IntoValue, IntoValueView, Tensor, Value, ValueView};
+
+/// Copy the data of a tensor into a new `Value`.
+impl TryFrom<&onnx_protos::Tensor> for Value {
+    type Error = ErrorStatus;
+
+    fn try_from(tensor: &onnx_protos::Tensor) -> Result<Self, Self::Error> {
+        match &tensor.tensor {
+            TensorValue::U8(arr) => arr.view().into_value(),
+            TensorValue::U16(arr) => arr.view().into_value(),
+            TensorValue::U32(arr) => arr.view().into_value(),
+            TensorValue::U64(arr) => arr.view().into_value(),
+
+            TensorValue::I8(arr) => arr.view().into_value(),
+            TensorValue::I16(arr) => arr.view().into_value(),
+            TensorValue::I32(arr) => arr.view().into_value(),
+            TensorValue::I64(arr) => arr.view().into_value(),
+
+            TensorValue::F64(arr) => arr.view().into_value(),
+            TensorValue::F32(arr) => arr.view().into_value(),
+
+            TensorValue::Bool(arr) => arr.view().into_value(),
+
+            TensorValue::String(arr) => arr.map(String::as_str).into_value(),
+
+            TensorValue::Unloaded { .. } => Err(ErrorStatus::new_invalid_argument(
+                "The tensor's external data has not been loaded".to_string(),
+            )),
+            TensorValue::Raw(raw) => {
+                let tensor = onnx_protos::Tensor {
+                    tensor: raw
+                        .decode()
+                        .map_err(|e| ErrorStatus::new_invalid_argument(e.to_string()))?,
+                    path: None,
+                };
+                Value::try_from(&tensor)
+            }
+            other => Err(ErrorStatus::new_invalid_argument(format!(
+                "Unsupported data type {:?}",
+                other.dtype()
+            ))),
+        }
+    }
+}
+
+/// Borrow the data of a tensor if its layout allows, or copy it
+/// otherwise. Strings and lazily decoded tensors are always copied.
+impl<'a> TryFrom<&'a onnx_protos::Tensor> for ValueView<'a> {
+    type Error = ErrorStatus;
+
+    fn try_from(tensor: &'a onnx_protos::Tensor) -> Result<Self, Self::Error> {
+        match &tensor.tensor {
+            TensorValue::U8(arr) => arr.view().into_value_view(),
+            TensorValue::U16(arr) => arr.view().into_value_view(),
+            TensorValue::U32(arr) => arr.view().into_value_view(),
+            TensorValue::U64(arr) => arr.view().into_value_view(),
+
+            TensorValue::I8(arr) => arr.view().into_value_view(),
+            TensorValue::I16(arr) => arr.view().into_value_view(),
+            TensorValue::I32(arr) => arr.view().into_value_view(),
+            TensorValue::I64(arr) => arr.view().into_value_view(),
+
+            TensorValue::F64(arr) => arr.view().into_value_view(),
+            TensorValue::F32(arr) => arr.view().into_value_view(),
+
+            TensorValue::Bool(arr) => arr.view().into_value_view(),
+
+            _ => Value::try_from(tensor).map(ValueView::from),
+        }
+    }
+}
+
+/// Copy the data of a `Value` into a new tensor stored inline.
+impl TryFrom<&Value> for onnx_protos::Tensor {
+    type Error = ErrorStatus;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let tensor = match value {
+            Value::Tensor(Tensor::U8(data)) => TensorValue::U8(data.array_view().to_owned()),
+            Value::Tensor(Tensor::U16(data)) => TensorValue::U16(data.array_view().to_owned()),
+            Value::Tensor(Tensor::U32(data)) => TensorValue::U32(data.array_view().to_owned()),
+            Value::Tensor(Tensor::U64(data)) => TensorValue::U64(data.array_view().to_owned()),
+
+            Value::Tensor(Tensor::I8(data)) => TensorValue::I8(data.array_view().to_owned()),
+            Value::Tensor(Tensor::I16(data)) => TensorValue::I16(data.array_view().to_owned()),
+            Value::Tensor(Tensor::I32(data)) => TensorValue::I32(data.array_view().to_owned()),
+            Value::Tensor(Tensor::I64(data)) => TensorValue::I64(data.array_view().to_owned()),
+
+            Value::Tensor(Tensor::F64(data)) => TensorValue::F64(data.array_view().to_owned()),
+            Value::Tensor(Tensor::F32(data)) => TensorValue::F32(data.array_view().to_owned()),
+
+            Value::Tensor(Tensor::Bool(data)) => TensorValue::Bool(data.array_view().to_owned()),
+
+            Value::Tensor(Tensor::String(data)) => {
+                TensorValue::String(data.str_container().array().mapv(str::to_string))
+            }
+        };
+        Ok(onnx_protos::Tensor { tensor, path: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    fn roundtrip(tensor: onnx_protos::Tensor) {
+        let value = Value::try_from(&tensor).unwrap();
+        let candidate = onnx_protos::Tensor::try_from(&value).unwrap();
+
+        assert_eq!(tensor, candidate);
+    }
+
+    #[test]
+    fn test_roundtrip_tensor_to_value_to_tensor() {
+        roundtrip(onnx_protos::Tensor {
+            tensor: TensorValue::F32(array![[1.0, 2.0], [3.0, 4.0]].into_dyn()),
+            path: None,
+        });
+        roundtrip(onnx_protos::Tensor {
+            tensor: TensorValue::I64(array![1, -2, 3].into_dyn()),
+            path: None,
+        });
+        roundtrip(onnx_protos::Tensor {
+            tensor: TensorValue::Bool(array![true, false].into_dyn()),
+            path: None,
+        });
+        roundtrip(onnx_protos::Tensor {
+            tensor: TensorValue::String(array!["foo".to_string(), "".to_string()].into_dyn()),
+            path: None,
+        });
+    }
+
+    #[test]
+    fn test_non_standard_layout() {
+        let arr = array![[1u8, 2, 3], [4, 5, 6]].into_dyn();
+        let tensor = onnx_protos::Tensor {
+            tensor: TensorValue::U8(arr.clone().reversed_axes()),
+            path: None,
+        };
+        let value = Value::try_from(&tensor).unwrap();
+
+        if let Value::Tensor(Tensor::U8(data)) = value {
+            assert_eq!(data.array_view(), arr.t());
+        } else {
+            panic!("Expected `U8` value.")
+        }
+    }
+
+    #[test]
+    fn test_external_data() {
+        let loaded = onnx_protos::Tensor {
+            tensor: TensorValue::F32(array![1.0f32].into_dyn()),
+            path: Some(onnx_protos::ExternalData::new("weights.bin")),
+        };
+        let value = Value::try_from(&loaded).unwrap();
+        let candidate = onnx_protos::Tensor::try_from(&value).unwrap();
+        assert_eq!(candidate.tensor, loaded.tensor);
+        assert!(candidate.path.is_none());
+
+        let unloaded = onnx_protos::Tensor {
+            tensor: TensorValue::Unloaded {
+                dtype: onnx_protos::Dtype::F32,
+                shape: vec![1],
+            },
+            path: None,
+        };
+        assert!(Value::try_from(&unloaded).is_err());
+        assert!(ValueView::try_from(&unloaded).is_err());
+    }
+
+    #[test]
+    fn test_value_view() {
+        let tensor = onnx_protos::Tensor {
+            tensor: TensorValue::I64(array![[1, 2], [3, 4]].into_dyn()),
+            path: None,
+        };
+        let view = ValueView::try_from(&tensor).unwrap();
+        if let (Value::Tensor(Tensor::I64(data)), TensorValue::I64(arr)) = (&*view, &tensor.tensor)
+        {
+            assert_eq!(data.array_view().as_ptr(), arr.as_ptr());
+        } else {
+            panic!("Expected `I64` value.")
+        }
+        assert_eq!(onnx_protos::Tensor::try_from(&*view).unwrap(), tensor);
+
+        let tensor = onnx_protos::Tensor {
+            tensor: TensorValue::String(array!["foo".to_string()].into_dyn()),
+            path: None,
+        };
+        let view = ValueView::try_from(&tensor).unwrap();
+        assert_eq!(onnx_protos::Tensor::try_from(&*view).unwrap(), tensor);
+    }
+}
@@ -47,7 +47,7 @@ impl TypeInfo {
                 let fixed_dims = api.get_tensor_shape(tensor_info)?;
                 let sym_dims = api.get_tensor_shape_symbolic(tensor_info)?;
                 let mut shape = Vec::with_capacity(fixed_dims.len());
-                for (fixed, sym) in fixed_dims.into_iter().zip(sym_dims.into_iter()) {
+                for (fixed, sym) in fixed_dims.into_iter().zip(sym_dims) {
                     let sym = CStr::from_ptr(sym).to_str().unwrap();
 
                     let dim = if fixed > 0 {
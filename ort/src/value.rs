@@ -1,4 +1,5 @@
 use std::marker::PhantomData;
+use std::ops::Deref;
 
 use crate::{api::Api, error::ErrorStatus, TensorDataType, Wrapper};
 use ndarray::{ArrayD, ArrayViewD};
@@ -36,6 +37,13 @@ pub struct Data<T> {
     phantom_type: PhantomData<T>,
 }
 
+/// A `Value` which may borrow its data for the lifetime `'a` instead
+/// of owning a copy.
+pub struct ValueView<'a> {
+    value: Value,
+    phantom_data: PhantomData<&'a ()>,
+}
+
 /// Container holding a contiguous buffer of `str`s (NOT null terminated) and their respective offsets.
 pub struct StringContainer {
     contiguous_buffer: Vec<u8>,
@@ -65,6 +73,23 @@ impl Value {
     }
 }
 
+impl Deref for ValueView<'_> {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.value
+    }
+}
+
+impl From<Value> for ValueView<'_> {
+    fn from(value: Value) -> Self {
+        ValueView {
+            value,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
 impl<T> Data<T>
 where
     T: Copy,
@@ -142,7 +167,30 @@ where
     }
 }
 
-impl<'b> IntoValue for ArrayD<&'b str> {
+pub trait IntoValueView<'a> {
+    /// Create a new value borrowing the data if its layout allows,
+    /// otherwise with a copy of the data.
+    fn into_value_view(self) -> Result<ValueView<'a>, ErrorStatus>;
+}
+
+impl<'a, T> IntoValueView<'a> for ArrayViewD<'a, T>
+where
+    T: Copy + TensorDataType,
+{
+    fn into_value_view(self) -> Result<ValueView<'a>, ErrorStatus> {
+        let Some(slice) = self.to_slice() else {
+            return self.into_value().map(ValueView::from);
+        };
+        let api = Api::new();
+
+        // `Value` never hands out mutable access to its data
+        let ort_value = unsafe { api.create_tensor_with_borrowed_data(slice, self.shape())? };
+
+        ort_value.into_value().map(ValueView::from)
+    }
+}
+
+impl IntoValue for ArrayD<&str> {
     fn into_value(self) -> Result<Value, ErrorStatus> {
         let api = Api::new();
 
@@ -255,4 +303,23 @@ mod tests {
 
         assert_eq!(arr.as_slice().unwrap(), round_trip);
     }
+
+    #[test]
+    fn test_value_view() {
+        let arr = array![[1.0f32, 2.0], [3.0, 4.0]].into_dyn();
+
+        let view = arr.view().into_value_view().unwrap();
+        let api = Api::new();
+        let ptr: *const f32 = unsafe { api.get_tensor_data_mut(view.ref_ort_value().ptr).unwrap() };
+        assert_eq!(ptr, arr.as_ptr());
+
+        // Transposed arrays are copied into standard layout
+        let view = arr.t().into_value_view().unwrap();
+        if let Value::Tensor(Tensor::F32(data)) = &*view {
+            assert_ne!(data.array_view().as_ptr(), arr.as_ptr());
+            assert_eq!(data.array_view(), arr.t());
+        } else {
+            panic!("Expected `F32` value.")
+        }
+    }
 }